@@ -1,2 +0,0 @@
-"""The replit Python package."""
-
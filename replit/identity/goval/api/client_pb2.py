@@ -1,60 +0,0 @@
-# -*- coding: utf-8 -*-
-# Generated by the protocol buffer compiler.  DO NOT EDIT!
-# source: replit/goval/api/client.proto
-"""Generated protocol buffer code."""
-from google.protobuf import descriptor as _descriptor
-from google.protobuf import descriptor_pool as _descriptor_pool
-from google.protobuf import symbol_database as _symbol_database
-from google.protobuf.internal import builder as _builder
-
-# @@protoc_insertion_point(imports)
-
-_sym_db = _symbol_database.Default()
-
-from google.protobuf import timestamp_pb2 as google_dot_protobuf_dot_timestamp__pb2
-from identity.goval.api.repl import (
-    repl_pb2 as replit_dot_goval_dot_api_dot_repl_dot_repl__pb2, )
-from identity.goval.api.features import (
-    features_pb2 as replit_dot_goval_dot_api_dot_features_dot_features__pb2, )
-
-DESCRIPTOR = _descriptor_pool.Default().AddSerializedFile(
-    b'\n\x1dreplit/goval/api/client.proto\x12\x10replit.goval.api\x1a\x1fgoogle/protobuf/timestamp.proto\x1a replit/goval/api/repl/repl.proto\x1a(replit/goval/api/features/features.proto"\xdb\x07\n\tReplToken\x12\'\n\x03iat\x18\x01 \x01(\x0b\x32\x1a.google.protobuf.Timestamp\x12\'\n\x03\x65xp\x18\x02 \x01(\x0b\x32\x1a.google.protobuf.Timestamp\x12\x0c\n\x04salt\x18\x03 \x01(\t\x12\x0f\n\x07\x63luster\x18\x04 \x01(\t\x12\x37\n\x0bpersistence\x18\x06 \x01(\x0e\x32".replit.goval.api.repl.Persistence\x12+\n\x04repl\x18\x07 \x01(\x0b\x32\x1b.replit.goval.api.repl.ReplH\x00\x12\x30\n\x02id\x18\x08 \x01(\x0b\x32".replit.goval.api.ReplToken.ReplIDH\x00\x12\x46\n\tclassroom\x18\t \x01(\x0b\x32-.replit.goval.api.ReplToken.ClassroomMetadataB\x02\x18\x01H\x00\x12=\n\x0eresourceLimits\x18\n \x01(\x0b\x32%.replit.goval.api.repl.ResourceLimits\x12H\n\x19interactiveResourceLimits\x18\x11 \x01(\x0b\x32%.replit.goval.api.repl.ResourceLimits\x12\x36\n\x06\x66ormat\x18\x0c \x01(\x0e\x32&.replit.goval.api.ReplToken.WireFormat\x12\x38\n\tpresenced\x18\r \x01(\x0b\x32%.replit.goval.api.ReplToken.Presenced\x12\r\n\x05\x66lags\x18\x0e \x03(\t\x12\x37\n\x0bpermissions\x18\x0f \x01(\x0b\x32".replit.goval.api.repl.Permissions\x12\x34\n\x08\x66\x65\x61tures\x18\x10 \x03(\x0b\x32".replit.goval.api.features.Feature\x12\x34\n\nbuild_info\x18\x12 \x01(\x0b\x32 .replit.goval.api.repl.BuildInfo\x1a\x31\n\x11\x43lassroomMetadata\x12\n\n\x02id\x18\x01 \x01(\t\x12\x10\n\x08language\x18\x02 \x01(\t\x1a(\n\x06ReplID\x12\n\n\x02id\x18\x01 \x01(\t\x12\x12\n\nsourceRepl\x18\x02 \x01(\t\x1a\x31\n\tPresenced\x12\x10\n\x08\x62\x65\x61rerID\x18\x01 \x01(\r\x12\x12\n\nbearerName\x18\x02 \x01(\t"2\n\nWireFormat\x12\x0c\n\x08PROTOBUF\x10\x00\x12\x0c\n\x04JSON\x10\x01\x1a\x02\x08\x01\x12\x08\n\x04PID2\x10\x02\x42\n\n\x08metadata".\n\x0eTLSCertificate\x12\x0e\n\x06\x64omain\x18\x01 \x01(\t\x12\x0c\n\x04\x63\x65rt\x18\x02 \x01(\x0c"\xc0\x02\n\x0cReplTransfer\x12)\n\x04repl\x18\x01 \x01(\x0b\x32\x1b.replit.goval.api.repl.Repl\x12\x39\n\nreplLimits\x18\x02 \x01(\x0b\x32%.replit.goval.api.repl.ResourceLimits\x12\x39\n\nuserLimits\x18\x03 \x01(\x0b\x32%.replit.goval.api.repl.ResourceLimits\x12\x15\n\rcustomDomains\x18\x04 \x03(\t\x12\x36\n\x0c\x63\x65rtificates\x18\x05 \x03(\x0b\x32 .replit.goval.api.TLSCertificate\x12\r\n\x05\x66lags\x18\x06 \x03(\t\x12\x31\n\x08metadata\x18\x07 \x01(\x0b\x32\x1f.replit.goval.api.repl.Metadata"H\n\x10\x41llowReplRequest\x12\x34\n\x0creplTransfer\x18\x01 \x01(\x0b\x32\x1e.replit.goval.api.ReplTransfer"l\n\x0f\x43lusterMetadata\x12\n\n\x02id\x18\x01 \x01(\t\x12\x11\n\tconmanURL\x18\x02 \x01(\t\x12\x0c\n\x04gurl\x18\x03 \x01(\t\x12\r\n\x05proxy\x18\x05 \x01(\t\x12\x11\n\tcontinent\x18\x07 \x01(\tJ\x04\x08\x04\x10\x05J\x04\x08\x06\x10\x07"y\n\x10\x45victReplRequest\x12:\n\x0f\x63lusterMetadata\x18\x01 \x01(\x0b\x32!.replit.goval.api.ClusterMetadata\x12\r\n\x05token\x18\x02 \x01(\t\x12\x0c\n\x04user\x18\x03 \x01(\t\x12\x0c\n\x04slug\x18\x04 \x01(\t"I\n\x11\x45victReplResponse\x12\x34\n\x0creplTransfer\x18\x01 \x01(\x0b\x32\x1e.replit.goval.api.ReplTransferB\x1dZ\x1bgithub.com/replit/goval/apib\x06proto3'
-)
-
-_globals = globals()
-_builder.BuildMessageAndEnumDescriptors(DESCRIPTOR, _globals)
-_builder.BuildTopDescriptorsAndMessages(DESCRIPTOR,
-                                        "replit.goval.api.client_pb2",
-                                        _globals)
-if _descriptor._USE_C_DESCRIPTORS == False:
-    DESCRIPTOR._options = None
-    DESCRIPTOR._serialized_options = b"Z\033github.com/replit/goval/api"
-    _REPLTOKEN_WIREFORMAT.values_by_name["JSON"]._options = None
-    _REPLTOKEN_WIREFORMAT.values_by_name[
-        "JSON"]._serialized_options = b"\010\001"
-    _REPLTOKEN.fields_by_name["classroom"]._options = None
-    _REPLTOKEN.fields_by_name["classroom"]._serialized_options = b"\030\001"
-    _globals["_REPLTOKEN"]._serialized_start = 161
-    _globals["_REPLTOKEN"]._serialized_end = 1148
-    _globals["_REPLTOKEN_CLASSROOMMETADATA"]._serialized_start = 942
-    _globals["_REPLTOKEN_CLASSROOMMETADATA"]._serialized_end = 991
-    _globals["_REPLTOKEN_REPLID"]._serialized_start = 993
-    _globals["_REPLTOKEN_REPLID"]._serialized_end = 1033
-    _globals["_REPLTOKEN_PRESENCED"]._serialized_start = 1035
-    _globals["_REPLTOKEN_PRESENCED"]._serialized_end = 1084
-    _globals["_REPLTOKEN_WIREFORMAT"]._serialized_start = 1086
-    _globals["_REPLTOKEN_WIREFORMAT"]._serialized_end = 1136
-    _globals["_TLSCERTIFICATE"]._serialized_start = 1150
-    _globals["_TLSCERTIFICATE"]._serialized_end = 1196
-    _globals["_REPLTRANSFER"]._serialized_start = 1199
-    _globals["_REPLTRANSFER"]._serialized_end = 1519
-    _globals["_ALLOWREPLREQUEST"]._serialized_start = 1521
-    _globals["_ALLOWREPLREQUEST"]._serialized_end = 1593
-    _globals["_CLUSTERMETADATA"]._serialized_start = 1595
-    _globals["_CLUSTERMETADATA"]._serialized_end = 1703
-    _globals["_EVICTREPLREQUEST"]._serialized_start = 1705
-    _globals["_EVICTREPLREQUEST"]._serialized_end = 1826
-    _globals["_EVICTREPLRESPONSE"]._serialized_start = 1828
-    _globals["_EVICTREPLRESPONSE"]._serialized_end = 1901
-# @@protoc_insertion_point(module_scope)
-
@@ -1,50 +0,0 @@
-# -*- coding: utf-8 -*-
-# Generated by the protocol buffer compiler.  DO NOT EDIT!
-# source: replit/goval/api/repl/repl.proto
-"""Generated protocol buffer code."""
-from google.protobuf import descriptor as _descriptor
-from google.protobuf import descriptor_pool as _descriptor_pool
-from google.protobuf import symbol_database as _symbol_database
-from google.protobuf.internal import builder as _builder
-
-# @@protoc_insertion_point(imports)
-
-_sym_db = _symbol_database.Default()
-
-from identity.goval.api.features import (
-    features_pb2 as replit_dot_goval_dot_api_dot_features_dot_features__pb2, )
-
-DESCRIPTOR = _descriptor_pool.Default().AddSerializedFile(
-    b'\n replit/goval/api/repl/repl.proto\x12\x15replit.goval.api.repl\x1a(replit/goval/api/features/features.proto"C\n\x07\x42uckets\x12\x11\n\tsnapshots\x18\x01 \x01(\t\x12\x10\n\x08metadata\x18\x02 \x01(\t\x12\x13\n\x0b\x64isk_blocks\x18\x03 \x01(\t"\x8b\x02\n\x04Repl\x12\n\n\x02id\x18\x01 \x01(\t\x12\x10\n\x08language\x18\x02 \x01(\t\x12\x0e\n\x06\x62ucket\x18\x03 \x01(\t\x12\x0c\n\x04slug\x18\x04 \x01(\t\x12\x0c\n\x04user\x18\x05 \x01(\t\x12\x12\n\nsourceRepl\x18\x06 \x01(\t\x12\x10\n\x08\x64\x61tabase\x18\x07 \x01(\t\x12/\n\x07\x62uckets\x18\x08 \x01(\x0b\x32\x1e.replit.goval.api.repl.Buckets\x12.\n\x07user_id\x18\t \x01(\x0b\x32\x1d.replit.goval.api.repl.UserId\x12\x0f\n\x07is_team\x18\n \x01(\x08\x12\r\n\x05roles\x18\x0b \x03(\t\x12\x12\n\nlog_fields\x18\r \x01(\t"M\n\x06UserId\x12\n\n\x02id\x18\x01 \x01(\x03\x12\x37\n\x0b\x65nvironment\x18\x02 \x01(\x0e\x32".replit.goval.api.repl.Environment"\xa7\x02\n\x0eResourceLimits\x12\x0b\n\x03net\x18\x01 \x01(\x08\x12\x0e\n\x06memory\x18\x02 \x01(\x03\x12\x0f\n\x07threads\x18\x03 \x01(\x01\x12\x0e\n\x06shares\x18\x04 \x01(\x01\x12\x0c\n\x04\x64isk\x18\x05 \x01(\x03\x12\x14\n\x0cminimum_disk\x18\n \x01(\x03\x12\x14\n\x0cscratch_disk\x18\t \x01(\x03\x12@\n\x05\x63\x61\x63he\x18\x06 \x01(\x0e\x32\x31.replit.goval.api.repl.ResourceLimits.Cachability\x12\x17\n\x0frestrictNetwork\x18\x07 \x01(\x08\x12\x15\n\rpreventWakeup\x18\x08 \x01(\x08"+\n\x0b\x43\x61\x63hability\x12\x08\n\x04NONE\x10\x00\x12\x08\n\x04USER\x10\x01\x12\x08\n\x04REPL\x10\x02"%\n\x0bPermissions\x12\x16\n\x0etoggleAlwaysOn\x18\x01 \x01(\x08"\xab\x03\n\x08Metadata\x12)\n\x04repl\x18\x07 \x01(\x0b\x32\x1b.replit.goval.api.repl.Repl\x12=\n\x0eresourceLimits\x18\n \x01(\x0b\x32%.replit.goval.api.repl.ResourceLimits\x12H\n\x19interactiveResourceLimits\x18\x11 \x01(\x0b\x32%.replit.goval.api.repl.ResourceLimits\x12\x37\n\x0bpersistence\x18\x06 \x01(\x0e\x32".replit.goval.api.repl.Persistence\x12\r\n\x05\x66lags\x18\x0e \x03(\t\x12\x37\n\x0bpermissions\x18\x0f \x01(\x0b\x32".replit.goval.api.repl.Permissions\x12\x34\n\x08\x66\x65\x61tures\x18\x10 \x03(\x0b\x32".replit.goval.api.features.Feature\x12\x34\n\nbuild_info\x18\x12 \x01(\x0b\x32 .replit.goval.api.repl.BuildInfo"W\n\tBuildInfo\x12\x15\n\rdeployment_id\x18\x01 \x01(\t\x12\x0b\n\x03url\x18\x02 \x01(\t\x12\x10\n\x08\x62uild_id\x18\x03 \x01(\t\x12\x14\n\x0cmachine_tier\x18\x04 \x01(\t*.\n\x0b\x45nvironment\x12\x0f\n\x0b\x44\x45VELOPMENT\x10\x00\x12\x0e\n\nPRODUCTION\x10\x01*E\n\x0bPersistence\x12\x0e\n\nPERSISTENT\x10\x00\x12\r\n\tEPHEMERAL\x10\x01\x12\x08\n\x04NONE\x10\x02\x12\r\n\tREAD_ONLY\x10\x03\x42"Z github.com/replit/goval/api/replb\x06proto3'
-)
-
-_globals = globals()
-_builder.BuildMessageAndEnumDescriptors(DESCRIPTOR, _globals)
-_builder.BuildTopDescriptorsAndMessages(DESCRIPTOR,
-                                        "replit.goval.api.repl.repl_pb2",
-                                        _globals)
-if _descriptor._USE_C_DESCRIPTORS == False:
-    DESCRIPTOR._options = None
-    DESCRIPTOR._serialized_options = b"Z github.com/replit/goval/api/repl"
-    _globals["_ENVIRONMENT"]._serialized_start = 1375
-    _globals["_ENVIRONMENT"]._serialized_end = 1421
-    _globals["_PERSISTENCE"]._serialized_start = 1423
-    _globals["_PERSISTENCE"]._serialized_end = 1492
-    _globals["_BUCKETS"]._serialized_start = 101
-    _globals["_BUCKETS"]._serialized_end = 168
-    _globals["_REPL"]._serialized_start = 171
-    _globals["_REPL"]._serialized_end = 438
-    _globals["_USERID"]._serialized_start = 440
-    _globals["_USERID"]._serialized_end = 517
-    _globals["_RESOURCELIMITS"]._serialized_start = 520
-    _globals["_RESOURCELIMITS"]._serialized_end = 815
-    _globals["_RESOURCELIMITS_CACHABILITY"]._serialized_start = 772
-    _globals["_RESOURCELIMITS_CACHABILITY"]._serialized_end = 815
-    _globals["_PERMISSIONS"]._serialized_start = 817
-    _globals["_PERMISSIONS"]._serialized_end = 854
-    _globals["_METADATA"]._serialized_start = 857
-    _globals["_METADATA"]._serialized_end = 1284
-    _globals["_BUILDINFO"]._serialized_start = 1286
-    _globals["_BUILDINFO"]._serialized_end = 1373
-# @@protoc_insertion_point(module_scope)
-
@@ -1,55 +0,0 @@
-# -*- coding: utf-8 -*-
-# Generated by the protocol buffer compiler.  DO NOT EDIT!
-# source: replit/goval/api/signing.proto
-"""Generated protocol buffer code."""
-from google.protobuf import descriptor as _descriptor
-from google.protobuf import descriptor_pool as _descriptor_pool
-from google.protobuf import symbol_database as _symbol_database
-from google.protobuf.internal import builder as _builder
-
-# @@protoc_insertion_point(imports)
-
-_sym_db = _symbol_database.Default()
-
-
-from google.protobuf import timestamp_pb2 as google_dot_protobuf_dot_timestamp__pb2
-from identity.goval.api import (
-    client_pb2 as replit_dot_goval_dot_api_dot_client__pb2,
-)
-from identity.goval.api.repl import (
-    repl_pb2 as replit_dot_goval_dot_api_dot_repl_dot_repl__pb2,
-)
-
-
-DESCRIPTOR = _descriptor_pool.Default().AddSerializedFile(
-    b'\n\x1ereplit/goval/api/signing.proto\x12\x10replit.goval.api\x1a\x1fgoogle/protobuf/timestamp.proto\x1a\x1dreplit/goval/api/client.proto\x1a replit/goval/api/repl/repl.proto"\x89\x01\n\x15GovalSigningAuthority\x12\x10\n\x06key_id\x18\x01 \x01(\tH\x00\x12\x15\n\x0bsigned_cert\x18\x02 \x01(\tH\x00\x12/\n\x07version\x18\x03 \x01(\x0e\x32\x1e.replit.goval.api.TokenVersion\x12\x0e\n\x06issuer\x18\x04 \x01(\tB\x06\n\x04\x63\x65rt"\xbc\x01\n\x10\x43\x65rtificateClaim\x12\x10\n\x06replid\x18\x01 \x01(\tH\x00\x12\x0e\n\x04user\x18\x02 \x01(\tH\x00\x12\x11\n\x07user_id\x18\x07 \x01(\x03H\x00\x12\x11\n\x07\x63luster\x18\x04 \x01(\tH\x00\x12\x14\n\nsubcluster\x18\x05 \x01(\tH\x00\x12\x14\n\ndeployment\x18\x06 \x01(\x08H\x00\x12+\n\x04\x66lag\x18\x03 \x01(\x0e\x32\x1b.replit.goval.api.FlagClaimH\x00\x42\x07\n\x05\x63laim"\xa4\x01\n\tGovalCert\x12\'\n\x03iat\x18\x01 \x01(\x0b\x32\x1a.google.protobuf.Timestamp\x12\'\n\x03\x65xp\x18\x02 \x01(\x0b\x32\x1a.google.protobuf.Timestamp\x12\x32\n\x06\x63laims\x18\x03 \x03(\x0b\x32".replit.goval.api.CertificateClaim\x12\x11\n\tpublicKey\x18\x04 \x01(\t"\xe8\x01\n\nGovalToken\x12\'\n\x03iat\x18\x01 \x01(\x0b\x32\x1a.google.protobuf.Timestamp\x12\'\n\x03\x65xp\x18\x02 \x01(\x0b\x32\x1a.google.protobuf.Timestamp\x12\x0e\n\x06replid\x18\x03 \x01(\t\x12\x31\n\nrepl_token\x18\x04 \x01(\x0b\x32\x1b.replit.goval.api.ReplTokenH\x00\x12<\n\rrepl_identity\x18\x05 \x01(\x0b\x32#.replit.goval.api.GovalReplIdentityH\x00\x42\x07\n\x05Token"\xe7\x02\n\x11GovalReplIdentity\x12\x0e\n\x06replid\x18\x01 \x01(\t\x12\x0c\n\x04user\x18\x02 \x01(\t\x12\x0c\n\x04slug\x18\x03 \x01(\t\x12\x0b\n\x03\x61ud\x18\x04 \x01(\t\x12\x11\n\tephemeral\x18\x05 \x01(\x08\x12\x14\n\x0coriginReplid\x18\x06 \x01(\t\x12\x0f\n\x07user_id\x18\x07 \x01(\x03\x12\x34\n\nbuild_info\x18\x08 \x01(\x0b\x32 .replit.goval.api.repl.BuildInfo\x12\x0f\n\x07is_team\x18\t \x01(\x08\x12\r\n\x05roles\x18\n \x03(\t\x12?\n\x0binteractive\x18\x0b \x01(\x0b\x32(.replit.goval.api.ReplRuntimeInteractiveH\x00\x12=\n\ndeployment\x18\x0c \x01(\x0b\x32\'.replit.goval.api.ReplRuntimeDeploymentH\x00\x42\t\n\x07runtime"=\n\x16ReplRuntimeInteractive\x12\x0f\n\x07\x63luster\x18\x01 \x01(\t\x12\x12\n\nsubcluster\x18\x02 \x01(\t"\x17\n\x15ReplRuntimeDeployment*9\n\x0cTokenVersion\x12\x13\n\x0f\x42\x41RE_REPL_TOKEN\x10\x00\x12\x14\n\x10TYPE_AWARE_TOKEN\x10\x01*\xe3\x01\n\tFlagClaim\x12\x14\n\x10MINT_GOVAL_TOKEN\x10\x00\x12\x1a\n\x16SIGN_INTERMEDIATE_CERT\x10\x01\x12\x0c\n\x08IDENTITY\x10\x05\x12\x0f\n\x0bGHOSTWRITER\x10\x06\x12\x12\n\x0eRENEW_IDENTITY\x10\x07\x12\x0c\n\x08RENEW_KV\x10\x08\x12\x0f\n\x0b\x44\x45PLOYMENTS\x10\n\x12\x0e\n\nANY_REPLID\x10\x02\x12\x0c\n\x08\x41NY_USER\x10\x03\x12\x0f\n\x0b\x41NY_USER_ID\x10\x0b\x12\x0f\n\x0b\x41NY_CLUSTER\x10\x04\x12\x12\n\x0e\x41NY_SUBCLUSTER\x10\tB\x1dZ\x1bgithub.com/replit/goval/apib\x06proto3'
-)
-
-_globals = globals()
-_builder.BuildMessageAndEnumDescriptors(DESCRIPTOR, _globals)
-_builder.BuildTopDescriptorsAndMessages(
-    DESCRIPTOR, "replit.goval.api.signing_pb2", _globals
-)
-if _descriptor._USE_C_DESCRIPTORS == False:
-    DESCRIPTOR._options = None
-    DESCRIPTOR._serialized_options = b"Z\033github.com/replit/goval/api"
-    _globals["_TOKENVERSION"]._serialized_start = 1333
-    _globals["_TOKENVERSION"]._serialized_end = 1390
-    _globals["_FLAGCLAIM"]._serialized_start = 1393
-    _globals["_FLAGCLAIM"]._serialized_end = 1620
-    _globals["_GOVALSIGNINGAUTHORITY"]._serialized_start = 151
-    _globals["_GOVALSIGNINGAUTHORITY"]._serialized_end = 288
-    _globals["_CERTIFICATECLAIM"]._serialized_start = 291
-    _globals["_CERTIFICATECLAIM"]._serialized_end = 479
-    _globals["_GOVALCERT"]._serialized_start = 482
-    _globals["_GOVALCERT"]._serialized_end = 646
-    _globals["_GOVALTOKEN"]._serialized_start = 649
-    _globals["_GOVALTOKEN"]._serialized_end = 881
-    _globals["_GOVALREPLIDENTITY"]._serialized_start = 884
-    _globals["_GOVALREPLIDENTITY"]._serialized_end = 1243
-    _globals["_REPLRUNTIMEINTERACTIVE"]._serialized_start = 1245
-    _globals["_REPLRUNTIMEINTERACTIVE"]._serialized_end = 1306
-    _globals["_REPLRUNTIMEDEPLOYMENT"]._serialized_start = 1308
-    _globals["_REPLRUNTIMEDEPLOYMENT"]._serialized_end = 1331
-# @@protoc_insertion_point(module_scope)
-
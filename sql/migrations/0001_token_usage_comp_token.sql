@@ -0,0 +1,15 @@
+-- Token Usage, one row per meter (limiting caveat) of a token
+CREATE TABLE IF NOT EXISTS "token_usage" (
+    meter_id VARCHAR(64) PRIMARY KEY,
+    uses BIGINT NOT NULL DEFAULT 0,
+    spent_msat BIGINT NOT NULL DEFAULT 0,
+    -- Unix timestamp of the first use, starting the window of pass tokens.
+    first_used_at BIGINT NOT NULL DEFAULT 0
+);
+-- Comp Token
+CREATE TABLE IF NOT EXISTS "comp_token" (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY (START WITH 1000) PRIMARY KEY,
+    token_id VARCHAR(64) NOT NULL UNIQUE,
+    label VARCHAR(256) NOT NULL,
+    revoked BOOLEAN NOT NULL DEFAULT FALSE
+);
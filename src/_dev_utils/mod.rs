@@ -24,17 +24,12 @@ pub async fn init_dev() {
 }
 
 /// Initialize test environment.
+/// (The db is initialized once, the pool is per test, each test running on its
+/// own runtime.)
 pub async fn init_test() -> ModelManager {
-    static INIT: OnceCell<ModelManager> = OnceCell::const_new();
+    init_dev().await;
 
-    let mm = INIT
-        .get_or_init(|| async {
-            init_dev().await;
-            ModelManager::new().await.unwrap()
-        })
-        .await;
-
-    mm.clone()
+    ModelManager::new().await.unwrap()
 }
 
 pub async fn seed_balances(
@@ -53,4 +48,3 @@ pub async fn seed_balances(
 
     Ok(balances)
 }
-
@@ -0,0 +1,502 @@
+use std::fmt::{self, Debug};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::{error, info};
+
+use super::google::{GoogleServiceAccountCredential, GOOGLE_APPLICATION_CREDENTIALS};
+use super::replit::ReplitIdentityCredential;
+use super::{Error, Result};
+use crate::config::env_var;
+use crate::utils::now_utc;
+
+/// A cached credential is refreshed this long before it expires.
+const REFRESH_BEFORE_EXPIRY_SEC: i64 = 60;
+/// Delay before retrying a failed background refresh.
+const REFRESH_RETRY_SEC: i64 = 10;
+/// How often a key file is re-read.
+const DEFAULT_FILE_REFRESH_SEC: i64 = 30;
+
+#[derive(Clone)]
+pub struct Credential {
+    pub key: String,
+    /// Unix timestamp, `None` if the key does not expire.
+    pub expires_at: Option<i64>,
+}
+
+impl Credential {
+    fn is_fresh(&self) -> bool {
+        self.expires_at.is_none_or(|expires_at| {
+            now_utc().unix_timestamp() < expires_at - REFRESH_BEFORE_EXPIRY_SEC
+        })
+    }
+}
+
+/// Source of the operator key of a provider.
+#[async_trait]
+pub trait CredentialProvider: Debug + Send + Sync {
+    async fn fetch(&self) -> Result<Credential>;
+}
+
+// region:    --- Params
+
+/// `credentials` of a provider, overriding `key_env` and `key_file`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum CredentialParams {
+    /// Key read once from an environment variable.
+    Env { name: String },
+    /// Key file re-read every `refresh_sec`, so it can be rotated in place.
+    File {
+        path: String,
+        refresh_sec: Option<i64>,
+    },
+    /// Command printing the key, or a JSON `{"token", "timeout"}` with the
+    /// lifetime of the token in seconds.
+    Command { command: Vec<String> },
+    /// Replit Modelfarm identity token, signed in process.
+    ReplitIdentity,
+    /// OAuth 2 refresh token grant.
+    OauthRefresh {
+        token_url: String,
+        client_id: String,
+        client_secret_env: Option<String>,
+        refresh_token_env: String,
+        scope: Option<String>,
+    },
+    /// Google service account JSON key at `path`, or at
+    /// `GOOGLE_APPLICATION_CREDENTIALS`, exchanged for access tokens.
+    GoogleServiceAccount {
+        path: Option<String>,
+        scope: Option<String>,
+    },
+}
+
+impl CredentialParams {
+    /// Id of the key in the pool of its provider, never the key itself.
+    pub fn id(&self) -> String {
+        match self {
+            Self::Env { name } => name.clone(),
+            Self::File { path, .. } => path.clone(),
+            Self::Command { command } => command.join(" "),
+            Self::ReplitIdentity => "replit-identity".to_string(),
+            Self::OauthRefresh {
+                refresh_token_env, ..
+            } => refresh_token_env.clone(),
+            Self::GoogleServiceAccount { path, .. } => path
+                .clone()
+                .unwrap_or_else(|| GOOGLE_APPLICATION_CREDENTIALS.to_string()),
+        }
+    }
+
+    /// Hash of the params and of the secrets they read from the environment,
+    /// telling whether a reloaded key is the one already in use.
+    pub fn fingerprint(&self) -> String {
+        let secrets: Vec<Option<String>> = match self {
+            Self::Env { name } => vec![env_var(name)],
+            Self::OauthRefresh {
+                client_secret_env,
+                refresh_token_env,
+                ..
+            } => vec![
+                client_secret_env.as_deref().and_then(env_var),
+                env_var(refresh_token_env),
+            ],
+            Self::GoogleServiceAccount { path: None, .. } => {
+                vec![env_var(GOOGLE_APPLICATION_CREDENTIALS)]
+            }
+            _ => Vec::new(),
+        };
+
+        hex::encode(Sha256::digest(format!("{self:?} {secrets:?}")))
+    }
+
+    /// Provider of the credential, `None` if it is not configured in the
+    /// environment.
+    pub fn provider(&self) -> Result<Option<Box<dyn CredentialProvider>>> {
+        let provider: Box<dyn CredentialProvider> = match self {
+            Self::Env { name } => match env_var(name) {
+                Some(key) => Box::new(StaticCredential(key)),
+                None => return Ok(None),
+            },
+            Self::File { path, refresh_sec } => {
+                let provider = FileCredential {
+                    path: path.clone(),
+                    refresh_sec: refresh_sec.unwrap_or(DEFAULT_FILE_REFRESH_SEC),
+                };
+                // Fails early on a missing file rather than on the first request.
+                provider.read()?;
+                Box::new(provider)
+            }
+            Self::Command { command } => {
+                if command.is_empty() {
+                    return Err(Error::CredentialConfig("empty command".to_string()));
+                }
+                Box::new(CommandCredential {
+                    command: command.clone(),
+                })
+            }
+            Self::ReplitIdentity => Box::new(ReplitIdentityCredential),
+            Self::OauthRefresh {
+                token_url,
+                client_id,
+                client_secret_env,
+                refresh_token_env,
+                scope,
+            } => {
+                let Some(refresh_token) = env_var(refresh_token_env) else {
+                    return Ok(None);
+                };
+                let client_secret = match client_secret_env {
+                    Some(name) => Some(
+                        env_var(name)
+                            .ok_or_else(|| Error::CredentialConfig(format!("{name} not set")))?,
+                    ),
+                    None => None,
+                };
+                Box::new(OauthRefreshCredential {
+                    token_url: token_url.clone(),
+                    client_id: client_id.clone(),
+                    client_secret,
+                    scope: scope.clone(),
+                    refresh_token: Mutex::new(refresh_token),
+                })
+            }
+            Self::GoogleServiceAccount { path, scope } => {
+                let path = match path {
+                    Some(path) => path.clone(),
+                    None => match env_var(GOOGLE_APPLICATION_CREDENTIALS) {
+                        Some(path) => path,
+                        None => return Ok(None),
+                    },
+                };
+                // Fails early on an unreadable key rather than on the first request.
+                Box::new(GoogleServiceAccountCredential::from_file(
+                    &path,
+                    scope.as_deref(),
+                )?)
+            }
+        };
+
+        Ok(Some(provider))
+    }
+}
+
+// endregion: --- Params
+
+// region:    --- Cache
+
+/// Credential shared by all the requests to a provider. Refreshed in the
+/// background before expiry, and by a single request at a time when stale.
+pub struct CredentialCache {
+    provider: Box<dyn CredentialProvider>,
+    current: RwLock<Option<Credential>>,
+    refresh: tokio::sync::Mutex<()>,
+    refresher_started: AtomicBool,
+}
+
+impl Debug for CredentialCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CredentialCache")
+            .field("provider", &self.provider)
+            .finish()
+    }
+}
+
+impl CredentialCache {
+    pub fn new(provider: Box<dyn CredentialProvider>) -> Arc<Self> {
+        Arc::new(Self {
+            provider,
+            current: RwLock::new(None),
+            refresh: tokio::sync::Mutex::new(()),
+            refresher_started: AtomicBool::new(false),
+        })
+    }
+
+    /// Current key, fetched first if missing or about to expire.
+    pub async fn get(self: &Arc<Self>) -> Result<String> {
+        if let Some(key) = self.fresh_key() {
+            return Ok(key);
+        }
+
+        let _refresh = self.refresh.lock().await;
+        // Refreshed by the request holding the lock before us.
+        if let Some(key) = self.fresh_key() {
+            return Ok(key);
+        }
+
+        let credential = self.fetch().await?;
+        self.start_refresher(&credential);
+
+        Ok(credential.key)
+    }
+
+    fn fresh_key(&self) -> Option<String> {
+        let current = self.current.read().unwrap_or_else(|e| e.into_inner());
+        current
+            .as_ref()
+            .filter(|credential| credential.is_fresh())
+            .map(|credential| credential.key.clone())
+    }
+
+    async fn fetch(&self) -> Result<Credential> {
+        let credential = self.provider.fetch().await?;
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Some(credential.clone());
+        Ok(credential)
+    }
+
+    /// Keeps expiring credentials fresh until the cache is dropped, e.g. on a
+    /// config reload.
+    fn start_refresher(self: &Arc<Self>, credential: &Credential) {
+        if credential.expires_at.is_none() || self.refresher_started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let weak_cache = Arc::downgrade(self);
+        let mut expires_at = credential.expires_at;
+        tokio::spawn(async move {
+            while let Some(at) = expires_at {
+                let refresh_in = at - REFRESH_BEFORE_EXPIRY_SEC - now_utc().unix_timestamp();
+                tokio::time::sleep(Duration::from_secs(refresh_in.max(0) as u64)).await;
+
+                let Some(cache) = Weak::upgrade(&weak_cache) else {
+                    return;
+                };
+                let _refresh = cache.refresh.lock().await;
+                expires_at = match cache.fetch().await {
+                    Ok(credential) => credential.expires_at,
+                    Err(e) => {
+                        error!("Credential refresh failed for {:?}: {}", cache.provider, e);
+                        // Retried in REFRESH_RETRY_SEC.
+                        let now = now_utc().unix_timestamp();
+                        Some(now + REFRESH_BEFORE_EXPIRY_SEC + REFRESH_RETRY_SEC)
+                    }
+                };
+            }
+        });
+    }
+}
+
+// endregion: --- Cache
+
+// region:    --- Providers
+
+pub struct StaticCredential(pub String);
+
+impl Debug for StaticCredential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("StaticCredential")
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for StaticCredential {
+    async fn fetch(&self) -> Result<Credential> {
+        Ok(Credential {
+            key: self.0.clone(),
+            expires_at: None,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct FileCredential {
+    path: String,
+    refresh_sec: i64,
+}
+
+impl FileCredential {
+    fn read(&self) -> Result<String> {
+        let key = std::fs::read_to_string(&self.path)
+            .map_err(|e| Error::CredentialFetch(format!("{}: {}", self.path, e)))?;
+        Ok(key.trim().to_string())
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for FileCredential {
+    async fn fetch(&self) -> Result<Credential> {
+        Ok(Credential {
+            key: self.read()?,
+            expires_at: Some(
+                now_utc().unix_timestamp() + REFRESH_BEFORE_EXPIRY_SEC + self.refresh_sec,
+            ),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct CommandCredential {
+    command: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct CommandOutput {
+    token: String,
+    timeout: Option<i64>,
+}
+
+#[async_trait]
+impl CredentialProvider for CommandCredential {
+    async fn fetch(&self) -> Result<Credential> {
+        info!("Running credential command {:?}", self.command);
+        let output = tokio::process::Command::new(&self.command[0])
+            .args(&self.command[1..])
+            .output()
+            .await
+            .map_err(|e| Error::CredentialFetch(format!("{:?}: {}", self.command, e)))?;
+        if !output.status.success() {
+            return Err(Error::CredentialFetch(format!(
+                "{:?} exited with {}",
+                self.command, output.status
+            )));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stdout = stdout.trim();
+        if stdout.is_empty() {
+            return Err(Error::CredentialFetch(format!(
+                "{:?}: no output",
+                self.command
+            )));
+        }
+
+        Ok(match serde_json::from_str::<CommandOutput>(stdout) {
+            Ok(output) => Credential {
+                key: output.token,
+                expires_at: output
+                    .timeout
+                    .map(|timeout| now_utc().unix_timestamp() + timeout),
+            },
+            Err(_) => Credential {
+                key: stdout.to_string(),
+                expires_at: None,
+            },
+        })
+    }
+}
+
+pub struct OauthRefreshCredential {
+    token_url: String,
+    client_id: String,
+    client_secret: Option<String>,
+    scope: Option<String>,
+    /// Replaced when the server rotates it.
+    refresh_token: Mutex<String>,
+}
+
+impl Debug for OauthRefreshCredential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OauthRefreshCredential")
+            .field("token_url", &self.token_url)
+            .field("client_id", &self.client_id)
+            .finish()
+    }
+}
+
+#[derive(Deserialize)]
+struct OauthTokenResponse {
+    access_token: String,
+    expires_in: Option<i64>,
+    refresh_token: Option<String>,
+}
+
+#[async_trait]
+impl CredentialProvider for OauthRefreshCredential {
+    async fn fetch(&self) -> Result<Credential> {
+        let refresh_token = self
+            .refresh_token
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        let mut form = vec![
+            ("grant_type", "refresh_token".to_string()),
+            ("refresh_token", refresh_token),
+            ("client_id", self.client_id.clone()),
+        ];
+        if let Some(client_secret) = &self.client_secret {
+            form.push(("client_secret", client_secret.clone()));
+        }
+        if let Some(scope) = &self.scope {
+            form.push(("scope", scope.clone()));
+        }
+
+        let res = reqwest::Client::new()
+            .post(&self.token_url)
+            .form(&form)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|e| Error::CredentialFetch(format!("{}: {}", self.token_url, e)))?;
+        let token: OauthTokenResponse = res
+            .json()
+            .await
+            .map_err(|e| Error::CredentialFetch(format!("{}: {}", self.token_url, e)))?;
+
+        if let Some(refresh_token) = token.refresh_token {
+            *self.refresh_token.lock().unwrap_or_else(|e| e.into_inner()) = refresh_token;
+        }
+
+        Ok(Credential {
+            key: token.access_token,
+            expires_at: token
+                .expires_in
+                .map(|expires_in| now_utc().unix_timestamp() + expires_in),
+        })
+    }
+}
+
+// endregion: --- Providers
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use anyhow::Result;
+
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct CountingCredential {
+        fetches: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl CredentialProvider for Arc<CountingCredential> {
+        async fn fetch(&self) -> super::Result<Credential> {
+            self.fetches.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Ok(Credential {
+                key: "sk-1".to_string(),
+                expires_at: Some(now_utc().unix_timestamp() + 3600),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_credential_cache_single_flight() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_provider = Arc::new(CountingCredential::default());
+        let cache = CredentialCache::new(Box::new(fx_provider.clone()));
+
+        // -- Exec
+        let gets = (0..8).map(|_| {
+            let cache = cache.clone();
+            tokio::spawn(async move { cache.get().await })
+        });
+        for get in gets {
+            assert_eq!(get.await??, "sk-1");
+        }
+
+        // -- Check
+        assert_eq!(fx_provider.fetches.load(Ordering::SeqCst), 1);
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
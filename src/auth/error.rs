@@ -0,0 +1,41 @@
+use serde::Serialize;
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[derive(Debug, Serialize)]
+pub enum Error {
+    // -- Config
+    AuthHeaderMissing { provider: String },
+    AwsRegionMissing { provider: String },
+    CredentialConfig(String),
+
+    // -- Credentials
+    CredentialFetch(String),
+
+    // -- Replit
+    ReplitEnvMissing(String),
+    ReplitTokenInvalid(String),
+    ReplitKeyInvalid(String),
+    ReplitSignatureInvalid,
+    ReplitProtobuf(String),
+    ReplitNotAuthorized(String),
+    ReplitDeploymentToken(String),
+
+    // -- AWS
+    AwsKeyInvalid,
+
+    // -- Request
+    InvalidHeaderName(String),
+    InvalidHeaderValue(String),
+    InvalidUri(String),
+}
+
+// region:    --- Error Boilerplate
+impl core::fmt::Display for Error {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::result::Result<(), core::fmt::Error> {
+        write!(fmt, "{self:?}")
+    }
+}
+
+impl std::error::Error for Error {}
+// endregion: --- Error Boilerplate
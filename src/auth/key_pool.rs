@@ -0,0 +1,480 @@
+use std::sync::atomic::{AtomicI64, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use http::{HeaderMap, StatusCode};
+use serde::{Deserialize, Serialize};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tracing::info;
+
+use super::{CredentialCache, Result};
+use crate::utils::now_utc;
+
+/// Cooldown of a key rate limited or out of quota, without a `retry-after`.
+const RATE_LIMITED_COOLDOWN_MS: i64 = 60_000;
+/// Cooldown of a key the provider does not accept, long enough for the operator to
+/// notice.
+const REFUSED_COOLDOWN_MS: i64 = 600_000;
+/// Longest wait taken from the rate limit headers, whatever they claim.
+const MAX_HEADER_WAIT_MS: i64 = 3_600_000;
+
+// -- Rate limit headers of OpenAI like and Anthropic providers
+const RETRY_AFTER: &str = "retry-after";
+const REMAINING_REQUESTS: [&str; 2] = [
+    "x-ratelimit-remaining-requests",
+    "anthropic-ratelimit-requests-remaining",
+];
+const REMAINING_TOKENS: [&str; 2] = [
+    "x-ratelimit-remaining-tokens",
+    "anthropic-ratelimit-tokens-remaining",
+];
+const RESET_REQUESTS: [&str; 2] = [
+    "x-ratelimit-reset-requests",
+    "anthropic-ratelimit-requests-reset",
+];
+const RESET_TOKENS: [&str; 2] = [
+    "x-ratelimit-reset-tokens",
+    "anthropic-ratelimit-tokens-reset",
+];
+
+/// How the key of a request is picked in the pool of a provider.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeySelection {
+    #[default]
+    RoundRobin,
+    LeastUsed,
+}
+
+/// Operator key of a provider, with its usage counters and the rate limits
+/// last reported by the provider.
+#[derive(Debug)]
+pub struct PooledKey {
+    pub id: String,
+    /// Changes with the source of the key, see `CredentialParams::fingerprint`.
+    fingerprint: String,
+    credential: Arc<CredentialCache>,
+    uses: AtomicU64,
+    failures: AtomicU64,
+    /// Unix timestamp in ms, in the past when the key is usable.
+    available_at_ms: AtomicI64,
+    /// -1 until reported.
+    requests_remaining: AtomicI64,
+    tokens_remaining: AtomicI64,
+}
+
+impl PooledKey {
+    pub fn new(id: String, fingerprint: String, credential: Arc<CredentialCache>) -> Self {
+        Self {
+            id,
+            fingerprint,
+            credential,
+            uses: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            available_at_ms: AtomicI64::new(0),
+            requests_remaining: AtomicI64::new(-1),
+            tokens_remaining: AtomicI64::new(-1),
+        }
+    }
+
+    pub async fn get(&self) -> Result<String> {
+        self.credential.get().await
+    }
+
+    /// Tracks the rate limits reported by the provider, and cools the key down
+    /// if it was rate limited or refused.
+    pub fn record_response(&self, status: StatusCode, headers: &HeaderMap) {
+        let now_ms = now_ms();
+        let requests_remaining = header_i64(headers, &REMAINING_REQUESTS);
+        let tokens_remaining = header_i64(headers, &REMAINING_TOKENS);
+        if let Some(remaining) = requests_remaining {
+            self.requests_remaining.store(remaining, Ordering::Relaxed);
+        }
+        if let Some(remaining) = tokens_remaining {
+            self.tokens_remaining.store(remaining, Ordering::Relaxed);
+        }
+
+        // Out of requests or tokens until the window resets.
+        let mut available_at_ms = None;
+        if requests_remaining == Some(0) {
+            available_at_ms = header_reset_ms(headers, &RESET_REQUESTS, now_ms);
+        }
+        if tokens_remaining == Some(0) {
+            available_at_ms = available_at_ms.max(header_reset_ms(headers, &RESET_TOKENS, now_ms));
+        }
+
+        let cooldown_ms = match status {
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::PAYMENT_REQUIRED => {
+                Some(retry_after_ms(headers).unwrap_or(RATE_LIMITED_COOLDOWN_MS))
+            }
+            // Not on a 403, often caused by the request itself, e.g. a model
+            // the key may not use, which buyers could send to every key.
+            StatusCode::UNAUTHORIZED => Some(REFUSED_COOLDOWN_MS),
+            _ => None,
+        };
+        if let Some(cooldown_ms) = cooldown_ms {
+            info!(
+                "Key {} got {}, cooling down {}ms",
+                self.id, status, cooldown_ms
+            );
+            self.failures.fetch_add(1, Ordering::Relaxed);
+            available_at_ms = available_at_ms.max(Some(now_ms.saturating_add(cooldown_ms)));
+        }
+
+        if let Some(available_at_ms) = available_at_ms {
+            self.available_at_ms
+                .fetch_max(available_at_ms, Ordering::Relaxed);
+        }
+    }
+
+    fn is_cooling_down(&self, now_ms: i64) -> bool {
+        self.available_at_ms.load(Ordering::Relaxed) > now_ms
+    }
+
+    fn stats(&self) -> KeyStats {
+        let available_at_ms = self.available_at_ms.load(Ordering::Relaxed);
+        let remaining = |counter: &AtomicI64| {
+            let remaining = counter.load(Ordering::Relaxed);
+            (remaining >= 0).then_some(remaining)
+        };
+        KeyStats {
+            id: self.id.clone(),
+            uses: self.uses.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            cooldown_until: self
+                .is_cooling_down(now_ms())
+                .then_some(available_at_ms / 1000),
+            requests_remaining: remaining(&self.requests_remaining),
+            tokens_remaining: remaining(&self.tokens_remaining),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeyStats {
+    pub id: String,
+    pub uses: u64,
+    pub failures: u64,
+    pub cooldown_until: Option<i64>,
+    pub requests_remaining: Option<i64>,
+    pub tokens_remaining: Option<i64>,
+}
+
+/// Keys of a provider, shared by the clones of its params.
+#[derive(Debug)]
+pub struct KeyPool {
+    keys: Vec<Arc<PooledKey>>,
+    selection: KeySelection,
+    next: AtomicUsize,
+}
+
+impl KeyPool {
+    /// `keys` must not be empty.
+    pub fn new(keys: Vec<PooledKey>, selection: KeySelection) -> Self {
+        Self {
+            keys: keys.into_iter().map(Arc::new).collect(),
+            selection,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Key for the next request, skipping the keys cooling down. When all of
+    /// them are, the one available first is used.
+    pub fn select(&self) -> Arc<PooledKey> {
+        let now = now_ms();
+        let key = match self.selection {
+            KeySelection::RoundRobin => (0..self.keys.len())
+                .map(|_| &self.keys[self.next.fetch_add(1, Ordering::Relaxed) % self.keys.len()])
+                .find(|key| !key.is_cooling_down(now)),
+            KeySelection::LeastUsed => self
+                .keys
+                .iter()
+                .filter(|key| !key.is_cooling_down(now))
+                .min_by_key(|key| key.uses.load(Ordering::Relaxed)),
+        };
+        let key = key
+            .or_else(|| {
+                self.keys
+                    .iter()
+                    .min_by_key(|key| key.available_at_ms.load(Ordering::Relaxed))
+            })
+            .expect("key pool is never empty");
+
+        key.uses.fetch_add(1, Ordering::Relaxed);
+        key.clone()
+    }
+
+    /// Keeps the keys of `previous` which did not change, with their usage
+    /// counters, cooldowns and cached credentials, so a reload does not reset
+    /// them.
+    pub fn carry_over(mut self, previous: &KeyPool) -> Self {
+        for key in &mut self.keys {
+            let previous_key = previous.keys.iter().find(|previous_key| {
+                previous_key.id == key.id && previous_key.fingerprint == key.fingerprint
+            });
+            if let Some(previous_key) = previous_key {
+                *key = previous_key.clone();
+            }
+        }
+
+        self
+    }
+
+    /// Time until a key is usable, 0 if one is already.
+    pub fn wait_ms(&self) -> i64 {
+        let now = now_ms();
+        self.keys
+            .iter()
+            .map(|key| key.available_at_ms.load(Ordering::Relaxed) - now)
+            .min()
+            .unwrap_or_default()
+            .max(0)
+    }
+
+    pub fn stats(&self) -> Vec<KeyStats> {
+        self.keys.iter().map(|key| key.stats()).collect()
+    }
+}
+
+// region:    --- Rate Limit Headers
+fn now_ms() -> i64 {
+    (now_utc().unix_timestamp_nanos() / 1_000_000) as i64
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, names: &[&str]) -> Option<&'a str> {
+    names
+        .iter()
+        .find_map(|name| headers.get(*name))
+        .and_then(|value| value.to_str().ok())
+}
+
+fn header_i64(headers: &HeaderMap, names: &[&str]) -> Option<i64> {
+    header_str(headers, names).and_then(|value| value.trim().parse().ok())
+}
+
+/// `retry-after` in seconds, the http date form is not used by the providers.
+/// Within `0..=MAX_HEADER_WAIT_MS`, so a bogus value cannot overflow a wait.
+pub fn retry_after_ms(headers: &HeaderMap) -> Option<i64> {
+    header_str(headers, &[RETRY_AFTER])
+        .and_then(|value| value.trim().parse::<f64>().ok())
+        .and_then(|sec| wait_ms(sec * 1000.0))
+}
+
+/// Reset as a duration (`1s`, `6m0s`, `20ms`) or an RFC 3339 timestamp, at
+/// most `MAX_HEADER_WAIT_MS` from now.
+fn header_reset_ms(headers: &HeaderMap, names: &[&str], now_ms: i64) -> Option<i64> {
+    let value = header_str(headers, names)?.trim();
+    let max_ms = now_ms.saturating_add(MAX_HEADER_WAIT_MS);
+    if let Ok(time) = OffsetDateTime::parse(value, &Rfc3339) {
+        let time_ms = (time.unix_timestamp_nanos() / 1_000_000).min(max_ms as i128);
+        return Some(time_ms as i64);
+    }
+
+    parse_duration_ms(value).map(|duration_ms| now_ms.saturating_add(duration_ms))
+}
+
+/// Wait within `0..=MAX_HEADER_WAIT_MS`, `None` for NaN.
+fn wait_ms(ms: f64) -> Option<i64> {
+    match ms.is_nan() {
+        true => None,
+        false => Some(ms.clamp(0.0, MAX_HEADER_WAIT_MS as f64) as i64),
+    }
+}
+
+/// Go style durations, e.g. `1h2m3.5s` or `250ms`.
+fn parse_duration_ms(value: &str) -> Option<i64> {
+    let mut total_ms = 0.0;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let number: f64 = rest[..number_len].parse().ok()?;
+        rest = &rest[number_len..];
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        let unit_ms = match &rest[..unit_len] {
+            "h" => 3_600_000.0,
+            "m" => 60_000.0,
+            "s" => 1_000.0,
+            "ms" => 1.0,
+            _ => return None,
+        };
+        rest = &rest[unit_len..];
+        total_ms += number * unit_ms;
+    }
+
+    wait_ms(total_ms)
+}
+// endregion: --- Rate Limit Headers
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::auth::StaticCredential;
+
+    fn fx_pool(selection: KeySelection) -> KeyPool {
+        fx_pool_of(selection, &["key-1", "key-2", "key-3"])
+    }
+
+    fn fx_pool_of(selection: KeySelection, keys: &[&str]) -> KeyPool {
+        let keys = keys
+            .iter()
+            .map(|key| {
+                let credential = CredentialCache::new(Box::new(StaticCredential(key.to_string())));
+                let id = key.split(':').next().unwrap_or_default();
+                PooledKey::new(id.to_string(), key.to_string(), credential)
+            })
+            .collect();
+        KeyPool::new(keys, selection)
+    }
+
+    #[test]
+    fn test_key_pool_round_robin_skips_cooldown() -> Result<()> {
+        // -- Setup & Fixtures
+        let pool = fx_pool(KeySelection::RoundRobin);
+
+        // -- Exec
+        pool.select()
+            .record_response(StatusCode::TOO_MANY_REQUESTS, &HeaderMap::new());
+        let ids: Vec<String> = (0..4).map(|_| pool.select().id.clone()).collect();
+
+        // -- Check
+        assert_eq!(ids, ["key-2", "key-3", "key-2", "key-3"]);
+        assert_eq!(pool.stats()[0].failures, 1);
+        assert!(pool.stats()[0].cooldown_until.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_pool_cooldown_unauthorized_only() -> Result<()> {
+        // -- Setup & Fixtures
+        let pool = fx_pool(KeySelection::RoundRobin);
+
+        // -- Exec
+        pool.select()
+            .record_response(StatusCode::FORBIDDEN, &HeaderMap::new());
+        pool.select()
+            .record_response(StatusCode::UNAUTHORIZED, &HeaderMap::new());
+
+        // -- Check
+        let stats = pool.stats();
+        assert!(stats[0].cooldown_until.is_none());
+        assert!(stats[1].cooldown_until.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_pool_least_used() -> Result<()> {
+        // -- Setup & Fixtures
+        let pool = fx_pool(KeySelection::LeastUsed);
+
+        // -- Exec
+        let ids: Vec<String> = (0..6).map(|_| pool.select().id.clone()).collect();
+
+        // -- Check
+        assert_eq!(ids, ["key-1", "key-2", "key-3", "key-1", "key-2", "key-3"]);
+        assert!(pool.stats().iter().all(|stats| stats.uses == 2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_pool_rate_limit_headers() -> Result<()> {
+        // -- Setup & Fixtures
+        let pool = fx_pool(KeySelection::RoundRobin);
+        let mut fx_headers = HeaderMap::new();
+        fx_headers.insert("x-ratelimit-remaining-requests", "0".parse()?);
+        fx_headers.insert("x-ratelimit-remaining-tokens", "1200".parse()?);
+        fx_headers.insert("x-ratelimit-reset-requests", "1m30s".parse()?);
+
+        // -- Exec
+        for _ in 0..3 {
+            pool.select().record_response(StatusCode::OK, &fx_headers);
+        }
+
+        // -- Check
+        let stats = &pool.stats()[0];
+        assert_eq!(stats.requests_remaining, Some(0));
+        assert_eq!(stats.tokens_remaining, Some(1200));
+        assert!((89_000..=90_000).contains(&pool.wait_ms()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_pool_carry_over() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_previous = fx_pool_of(KeySelection::RoundRobin, &["key-1:a", "key-2:b"]);
+        for _ in 0..2 {
+            fx_previous
+                .select()
+                .record_response(StatusCode::TOO_MANY_REQUESTS, &HeaderMap::new());
+        }
+
+        // -- Exec
+        // key-1 is unchanged, key-2 was rotated.
+        let pool =
+            fx_pool_of(KeySelection::RoundRobin, &["key-1:a", "key-2:c"]).carry_over(&fx_previous);
+
+        // -- Check
+        let stats = pool.stats();
+        assert_eq!((stats[0].uses, stats[0].failures), (1, 1));
+        assert!(stats[0].cooldown_until.is_some());
+        assert_eq!((stats[1].uses, stats[1].failures), (0, 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_retry_after_ms_bounded() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_retry_after = |value: &'static str| {
+            HeaderMap::from_iter([(RETRY_AFTER.parse().unwrap(), value.parse().unwrap())])
+        };
+        let pool = fx_pool(KeySelection::RoundRobin);
+
+        // -- Exec
+        pool.select()
+            .record_response(StatusCode::TOO_MANY_REQUESTS, &fx_retry_after("inf"));
+
+        // -- Check
+        assert_eq!(retry_after_ms(&fx_retry_after("-1")), Some(0));
+        assert_eq!(
+            retry_after_ms(&fx_retry_after("inf")),
+            Some(MAX_HEADER_WAIT_MS)
+        );
+        assert_eq!(
+            retry_after_ms(&fx_retry_after("1e300")),
+            Some(MAX_HEADER_WAIT_MS)
+        );
+        assert_eq!(retry_after_ms(&fx_retry_after("NaN")), None);
+        assert_eq!(retry_after_ms(&fx_retry_after("2.5")), Some(2_500));
+        assert_eq!(pool.wait_ms(), 0);
+        assert!(pool.stats()[0].cooldown_until.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_duration_ms() -> Result<()> {
+        // -- Check
+        assert_eq!(parse_duration_ms("6m0s"), Some(360_000));
+        assert_eq!(parse_duration_ms("1.5s"), Some(1_500));
+        assert_eq!(parse_duration_ms("20ms"), Some(20));
+        assert_eq!(parse_duration_ms("soon"), None);
+        assert_eq!(
+            parse_duration_ms("99999999999999h"),
+            Some(MAX_HEADER_WAIT_MS)
+        );
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
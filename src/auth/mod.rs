@@ -0,0 +1,119 @@
+// region:    --- Modules
+
+mod credentials;
+mod error;
+mod google;
+mod key_pool;
+pub mod replit;
+mod sigv4;
+mod strategies;
+
+pub use self::credentials::*;
+pub use self::error::{Error, Result};
+pub use self::google::GoogleServiceAccountCredential;
+pub use self::key_pool::*;
+pub use self::sigv4::SigV4Auth;
+pub use self::strategies::*;
+
+use std::fmt::Debug;
+
+use http::header::AUTHORIZATION;
+use http::request::Parts;
+use http::HeaderName;
+
+use crate::config::apis::{ApiParams, AuthKind};
+
+// endregion: --- Modules
+
+/// Injects the operator credentials into a request forwarded to a provider.
+/// `body` is the final body, for the strategies signing the request.
+pub trait AuthStrategy: Debug + Send + Sync {
+    fn apply(&self, parts: &mut Parts, body: &[u8], key: &str) -> Result<()>;
+}
+
+/// Strategies of a provider, its extra `headers` then its `auth` kind, last
+/// as it may sign the headers.
+pub fn strategies(params: &ApiParams) -> Result<Vec<Box<dyn AuthStrategy>>> {
+    let mut strategies: Vec<Box<dyn AuthStrategy>> = Vec::new();
+    if !params.headers.is_empty() {
+        strategies.push(Box::new(StaticHeaders::new(&params.headers)?));
+    }
+    strategies.push(strategy(params)?);
+
+    Ok(strategies)
+}
+
+/// Headers of the buyer paying matador, which the provider could spend.
+const CLIENT_AUTH_HEADERS: [HeaderName; 2] = [AUTHORIZATION, HeaderName::from_static("x-cashu")];
+
+/// Applies all the strategies of the provider to the request, once the
+/// credentials of the buyer are removed.
+pub fn apply(params: &ApiParams, parts: &mut Parts, body: &[u8], key: &str) -> Result<()> {
+    for name in &CLIENT_AUTH_HEADERS {
+        parts.headers.remove(name);
+    }
+    for strategy in strategies(params)? {
+        strategy.apply(parts, body, key)?;
+    }
+
+    Ok(())
+}
+
+/// Strategy registered for the `auth` kind of the provider.
+fn strategy(params: &ApiParams) -> Result<Box<dyn AuthStrategy>> {
+    let strategy: Box<dyn AuthStrategy> = match params.auth {
+        AuthKind::Bearer => Box::new(BearerAuth),
+        AuthKind::Basic => Box::new(BasicAuth),
+        AuthKind::Token => Box::new(TokenAuth),
+        AuthKind::XApiKey => Box::new(HeaderAuth::new(X_API_KEY)?),
+        AuthKind::Header => {
+            let name = params
+                .auth_header
+                .as_deref()
+                .ok_or_else(|| Error::AuthHeaderMissing {
+                    provider: params.name.clone(),
+                })?;
+            Box::new(HeaderAuth::new(name)?)
+        }
+        AuthKind::QueryParam => {
+            let name = params.auth_query_param.as_deref().unwrap_or("key");
+            Box::new(QueryParamAuth::new(name))
+        }
+        AuthKind::None => Box::new(NoAuth),
+        AuthKind::AwsSigv4 => Box::new(SigV4Auth::new(params)?),
+    };
+
+    Ok(strategy)
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use http::Request;
+
+    use super::*;
+
+    #[test]
+    fn test_apply_strips_client_auth() -> Result<()> {
+        // -- Setup & Fixtures
+        let (mut fx_parts, _) = Request::post("/v1/messages")
+            .header(AUTHORIZATION, "L402 macaroon:preimage")
+            .header("x-cashu", "cashuAeyJ0b2tlbiI6W119")
+            .body(())?
+            .into_parts();
+        let mut fx_params = ApiParams::new().name("anthropic").auth(AuthKind::Header);
+        fx_params.auth_header = Some("api-key".to_string());
+
+        // -- Exec
+        apply(&fx_params, &mut fx_parts, b"", "sk-1")?;
+
+        // -- Check
+        assert_eq!(fx_parts.headers["api-key"], "sk-1");
+        assert!(fx_parts.headers.get(AUTHORIZATION).is_none());
+        assert!(fx_parts.headers.get("x-cashu").is_none());
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
@@ -0,0 +1,189 @@
+// region:    --- Modules
+
+mod paseto;
+mod protobuf;
+mod verify;
+
+pub use self::verify::{read_public_key_from_env, PubKeySource};
+
+use async_trait::async_trait;
+use ring::signature::Ed25519KeyPair;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::info;
+
+use self::paseto::Token;
+use self::protobuf::GovalReplIdentity;
+use super::{Credential, CredentialProvider, Error, Result};
+use crate::config::env_var;
+use crate::utils::now_utc;
+
+// endregion: --- Modules
+
+/// Audience of the tokens sent to the Modelfarm.
+pub const MODELFARM_AUDIENCE: &str = "modelfarm@replit.com";
+/// Lifetime of a signed token before it is signed again.
+const TOKEN_TIMEOUT_SEC: i64 = 300;
+/// Host endpoint signing the tokens in a deployment.
+const DEPLOYMENT_TOKEN_URL: &str = "http://localhost:1105/getIdentityToken";
+
+/// Signs tokens proving the identity of this repl to another audience, so the
+/// audience cannot forward them as its own.
+pub struct SigningAuthority {
+    identity: GovalReplIdentity,
+    /// Footer of the identity token, naming the cert of the repl key.
+    footer: Vec<u8>,
+    key_pair: Ed25519KeyPair,
+}
+
+impl SigningAuthority {
+    /// `private_key` is the PASERK of the repl key and `identity` the PASETO
+    /// of the repl identity, signed for `replid`.
+    pub fn new(
+        private_key: &str,
+        identity: &str,
+        replid: &str,
+        pubkey_source: &PubKeySource,
+    ) -> Result<Self> {
+        Ok(Self {
+            identity: verify::verify_identity_token(identity, replid, pubkey_source)?,
+            footer: Token::parse(identity)?.footer,
+            key_pair: paseto::key_pair_from_paserk(private_key)?,
+        })
+    }
+
+    /// The identity signed for `audience`, as a PASETO.
+    pub fn sign(&self, audience: &str) -> Result<String> {
+        let identity = self.identity.encode_with_aud(audience)?;
+
+        Ok(paseto::sign(
+            &self.key_pair,
+            &paseto::b64_encode(&identity),
+            &self.footer,
+        ))
+    }
+}
+
+/// Identity token for the Modelfarm, signed by the repl key in an interactive
+/// repl and by the host in a deployment.
+#[derive(Debug)]
+pub struct ReplitIdentityCredential;
+
+#[async_trait]
+impl CredentialProvider for ReplitIdentityCredential {
+    async fn fetch(&self) -> Result<Credential> {
+        let key = if env_var("REPLIT_DEPLOYMENT").is_some() {
+            deployment_token().await?
+        } else {
+            interactive_token()?
+        };
+        info!("Generated Replit identity token");
+
+        Ok(Credential {
+            key,
+            expires_at: Some(now_utc().unix_timestamp() + TOKEN_TIMEOUT_SEC),
+        })
+    }
+}
+
+fn interactive_token() -> Result<String> {
+    let authority = SigningAuthority::new(
+        &get_env("REPL_IDENTITY_KEY")?,
+        &get_env("REPL_IDENTITY")?,
+        &get_env("REPL_ID")?,
+        &read_public_key_from_env,
+    )?;
+
+    authority.sign(MODELFARM_AUDIENCE)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DeploymentTokenResponse {
+    identity_token: String,
+}
+
+async fn deployment_token() -> Result<String> {
+    let res = reqwest::Client::new()
+        .post(DEPLOYMENT_TOKEN_URL)
+        .json(&json!({ "audience": MODELFARM_AUDIENCE }))
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .map_err(|e| Error::ReplitDeploymentToken(e.to_string()))?;
+    let res: DeploymentTokenResponse = res
+        .json()
+        .await
+        .map_err(|e| Error::ReplitDeploymentToken(e.to_string()))?;
+
+    Ok(res.identity_token)
+}
+
+fn get_env(name: &str) -> Result<String> {
+    env_var(name).ok_or_else(|| Error::ReplitEnvMissing(name.to_string()))
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    // Chain of a root key `root-1`, a repl cert and a repl identity, with the
+    // expected Modelfarm token, generated with an independent python signer.
+    const FX_ROOT_PUBKEY: &str = "A6EHv/POEL4dcN0Y50vAmWfk1jCbpQ1fHdyGZBJVMbg=";
+    const FX_PRIVATE_KEY: &str = "k2.secret.ICEiIyQlJicoKSorLC0uLzAxMjM0NTY3ODk6Ozw9Pj8prLrhQbzK8LIuGpTTTQvHNh5SbQv-EsiXlLyTIpZt1w";
+    const FX_REPLID: &str = "f8f4a1f4-8c2e-4f0d-9a57-000000000001";
+    const FX_IDENTITY: &str = "v2.public.Q2lSbU9HWTBZVEZtTkMwNFl6SmxMVFJtTUdRdE9XRTFOeTB3TURBd01EQXdNREF3TURFU0IyMWhkR0ZrYjNJYUIyMWhkR0ZrYjNJaUpHWTRaalJoTVdZMExUaGpNbVV0TkdZd1pDMDVZVFUzTFRBd01EQXdNREF3TURBd01UZ3FXZzhLQm1kc2IySmhiQklGYzNWaUxURT2tVlruubB8ANZp7gH1RIKP1Q7PMHA3zBkkqFKt_4FtIu5iASjPieoLTYe5BC-g0lO2Rg4gyuX67rPkyo5qrKAK.RXVZQ2RqSXVjSFZpYkdsakxsRXlaRnBUVjJSUVZFWkNlRm94YkZSUmJXUndVVmhLZDJKWGRFVmxSemwwVVRKc1UySlZPVWhYVkVKYVZrVmFkRlJyVFhkT1JtdzJVMjE0VFZaR1NuUlVWV1JTWkVVNVdGSlVSazlsVkVJelZGVlNRbVF3TVVWUldHUk9Va1ZHTTFSVlVrWlpWVVp2V2pCYVNGb3laSEJSYlRGcll6SkplVk50YUdsUmJUbEVVakJHY21GVk5WaGpNMnhOWW10SmVGZFhNVFJqUm13MVRsVjRXbGRIYzNsVWExcFhVMFU1U1dKRVNsSk5NbmhPV1ZWb1IxWnJNVVZSV0dSVlUwZG5NbFl4WkZkV2JVcFZVV3N4V1UxdGFFNVZNV1F6VFZaV05tRklTbXhXV0ZKWlYxY3hVMkZ5TVdzMFNIRmFhelZzYjNKbFh6TmpURkpzYzFkWmFIRTJNR0V0VGs4ellWVXpjazlWUnpsRE56bEhhazFQTUZGRlREbGtVelJTTkRsSE4wWnZSM1J6VURaU2VIUjNiRGRSZERSeGIyY3hXVmhJZFhGUlRTNVJNbVJoWlZkSmVVOVVRazFXUlZaYVVWWk9TbEl4YTNsUFdGWnBWakJhTVJnQklnWmpiMjV0WVc0PQ";
+    const FX_MODELFARM_TOKEN: &str = "v2.public.Q2lSbU9HWTBZVEZtTkMwNFl6SmxMVFJtTUdRdE9XRTFOeTB3TURBd01EQXdNREF3TURFU0IyMWhkR0ZrYjNJYUIyMWhkR0ZrYjNJaUZHMXZaR1ZzWm1GeWJVQnlaWEJzYVhRdVkyOXRPQ3BhRHdvR1oyeHZZbUZzRWdWemRXSXRNUT09Ci27Bwd9uekXv_AWG1y-2hmp4E1J4i8A6dTVSQos46HjNlaBfLsz1IQSbkPlrp9zFBvbfMUnYQYI3YzC-uv3AA.RXVZQ2RqSXVjSFZpYkdsakxsRXlaRnBUVjJSUVZFWkNlRm94YkZSUmJXUndVVmhLZDJKWGRFVmxSemwwVVRKc1UySlZPVWhYVkVKYVZrVmFkRlJyVFhkT1JtdzJVMjE0VFZaR1NuUlVWV1JTWkVVNVdGSlVSazlsVkVJelZGVlNRbVF3TVVWUldHUk9Va1ZHTTFSVlVrWlpWVVp2V2pCYVNGb3laSEJSYlRGcll6SkplVk50YUdsUmJUbEVVakJHY21GVk5WaGpNMnhOWW10SmVGZFhNVFJqUm13MVRsVjRXbGRIYzNsVWExcFhVMFU1U1dKRVNsSk5NbmhPV1ZWb1IxWnJNVVZSV0dSVlUwZG5NbFl4WkZkV2JVcFZVV3N4V1UxdGFFNVZNV1F6VFZaV05tRklTbXhXV0ZKWlYxY3hVMkZ5TVdzMFNIRmFhelZzYjNKbFh6TmpURkpzYzFkWmFIRTJNR0V0VGs4ellWVXpjazlWUnpsRE56bEhhazFQTUZGRlREbGtVelJTTkRsSE4wWnZSM1J6VURaU2VIUjNiRGRSZERSeGIyY3hXVmhJZFhGUlRTNVJNbVJoWlZkSmVVOVVRazFXUlZaYVVWWk9TbEl4YTNsUFdGWnBWakJhTVJnQklnWmpiMjV0WVc0PQ";
+
+    fn fx_pubkey_source(key_id: &str, _issuer: &str) -> super::Result<Vec<u8>> {
+        match key_id {
+            "root-1" => paseto::b64_decode(FX_ROOT_PUBKEY.as_bytes()),
+            _ => Err(Error::ReplitKeyInvalid(key_id.to_string())),
+        }
+    }
+
+    #[test]
+    fn test_signing_authority_sign_ok() -> Result<()> {
+        // -- Setup & Fixtures
+        let authority =
+            SigningAuthority::new(FX_PRIVATE_KEY, FX_IDENTITY, FX_REPLID, &fx_pubkey_source)?;
+
+        // -- Exec
+        let token = authority.sign(MODELFARM_AUDIENCE)?;
+
+        // -- Check
+        assert_eq!(token, FX_MODELFARM_TOKEN);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_signing_authority_err_audience() -> Result<()> {
+        // -- Exec
+        let res =
+            SigningAuthority::new(FX_PRIVATE_KEY, FX_IDENTITY, "other-repl", &fx_pubkey_source);
+
+        // -- Check
+        assert!(matches!(res, Err(Error::ReplitNotAuthorized(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_signing_authority_err_tampered() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_identity = FX_IDENTITY.replacen("v2.public.Q2", "v2.public.Q3", 1);
+
+        // -- Exec
+        let res = SigningAuthority::new(FX_PRIVATE_KEY, &fx_identity, FX_REPLID, &fx_pubkey_source);
+
+        // -- Check
+        assert!(res.is_err());
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
@@ -0,0 +1,133 @@
+// PASETO v2.public tokens and PASERK k2 keys, ed25519 only.
+
+use base64_url::base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64_url::base64::Engine as _;
+use ring::signature::{Ed25519KeyPair, UnparsedPublicKey, ED25519};
+
+use super::super::{Error, Result};
+
+const HEADER: &str = "v2.public.";
+const SIGNATURE_LEN: usize = 64;
+const PASERK_SECRET: &str = "k2.secret.";
+const PASERK_PUBLIC: &str = "k2.public.";
+
+/// Parts of a token, the signature not being checked yet.
+#[derive(Debug)]
+pub struct Token {
+    pub message: Vec<u8>,
+    signature: Vec<u8>,
+    pub footer: Vec<u8>,
+}
+
+impl Token {
+    pub fn parse(token: &str) -> Result<Self> {
+        let parts: Vec<&str> = token.split('.').collect();
+        let [version, purpose, payload, footer] = parts[..] else {
+            return Err(Error::ReplitTokenInvalid(
+                "token is not correctly PASETO-encoded".to_string(),
+            ));
+        };
+        if version != "v2" {
+            return Err(Error::ReplitTokenInvalid(format!(
+                "only v2 is supported: {version}"
+            )));
+        }
+        if purpose != "public" {
+            return Err(Error::ReplitTokenInvalid(format!(
+                "only \"public\" purpose is supported: {purpose}"
+            )));
+        }
+
+        let mut message = b64u_decode(payload)?;
+        if message.len() < SIGNATURE_LEN {
+            return Err(Error::ReplitTokenInvalid("payload too short".to_string()));
+        }
+        let signature = message.split_off(message.len() - SIGNATURE_LEN);
+
+        Ok(Self {
+            message,
+            signature,
+            footer: b64u_decode(footer)?,
+        })
+    }
+
+    pub fn verify(&self, public_key: &[u8]) -> Result<()> {
+        UnparsedPublicKey::new(&ED25519, public_key)
+            .verify(
+                &pae(&[HEADER.as_bytes(), &self.message, &self.footer]),
+                &self.signature,
+            )
+            .map_err(|_| Error::ReplitSignatureInvalid)
+    }
+}
+
+pub fn sign(key_pair: &Ed25519KeyPair, message: &[u8], footer: &[u8]) -> String {
+    let signature = key_pair.sign(&pae(&[HEADER.as_bytes(), message, footer]));
+    let mut payload = message.to_vec();
+    payload.extend_from_slice(signature.as_ref());
+
+    format!(
+        "{HEADER}{}.{}",
+        URL_SAFE_NO_PAD.encode(payload),
+        URL_SAFE_NO_PAD.encode(footer)
+    )
+}
+
+/// Pre-authentication encoding of the signed pieces.
+fn pae(pieces: &[&[u8]]) -> Vec<u8> {
+    let mut out = (pieces.len() as u64).to_le_bytes().to_vec();
+    for piece in pieces {
+        out.extend_from_slice(&(piece.len() as u64).to_le_bytes());
+        out.extend_from_slice(piece);
+    }
+
+    out
+}
+
+/// `k2.secret.` key, the ed25519 seed followed by the public key.
+pub fn key_pair_from_paserk(paserk: &str) -> Result<Ed25519KeyPair> {
+    let key = paserk
+        .strip_prefix(PASERK_SECRET)
+        .ok_or_else(|| Error::ReplitKeyInvalid(format!("expected {PASERK_SECRET}")))
+        .and_then(b64u_decode)?;
+    if key.len() != 64 {
+        return Err(Error::ReplitKeyInvalid(
+            "secret key must be 64 bytes".to_string(),
+        ));
+    }
+
+    Ed25519KeyPair::from_seed_and_public_key(&key[..32], &key[32..])
+        .map_err(|e| Error::ReplitKeyInvalid(e.to_string()))
+}
+
+/// `k2.public.` key, the raw ed25519 public key.
+pub fn public_key_from_paserk(paserk: &str) -> Result<Vec<u8>> {
+    let key = paserk
+        .strip_prefix(PASERK_PUBLIC)
+        .ok_or_else(|| Error::ReplitKeyInvalid(format!("expected {PASERK_PUBLIC}")))
+        .and_then(b64u_decode)?;
+    if key.len() != 32 {
+        return Err(Error::ReplitKeyInvalid(
+            "public key must be 32 bytes".to_string(),
+        ));
+    }
+
+    Ok(key)
+}
+
+fn b64u_decode(content: &str) -> Result<Vec<u8>> {
+    URL_SAFE_NO_PAD
+        .decode(content.trim_end_matches('='))
+        .map_err(|e| Error::ReplitTokenInvalid(e.to_string()))
+}
+
+/// The goval messages are base64 encoded before being signed.
+pub fn b64_decode(content: &[u8]) -> Result<Vec<u8>> {
+    STANDARD
+        .decode(content)
+        .map_err(|e| Error::ReplitTokenInvalid(e.to_string()))
+}
+
+pub fn b64_encode(content: &[u8]) -> Vec<u8> {
+    STANDARD.encode(content).into_bytes()
+}
@@ -0,0 +1,314 @@
+// Minimal protobuf wire format for the goval identity messages
+// (goval `api/signing.proto`), only the fields matador reads.
+
+use super::super::{Error, Result};
+
+const WIRE_VARINT: u8 = 0;
+const WIRE_FIXED64: u8 = 1;
+const WIRE_LEN: u8 = 2;
+const WIRE_FIXED32: u8 = 5;
+
+// -- GovalReplIdentity field numbers
+const IDENTITY_REPLID: u32 = 1;
+const IDENTITY_AUD: u32 = 4;
+const IDENTITY_INTERACTIVE: u32 = 11;
+const IDENTITY_DEPLOYMENT: u32 = 12;
+
+/// `FlagClaim` values.
+pub const FLAG_SIGN_INTERMEDIATE_CERT: u64 = 1;
+pub const FLAG_ANY_CLUSTER: u64 = 4;
+pub const FLAG_ANY_SUBCLUSTER: u64 = 9;
+
+// region:    --- Wire Format
+
+#[derive(Debug, Clone, Copy)]
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// A field of a message, with its raw encoding kept for re-encoding.
+#[derive(Debug, Clone, Copy)]
+struct Field<'a> {
+    number: u32,
+    value: Value<'a>,
+    raw: &'a [u8],
+}
+
+impl<'a> Field<'a> {
+    fn bytes(&self) -> Result<&'a [u8]> {
+        match self.value {
+            Value::Bytes(bytes) => Ok(bytes),
+            _ => Err(protobuf_err(format!("field {} is not bytes", self.number))),
+        }
+    }
+
+    fn string(&self) -> Result<String> {
+        String::from_utf8(self.bytes()?.to_vec())
+            .map_err(|_| protobuf_err(format!("field {} is not utf-8", self.number)))
+    }
+
+    fn varint(&self) -> Result<u64> {
+        match self.value {
+            Value::Varint(value) => Ok(value),
+            _ => Err(protobuf_err(format!(
+                "field {} is not a varint",
+                self.number
+            ))),
+        }
+    }
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes
+            .get(*pos)
+            .ok_or_else(|| protobuf_err("truncated varint"))?;
+        *pos += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte < 0x80 {
+            return Ok(value);
+        }
+    }
+
+    Err(protobuf_err("varint too long"))
+}
+
+fn read_fields(bytes: &[u8]) -> Result<Vec<Field<'_>>> {
+    let mut fields = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let start = pos;
+        let key = read_varint(bytes, &mut pos)?;
+        let number = (key >> 3) as u32;
+        let value = match (key & 0x7) as u8 {
+            WIRE_VARINT => Value::Varint(read_varint(bytes, &mut pos)?),
+            WIRE_LEN => {
+                let len = read_varint(bytes, &mut pos)? as usize;
+                let end = pos
+                    .checked_add(len)
+                    .filter(|end| *end <= bytes.len())
+                    .ok_or_else(|| protobuf_err("truncated field"))?;
+                let value = Value::Bytes(&bytes[pos..end]);
+                pos = end;
+                value
+            }
+            WIRE_FIXED64 => {
+                pos += 8;
+                Value::Fixed
+            }
+            WIRE_FIXED32 => {
+                pos += 4;
+                Value::Fixed
+            }
+            wire => return Err(protobuf_err(format!("unsupported wire type {wire}"))),
+        };
+        if pos > bytes.len() {
+            return Err(protobuf_err("truncated field"));
+        }
+        fields.push(Field {
+            number,
+            value,
+            raw: &bytes[start..pos],
+        });
+    }
+
+    Ok(fields)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_bytes(out: &mut Vec<u8>, number: u32, bytes: &[u8]) {
+    write_varint(out, (u64::from(number) << 3) | u64::from(WIRE_LEN));
+    write_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn protobuf_err(msg: impl Into<String>) -> Error {
+    Error::ReplitProtobuf(msg.into())
+}
+
+// endregion: --- Wire Format
+
+// region:    --- Messages
+
+/// `GovalSigningAuthority`, the footer of the goval tokens.
+#[derive(Debug, Default)]
+pub struct GovalSigningAuthority {
+    pub key_id: String,
+    pub signed_cert: String,
+    pub issuer: String,
+}
+
+impl GovalSigningAuthority {
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut authority = Self::default();
+        for field in read_fields(bytes)? {
+            match field.number {
+                1 => authority.key_id = field.string()?,
+                2 => authority.signed_cert = field.string()?,
+                4 => authority.issuer = field.string()?,
+                _ => {}
+            }
+        }
+
+        Ok(authority)
+    }
+}
+
+/// `CertificateClaim`, a oneof of which only the checked variants are kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CertificateClaim {
+    Replid(String),
+    User(String),
+    UserId(i64),
+    Cluster(String),
+    Subcluster(String),
+    Flag(u64),
+    Other,
+}
+
+impl CertificateClaim {
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut claim = Self::Other;
+        for field in read_fields(bytes)? {
+            claim = match field.number {
+                1 => Self::Replid(field.string()?),
+                2 => Self::User(field.string()?),
+                3 => Self::Flag(field.varint()?),
+                4 => Self::Cluster(field.string()?),
+                5 => Self::Subcluster(field.string()?),
+                7 => Self::UserId(field.varint()? as i64),
+                _ => Self::Other,
+            };
+        }
+
+        Ok(claim)
+    }
+}
+
+/// `GovalCert`, with `iat` and `exp` as unix timestamps.
+#[derive(Debug, Default)]
+pub struct GovalCert {
+    pub iat: i64,
+    pub exp: i64,
+    pub claims: Vec<CertificateClaim>,
+    pub public_key: String,
+}
+
+impl GovalCert {
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut cert = Self::default();
+        for field in read_fields(bytes)? {
+            match field.number {
+                1 => cert.iat = decode_timestamp(field.bytes()?)?,
+                2 => cert.exp = decode_timestamp(field.bytes()?)?,
+                3 => cert.claims.push(CertificateClaim::decode(field.bytes()?)?),
+                4 => cert.public_key = field.string()?,
+                _ => {}
+            }
+        }
+
+        Ok(cert)
+    }
+
+    pub fn has_flag(&self, flag: u64) -> bool {
+        self.claims.contains(&CertificateClaim::Flag(flag))
+    }
+}
+
+/// `google.protobuf.Timestamp`, seconds only.
+fn decode_timestamp(bytes: &[u8]) -> Result<i64> {
+    let mut seconds = 0;
+    for field in read_fields(bytes)? {
+        if field.number == 1 {
+            seconds = field.varint()? as i64;
+        }
+    }
+
+    Ok(seconds)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReplRuntime {
+    Interactive { cluster: String, subcluster: String },
+    Deployment,
+}
+
+/// `GovalReplIdentity`, kept encoded so that re-signing it for another
+/// audience leaves the other fields untouched.
+#[derive(Debug)]
+pub struct GovalReplIdentity {
+    bytes: Vec<u8>,
+    pub replid: String,
+    pub aud: String,
+    pub runtime: Option<ReplRuntime>,
+}
+
+impl GovalReplIdentity {
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut identity = Self {
+            bytes: bytes.to_vec(),
+            replid: String::new(),
+            aud: String::new(),
+            runtime: None,
+        };
+        for field in read_fields(bytes)? {
+            match field.number {
+                IDENTITY_REPLID => identity.replid = field.string()?,
+                IDENTITY_AUD => identity.aud = field.string()?,
+                IDENTITY_INTERACTIVE => {
+                    let mut cluster = String::new();
+                    let mut subcluster = String::new();
+                    for field in read_fields(field.bytes()?)? {
+                        match field.number {
+                            1 => cluster = field.string()?,
+                            2 => subcluster = field.string()?,
+                            _ => {}
+                        }
+                    }
+                    identity.runtime = Some(ReplRuntime::Interactive {
+                        cluster,
+                        subcluster,
+                    });
+                }
+                IDENTITY_DEPLOYMENT => identity.runtime = Some(ReplRuntime::Deployment),
+                _ => {}
+            }
+        }
+
+        Ok(identity)
+    }
+
+    /// Encodes the identity with `aud` replaced, in field number order like
+    /// the goval and python encoders.
+    pub fn encode_with_aud(&self, aud: &str) -> Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(self.bytes.len() + aud.len());
+        let mut aud_written = false;
+        for field in read_fields(&self.bytes)? {
+            if field.number == IDENTITY_AUD {
+                continue;
+            }
+            if !aud_written && field.number > IDENTITY_AUD {
+                write_bytes(&mut out, IDENTITY_AUD, aud.as_bytes());
+                aud_written = true;
+            }
+            out.extend_from_slice(field.raw);
+        }
+        if !aud_written {
+            write_bytes(&mut out, IDENTITY_AUD, aud.as_bytes());
+        }
+
+        Ok(out)
+    }
+}
+
+// endregion: --- Messages
@@ -0,0 +1,136 @@
+// Verification of the goval identity tokens and of their signing chain.
+
+use std::collections::HashMap;
+
+use super::super::{Error, Result};
+use super::paseto::{self, Token};
+use super::protobuf::{
+    CertificateClaim, GovalCert, GovalReplIdentity, GovalSigningAuthority, ReplRuntime,
+    FLAG_ANY_CLUSTER, FLAG_ANY_SUBCLUSTER, FLAG_SIGN_INTERMEDIATE_CERT,
+};
+use crate::config::env_var;
+use crate::utils::now_utc;
+
+/// Public key of a root signing key, from its key id and issuer.
+pub type PubKeySource = dyn Fn(&str, &str) -> Result<Vec<u8>> + Send + Sync;
+
+/// Reads the root public keys from the `REPL_PUBKEYS` JSON map.
+pub fn read_public_key_from_env(key_id: &str, _issuer: &str) -> Result<Vec<u8>> {
+    let pubkeys = env_var("REPL_PUBKEYS")
+        .ok_or_else(|| Error::ReplitEnvMissing("REPL_PUBKEYS".to_string()))?;
+    let pubkeys: HashMap<String, String> = serde_json::from_str(&pubkeys)
+        .map_err(|e| Error::ReplitKeyInvalid(format!("REPL_PUBKEYS: {e}")))?;
+    let pubkey = pubkeys
+        .get(key_id)
+        .ok_or_else(|| Error::ReplitKeyInvalid(format!("unknown key id {key_id}")))?;
+
+    paseto::b64_decode(pubkey.as_bytes())
+}
+
+/// Signing authority in the footer of a token.
+pub fn get_signing_authority(token: &Token) -> Result<GovalSigningAuthority> {
+    GovalSigningAuthority::decode(&paseto::b64_decode(&token.footer)?)
+}
+
+/// Verifies the identity token and its chain, returning the identity.
+pub fn verify_identity_token(
+    identity_token: &str,
+    audience: &str,
+    pubkey_source: &PubKeySource,
+) -> Result<GovalReplIdentity> {
+    let (raw_identity, cert) = verify_chain(identity_token, pubkey_source)?;
+    let identity = GovalReplIdentity::decode(&raw_identity)?;
+
+    if identity.aud != audience {
+        return Err(Error::ReplitNotAuthorized(format!(
+            "audience, got {:?}, want {:?}",
+            identity.aud, audience
+        )));
+    }
+    let cert = cert.ok_or_else(|| {
+        Error::ReplitNotAuthorized("identity not signed by a repl cert".to_string())
+    })?;
+    let (cluster, subcluster) = match &identity.runtime {
+        Some(ReplRuntime::Interactive {
+            cluster,
+            subcluster,
+        }) => (Some(cluster.as_str()), Some(subcluster.as_str())),
+        _ => (None, None),
+    };
+    verify_claims(&cert, cluster, subcluster)?;
+
+    Ok(identity)
+}
+
+/// Verifies the token up to a root key, returning its message and the cert
+/// which signed it, `None` for a root key.
+fn verify_chain(token: &str, pubkey_source: &PubKeySource) -> Result<(Vec<u8>, Option<GovalCert>)> {
+    let token = Token::parse(token)?;
+    let authority = get_signing_authority(&token)?;
+
+    if !authority.key_id.is_empty() {
+        token.verify(&pubkey_source(&authority.key_id, &authority.issuer)?)?;
+        return Ok((paseto::b64_decode(&token.message)?, None));
+    }
+
+    if !authority.signed_cert.is_empty() {
+        let (signing_bytes, skip_level_cert) = verify_chain(&authority.signed_cert, pubkey_source)?;
+        let signing_cert = verify_cert(&signing_bytes, skip_level_cert.as_ref())?;
+        token.verify(&paseto::public_key_from_paserk(&signing_cert.public_key)?)?;
+        return Ok((paseto::b64_decode(&token.message)?, Some(signing_cert)));
+    }
+
+    Err(Error::ReplitNotAuthorized(format!(
+        "invalid signing authority: {authority:?}"
+    )))
+}
+
+fn verify_cert(encoded_cert: &[u8], signing_cert: Option<&GovalCert>) -> Result<GovalCert> {
+    let cert = GovalCert::decode(encoded_cert)?;
+    verify_claims(&cert, None, None)?;
+
+    // Certs signed by a root key need no intermediate authority.
+    if let Some(signing_cert) = signing_cert {
+        if !signing_cert.has_flag(FLAG_SIGN_INTERMEDIATE_CERT) {
+            return Err(Error::ReplitNotAuthorized(
+                "signing cert does not have authority to sign intermediate certs".to_string(),
+            ));
+        }
+    }
+
+    Ok(cert)
+}
+
+/// Checks the validity period of the cert and that it allows the runtime.
+fn verify_claims(cert: &GovalCert, cluster: Option<&str>, subcluster: Option<&str>) -> Result<()> {
+    let now = now_utc().unix_timestamp();
+    if cert.iat > now {
+        return Err(Error::ReplitNotAuthorized(format!(
+            "not valid for {}s",
+            cert.iat - now
+        )));
+    }
+    if cert.exp < now {
+        return Err(Error::ReplitNotAuthorized(format!(
+            "expired {}s ago",
+            now - cert.exp
+        )));
+    }
+
+    if let Some(cluster) = cluster {
+        let claim = CertificateClaim::Cluster(cluster.to_string());
+        if !cert.has_flag(FLAG_ANY_CLUSTER) && !cert.claims.contains(&claim) {
+            return Err(Error::ReplitNotAuthorized(format!("cluster {cluster:?}")));
+        }
+    }
+    if let Some(subcluster) = subcluster {
+        let claim = CertificateClaim::Subcluster(subcluster.to_string());
+        if !cert.has_flag(FLAG_ANY_SUBCLUSTER) && !cert.claims.contains(&claim) {
+            return Err(Error::ReplitNotAuthorized(format!(
+                "subcluster {subcluster:?}"
+            )));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use base64_url::base64::engine::general_purpose;
+use base64_url::base64::Engine as _;
+use http::header::AUTHORIZATION;
+use http::request::Parts;
+use http::{HeaderName, HeaderValue};
+
+use super::{AuthStrategy, Error, Result};
+use crate::utils::set_query_param;
+
+pub const X_API_KEY: &str = "x-api-key";
+
+/// `Authorization: Bearer <key>`
+#[derive(Debug)]
+pub struct BearerAuth;
+
+impl AuthStrategy for BearerAuth {
+    fn apply(&self, parts: &mut Parts, _body: &[u8], key: &str) -> Result<()> {
+        insert_header(parts, AUTHORIZATION, &format!("Bearer {key}"))
+    }
+}
+
+/// No key.
+#[derive(Debug)]
+pub struct NoAuth;
+
+impl AuthStrategy for NoAuth {
+    fn apply(&self, _parts: &mut Parts, _body: &[u8], _key: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// `Authorization: Basic base64(<key>)`, the key being `user:password`.
+#[derive(Debug)]
+pub struct BasicAuth;
+
+impl AuthStrategy for BasicAuth {
+    fn apply(&self, parts: &mut Parts, _body: &[u8], key: &str) -> Result<()> {
+        let credentials = general_purpose::STANDARD.encode(key.as_bytes());
+        insert_header(parts, AUTHORIZATION, &format!("Basic {credentials}"))
+    }
+}
+
+/// `Authorization: Token <key>`
+#[derive(Debug)]
+pub struct TokenAuth;
+
+impl AuthStrategy for TokenAuth {
+    fn apply(&self, parts: &mut Parts, _body: &[u8], key: &str) -> Result<()> {
+        insert_header(parts, AUTHORIZATION, &format!("Token {key}"))
+    }
+}
+
+/// `<name>: <key>`, e.g. `x-api-key`.
+#[derive(Debug)]
+pub struct HeaderAuth {
+    name: HeaderName,
+}
+
+impl HeaderAuth {
+    pub fn new(name: &str) -> Result<Self> {
+        Ok(Self {
+            name: header_name(name)?,
+        })
+    }
+}
+
+impl AuthStrategy for HeaderAuth {
+    fn apply(&self, parts: &mut Parts, _body: &[u8], key: &str) -> Result<()> {
+        insert_header(parts, self.name.clone(), key)
+    }
+}
+
+/// `?<name>=<key>`, keeping the other query params of the request.
+#[derive(Debug)]
+pub struct QueryParamAuth {
+    name: String,
+}
+
+impl QueryParamAuth {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+        }
+    }
+}
+
+impl AuthStrategy for QueryParamAuth {
+    fn apply(&self, parts: &mut Parts, _body: &[u8], key: &str) -> Result<()> {
+        parts.uri = set_query_param(&parts.uri, &self.name, key)
+            .map_err(|e| Error::InvalidUri(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+/// Fixed headers sent on every request, e.g. `anthropic-version`. The key is
+/// not used.
+#[derive(Debug)]
+pub struct StaticHeaders {
+    headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl StaticHeaders {
+    pub fn new(headers: &HashMap<String, String>) -> Result<Self> {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| Ok((header_name(name)?, header_value(value)?)))
+            .collect::<Result<_>>()?;
+
+        Ok(Self { headers })
+    }
+}
+
+impl AuthStrategy for StaticHeaders {
+    fn apply(&self, parts: &mut Parts, _body: &[u8], _key: &str) -> Result<()> {
+        for (name, value) in &self.headers {
+            parts.headers.insert(name.clone(), value.clone());
+        }
+
+        Ok(())
+    }
+}
+
+// region:    --- Helpers
+fn header_name(name: &str) -> Result<HeaderName> {
+    HeaderName::from_str(name).map_err(|_| Error::InvalidHeaderName(name.to_string()))
+}
+
+fn header_value(value: &str) -> Result<HeaderValue> {
+    HeaderValue::from_str(value).map_err(|e| Error::InvalidHeaderValue(e.to_string()))
+}
+
+fn insert_header(parts: &mut Parts, name: HeaderName, value: &str) -> Result<()> {
+    parts.headers.insert(name, header_value(value)?);
+    Ok(())
+}
+// endregion: --- Helpers
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use http::uri::PathAndQuery;
+    use http::Request;
+
+    use super::*;
+
+    #[test]
+    fn test_query_param_auth_keeps_other_params() -> Result<()> {
+        // -- Setup & Fixtures
+        let (mut fx_parts, _) = Request::get("/v1/models?alt=json&key=client&page=2")
+            .body(())?
+            .into_parts();
+
+        // -- Exec
+        QueryParamAuth::new("key").apply(&mut fx_parts, b"", "sk/1")?;
+
+        // -- Check
+        assert_eq!(
+            fx_parts.uri.path_and_query().map(PathAndQuery::as_str),
+            Some("/v1/models?alt=json&page=2&key=sk%2F1")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_static_headers_ok() -> Result<()> {
+        // -- Setup & Fixtures
+        let (mut fx_parts, _) = Request::get("/v1/messages").body(())?.into_parts();
+        let fx_headers =
+            HashMap::from([("anthropic-version".to_string(), "2023-06-01".to_string())]);
+
+        // -- Exec
+        HeaderAuth::new(X_API_KEY)?.apply(&mut fx_parts, b"", "sk-1")?;
+        StaticHeaders::new(&fx_headers)?.apply(&mut fx_parts, b"", "sk-1")?;
+
+        // -- Check
+        assert_eq!(fx_parts.headers[X_API_KEY], "sk-1");
+        assert_eq!(fx_parts.headers["anthropic-version"], "2023-06-01");
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
@@ -0,0 +1,87 @@
+// Commands run instead of the server, for checking the config offline.
+
+use anyhow::{anyhow, bail, Context};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method};
+
+use crate::pricing::{ExprPricing, PricedRequest, PricingExpr, Scope};
+use crate::Result;
+
+const USAGE: &str = "Usage: matador price-expr <expr> [--method <method>] [--path <path>] \
+[--header '<name>: <value>']... [--body <file>] [--vocab <file>]";
+
+/// Commands run instead of the server, any other arguments are left to it.
+const COMMANDS: &[&str] = &["price-expr", "help"];
+
+pub fn is_command(arg: &str) -> bool {
+    COMMANDS.contains(&arg)
+}
+
+pub async fn run(args: &[String]) -> Result<()> {
+    match args.first().map(String::as_str) {
+        Some("price-expr") => price_expr(&args[1..]).await,
+        Some("help") => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        _ => bail!("Unknown command\n{USAGE}"),
+    }
+}
+
+/// Evaluates a pricing expression against a sample request, printing its
+/// value and the price it charges.
+async fn price_expr(args: &[String]) -> Result<()> {
+    let mut expr = None;
+    let mut method = Method::POST;
+    let mut path = "/".to_string();
+    let mut headers = HeaderMap::new();
+    let mut body = Vec::new();
+    let mut vocab = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| anyhow!("Missing value of {arg}\n{USAGE}"))
+        };
+        match arg.as_str() {
+            "--method" => method = value()?.parse().context("Invalid method")?,
+            "--path" => path = value()?.to_string(),
+            "--header" => {
+                let header = value()?;
+                let (name, header_value) = header.split_once(':').ok_or_else(|| {
+                    anyhow!("Invalid header {header}, expected '<name>: <value>'")
+                })?;
+                headers.append(
+                    HeaderName::try_from(name.trim())?,
+                    HeaderValue::try_from(header_value.trim())?,
+                );
+            }
+            "--body" => {
+                let file = value()?;
+                body = std::fs::read(file).with_context(|| format!("Cannot read {file}"))?;
+            }
+            "--vocab" => vocab = Some(value()?.to_string()),
+            _ if expr.is_none() && !arg.starts_with("--") => expr = Some(arg.as_str()),
+            _ => bail!("Unexpected argument {arg}\n{USAGE}"),
+        }
+    }
+    let expr = expr.ok_or_else(|| anyhow!("Missing expression\n{USAGE}"))?;
+
+    let pricing = ExprPricing {
+        expr: PricingExpr::parse(expr)?,
+        vocab,
+    };
+    pricing.load_vocab()?;
+    let req = PricedRequest {
+        method: &method,
+        path: &path,
+        headers: &headers,
+        body: &body,
+    };
+    let scope = Scope::new(&req, pricing.vocab.as_deref()).await;
+
+    println!("value: {}", pricing.expr.eval(&scope)?);
+    println!("price_msat: {}", pricing.expr.price_msat(&scope)?);
+
+    Ok(())
+}
@@ -1,420 +1,686 @@
-use std::collections::HashMap;
-use std::env;
-use std::process::Command;
-use std::str::FromStr;
-use std::sync::Mutex;
-
-use http::{HeaderName, HeaderValue, Request};
-use lazy_static::lazy_static;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use arc_swap::ArcSwap;
 use once_cell::sync::Lazy;
-use serde_json::Value;
-use time::OffsetDateTime;
-use tracing::{debug, info};
-
-use super::replit::{get_optional_replit, ReplitApiParams};
-use crate::config::config::Config;
-use crate::config::get_optional_env;
-use crate::utils::{
-    add_key_query_param, insert_auth_basic_header, insert_auth_bearer_header,
-    insert_auth_token_header, insert_x_api_key_header,
+use serde::Deserialize;
+use tracing::info;
+
+use super::azure::AzureParams;
+use super::replit::replit_provider;
+use crate::auth::{
+    self, CredentialCache, CredentialParams, KeyPool, KeySelection, PooledKey, StaticCredential,
 };
+use crate::config::{env_var, get_optional_env};
+use crate::policy::PolicyParams;
+use crate::pricing::PricingParams;
+use crate::translate::ApiFormat;
+
+/// Default providers, overridden by the file at `PROVIDERS_CONFIG_PATH`.
+const DEFAULT_PROVIDERS: &str = include_str!("../../providers.toml");
+/// OpenAI chat completions, also served by matador for all the models.
+pub const CHAT_COMPLETIONS_PATH: &str = "/v1/chat/completions";
+/// First segment of the unified endpoint, routed by model.
+pub const UNIFIED_ROUTE: &str = "v1";
+/// First segments of the unified, quote, L402 and admin endpoints, not usable
+/// by the providers.
+const RESERVED_PATHS: [&str; 4] = ["/v1", "/quote", "/l402", "/admin"];
+
+/// Providers and fallbacks are routed on the first path segment only, so their
+/// path is a single `/<segment>`.
+fn validate_route_path(path: &str) -> Result<()> {
+    let segment = path
+        .strip_prefix('/')
+        .ok_or_else(|| anyhow!("path must start with '/'"))?;
+    let valid = !segment.is_empty()
+        && segment
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(anyhow!(
+            "path {path} must be a single segment of letters, digits, '-', '_' or '.'"
+        ));
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Scheme {
+    Http,
+    #[default]
+    Https,
+    /// Unix domain socket at `socket`, for local servers.
+    Unix,
+}
+
+/// How the operator key is injected in the forwarded request.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthKind {
+    #[default]
+    Bearer,
+    Basic,
+    Token,
+    XApiKey,
+    Header,
+    QueryParam,
+    /// Self hosted servers without a key.
+    None,
+    /// AWS Signature Version 4, the key being `<access key id>:<secret>`,
+    /// optionally followed by `:<session token>`.
+    AwsSigv4,
+}
+
+/// How long requests wait on a rate limited provider.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct RateLimitParams {
+    /// Longest wait for a key to leave its rate limit before the request is
+    /// shed, before it is charged.
+    pub queue_ms: u64,
+    /// Deadline of the retries of a request answered with a 429.
+    pub retry_deadline_ms: u64,
+}
+
+impl Default for RateLimitParams {
+    fn default() -> Self {
+        Self {
+            queue_ms: 5_000,
+            retry_deadline_ms: 20_000,
+        }
+    }
+}
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default, Deserialize)]
 pub struct ApiParams {
-    pub key: String,
-    pub host: &'static str,
-    pub path: &'static str,
-    pub timeout: Option<i64>,
+    pub name: String,
+    pub path: String,
+    #[serde(default)]
+    pub host: String,
+    #[serde(default)]
+    pub scheme: Scheme,
+    pub port: Option<u16>,
+    /// Prefix of the forwarded paths, e.g. `/api` for `/ollama/chat` to reach
+    /// `/api/chat`.
+    pub base_path: Option<String>,
+    /// Path of the socket of a `unix` upstream.
+    pub socket: Option<String>,
+    pub auth: AuthKind,
+    pub auth_header: Option<String>,
+    pub auth_query_param: Option<String>,
+    pub aws_region: Option<String>,
+    /// Signing name of the AWS service, `bedrock` by default.
+    pub aws_service: Option<String>,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    pub key_env: Option<String>,
+    pub key_file: Option<String>,
+    pub credentials: Option<CredentialParams>,
+    /// More keys, load balanced with the one above.
+    #[serde(default)]
+    pub keys: Vec<CredentialParams>,
+    #[serde(default)]
+    pub key_selection: KeySelection,
+    pub azure: Option<AzureParams>,
+    #[serde(default)]
+    pub rate_limit: RateLimitParams,
+    #[serde(default)]
+    pub pricing: PricingParams,
+    #[serde(default)]
+    pub policy: PolicyParams,
+
+    /// Shared by the clones of the params, so refreshed keys and usage
+    /// counters are seen by all the requests.
+    #[serde(skip)]
+    pub key_pool: Option<Arc<KeyPool>>,
 }
 
 impl ApiParams {
     pub fn new() -> Self {
-        Self {
-            key: "".to_string(),
-            host: "",
-            path: "",
-            timeout: None,
-        }
+        Self::default()
     }
 
-    pub fn key(mut self, key: String) -> Self {
-        self.key = key;
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
         self
     }
 
-    pub fn set_key(&mut self, key: String) {
-        self.key = key;
+    pub fn host(mut self, host: &str) -> Self {
+        self.host = host.to_string();
+        self
     }
 
-    pub fn host(mut self, host: &'static str) -> Self {
-        self.host = host;
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = path.to_string();
         self
     }
 
-    pub fn path(mut self, path: &'static str) -> Self {
-        self.path = path;
+    pub fn auth(mut self, auth: AuthKind) -> Self {
+        self.auth = auth;
         self
     }
 
-    pub fn timeout(mut self, timeout: Option<i64>) -> Self {
-        self.timeout = timeout;
+    pub fn credentials(mut self, credentials: CredentialParams) -> Self {
+        self.credentials = Some(credentials);
         self
     }
 
-    pub fn is_expired(&self) -> bool {
-        match self.timeout {
-            Some(timeout) => {
-                let now = OffsetDateTime::now_utc().unix_timestamp();
-                now > timeout
-            }
-            None => false,
-        }
+    /// Pool of the operator keys, set once the config is loaded.
+    pub fn key_pool(&self) -> Result<&KeyPool, auth::Error> {
+        self.key_pool.as_deref().ok_or_else(|| {
+            auth::Error::CredentialConfig(format!("provider {}: no credentials", self.name))
+        })
     }
-}
 
-#[derive(Debug)]
-pub struct ApisConfig {
-    pub openai: Option<ApiParams>,
-    pub clipdrop: Option<ApiParams>,
-    pub palm: Option<ApiParams>,
-    pub replicate: Option<ApiParams>,
-    pub anthropic: Option<ApiParams>,
-    pub stability: Option<ApiParams>,
-    pub goose: Option<ApiParams>,
-    pub cohere: Option<ApiParams>,
-    pub ai21: Option<ApiParams>,
-    pub together: Option<ApiParams>,
-    pub scenario: Option<ApiParams>,
-    pub perplexity: Option<ApiParams>,
-    pub anyscale: Option<ApiParams>,
-    pub replit: Option<ReplitApiParams>,
-    pub bing: Option<ApiParams>,
-}
+    /// `credentials`, or the key from `key_env` or `key_file`, then `keys`.
+    fn credential_params(&self) -> Vec<CredentialParams> {
+        let key = if let Some(credentials) = &self.credentials {
+            Some(credentials.clone())
+        } else if let Some(name) = self.key_env.as_ref().filter(|name| env_var(name).is_some()) {
+            Some(CredentialParams::Env { name: name.clone() })
+        } else {
+            self.key_file.as_ref().map(|path| CredentialParams::File {
+                path: path.clone(),
+                refresh_sec: None,
+            })
+        };
+
+        key.into_iter().chain(self.keys.iter().cloned()).collect()
+    }
 
-impl ApisConfig {
-    pub fn get_params(&self, route: &str) -> Option<ApiParams> {
-        match route {
-            "openai" => self.openai.clone(),
-            "clipdrop" => self.clipdrop.clone(),
-            "palm" => self.palm.clone(),
-            "replicate" => self.replicate.clone(),
-            "anthropic" => self.anthropic.clone(),
-            "stability" => self.stability.clone(),
-            "goose" => self.goose.clone(),
-            "cohere" => self.cohere.clone(),
-            "ai21" => self.ai21.clone(),
-            "together" => self.together.clone(),
-            "scenario" => self.scenario.clone(),
-            "perplexity" => self.perplexity.clone(),
-            "anyscale" => self.anyscale.clone(),
-            "bing" => self.bing.clone(),
-            _ => None,
+    /// Host and port of the upstream.
+    pub fn authority(&self) -> String {
+        match self.port {
+            Some(port) => format!("{}:{}", self.host, port),
+            None => self.host.clone(),
         }
     }
 
-    pub fn get_params_per_api_keys_set(&self) -> Vec<ApiParams> {
-        let api_params = [
-            &self.openai,
-            &self.clipdrop,
-            &self.palm,
-            &self.replicate,
-            &self.anthropic,
-            &self.stability,
-            &self.goose,
-            &self.cohere,
-            &self.ai21,
-            &self.together,
-            &self.scenario,
-            &self.perplexity,
-            &self.anyscale,
-            &self.replit.clone().map(|replit| replit.params),
-            &self.bing,
-        ];
-
-        api_params
-            .iter()
-            .filter_map(|api_param| api_param.as_ref().cloned())
-            .collect()
+    /// Pool of the keys available in the environment, `None` if there are none.
+    fn build_key_pool(&self) -> Result<Option<KeyPool>> {
+        let mut keys = Vec::new();
+        if self.auth == AuthKind::None {
+            let credential = CredentialCache::new(Box::new(StaticCredential(String::new())));
+            keys.push(PooledKey::new(
+                "none".to_string(),
+                "none".to_string(),
+                credential,
+            ));
+        }
+        for params in self.credential_params() {
+            let credential = params
+                .provider()
+                .map_err(|e| anyhow!("provider {}: {}", self.name, e))?;
+            if let Some(credential) = credential {
+                keys.push(PooledKey::new(
+                    params.id(),
+                    params.fingerprint(),
+                    CredentialCache::new(credential),
+                ));
+            }
+        }
+
+        Ok((!keys.is_empty()).then(|| KeyPool::new(keys, self.key_selection)))
+    }
+
+    fn validate(&self) -> Result<()> {
+        validate_route_path(&self.path).map_err(|e| anyhow!("provider {}: {}", self.name, e))?;
+        match self.scheme {
+            Scheme::Unix if self.socket.is_none() => {
+                return Err(anyhow!(
+                    "provider {}: unix scheme needs a socket",
+                    self.name
+                ));
+            }
+            Scheme::Http | Scheme::Https if self.host.is_empty() => {
+                return Err(anyhow!("provider {}: host missing", self.name));
+            }
+            _ => (),
+        }
+        if let Some(base_path) = &self.base_path {
+            if !base_path.starts_with('/') {
+                return Err(anyhow!(
+                    "provider {}: base_path must start with '/'",
+                    self.name
+                ));
+            }
+        }
+        auth::strategies(self).map_err(|e| anyhow!("provider {}: {}", self.name, e))?;
+        self.pricing
+            .validate()
+            .map_err(|e| anyhow!("provider {}: {}", self.name, e))?;
+        self.policy
+            .validate()
+            .map_err(|e| anyhow!("provider {}: {}", self.name, e))?;
+
+        Ok(())
     }
 }
 
-pub static APIS_CONFIG: Lazy<ApisConfig> = Lazy::new(|| {
-    dotenv::dotenv().ok();
-    ApisConfigBuilder::new()
-        .openai()
-        .clipdrop()
-        .palm()
-        .replicate()
-        .anthropic()
-        .stability()
-        .goose()
-        .cohere()
-        .ai21()
-        .together()
-        .scenario()
-        .perplexity()
-        .anyscale()
-        .replit()
-        .bing()
-        .build()
-});
+/// Provider tried by a fallback route, at the path of its OpenAI compatible
+/// chat completions.
+#[derive(Clone, Debug, Deserialize)]
+pub struct FallbackTarget {
+    pub provider: String,
+    #[serde(default = "default_chat_completions_path")]
+    pub path: String,
+    /// Replaces the `model` of the request, the model names differ across
+    /// providers.
+    pub model: Option<String>,
+}
 
-pub fn apis_config() -> &'static ApisConfig {
-    &APIS_CONFIG
+fn default_chat_completions_path() -> String {
+    CHAT_COMPLETIONS_PATH.to_string()
 }
 
-#[derive(Debug)]
-pub struct ApisConfigBuilder {
-    openai: Option<ApiParams>,
-    clipdrop: Option<ApiParams>,
-    palm: Option<ApiParams>,
-    replicate: Option<ApiParams>,
-    anthropic: Option<ApiParams>,
-    stability: Option<ApiParams>,
-    goose: Option<ApiParams>,
-    cohere: Option<ApiParams>,
-    ai21: Option<ApiParams>,
-    together: Option<ApiParams>,
-    scenario: Option<ApiParams>,
-    perplexity: Option<ApiParams>,
-    anyscale: Option<ApiParams>,
-    replit: Option<ReplitApiParams>,
-    bing: Option<ApiParams>,
+/// Virtual route forwarding a chat completion to its first target, and to the
+/// next ones while they fail.
+#[derive(Clone, Debug, Deserialize)]
+pub struct FallbackParams {
+    pub name: String,
+    pub path: String,
+    pub targets: Vec<FallbackTarget>,
+    #[serde(default)]
+    pub pricing: PricingParams,
 }
 
-impl ApisConfigBuilder {
-    pub fn new() -> Self {
-        Self {
-            openai: None,
-            clipdrop: None,
-            palm: None,
-            replicate: None,
-            anthropic: None,
-            stability: None,
-            goose: None,
-            cohere: None,
-            ai21: None,
-            together: None,
-            scenario: None,
-            perplexity: None,
-            anyscale: None,
-            replit: None,
-            bing: None,
-        }
-    }
+/// Provider of the models matching `pattern` on the unified endpoint, either
+/// a model name or a prefix ending with `*`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ModelRoute {
+    pub pattern: String,
+    pub provider: String,
+    #[serde(default)]
+    pub format: ApiFormat,
+    /// Overrides the chat path of the format.
+    pub path: Option<String>,
+}
 
-    pub fn openai(mut self) -> Self {
-        if let Some(key) = get_optional_env("OPENAI_API_KEY") {
-            self.openai = Some(
-                ApiParams::new()
-                    .key(key)
-                    .host("api.openai.com")
-                    .path("/openai")
-                    .timeout(None),
-            );
+impl ModelRoute {
+    pub fn matches(&self, model: &str) -> bool {
+        match self.pattern.strip_suffix('*') {
+            Some(prefix) => model.starts_with(prefix),
+            None => model == self.pattern,
         }
-        self
     }
+}
 
-    pub fn clipdrop(mut self) -> Self {
-        if let Some(key) = get_optional_env("CLIPDROP_API_KEY") {
-            self.clipdrop = Some(
-                ApiParams::new()
-                    .key(key)
-                    .host("clipdrop-api.co")
-                    .path("/clipdrop")
-                    .timeout(None),
-            );
+#[derive(Debug, Deserialize)]
+struct ProvidersFile {
+    providers: Vec<ApiParams>,
+    #[serde(default)]
+    fallbacks: Vec<FallbackParams>,
+    #[serde(default)]
+    models: Vec<ModelRoute>,
+}
+
+#[derive(Debug)]
+pub struct ApisConfig {
+    pub providers: Vec<ApiParams>,
+    pub fallbacks: Vec<FallbackParams>,
+    pub models: Vec<ModelRoute>,
+}
+
+impl ApisConfig {
+    /// Loads the providers file and keeps the providers which have a key.
+    ///
+    /// The unchanged keys of the `previous` providers are kept with their
+    /// usage and cooldowns.
+    pub fn load(previous: Option<&ApisConfig>) -> Result<Self> {
+        let file = match get_optional_env("PROVIDERS_CONFIG_PATH") {
+            Some(path) => {
+                info!("Loading providers from {}", path);
+                let content = fs::read_to_string(&path).map_err(|e| anyhow!("{}: {}", path, e))?;
+                Self::parse_providers(&content, replit_provider())
+                    .map_err(|e| anyhow!("{}: {}", path, e))?
+            }
+            None => Self::parse_providers(DEFAULT_PROVIDERS, replit_provider())?,
+        };
+
+        let mut keyed_providers = Vec::new();
+        for mut provider in file.providers {
+            match provider.build_key_pool()? {
+                Some(mut key_pool) => {
+                    let previous_key_pool = previous
+                        .and_then(|previous| previous.get_provider(&provider.name))
+                        .and_then(|previous| previous.key_pool.as_deref());
+                    if let Some(previous_key_pool) = previous_key_pool {
+                        key_pool = key_pool.carry_over(previous_key_pool);
+                    }
+                    provider.key_pool = Some(Arc::new(key_pool));
+                    keyed_providers.push(provider);
+                }
+                None => info!("No key set for provider {}, skipping", provider.name),
+            }
         }
-        self
-    }
 
-    pub fn palm(mut self) -> Self {
-        if let Some(key) = get_optional_env("PALM_API_KEY") {
-            self.palm = Some(
-                ApiParams::new()
-                    .key(key)
-                    .host("generativelanguage.googleapis.com")
-                    .path("/palm")
-                    .timeout(None),
-            );
+        // Targets without a key are left out, as their provider.
+        let mut fallbacks = Vec::new();
+        for mut fallback in file.fallbacks {
+            fallback.targets.retain(|target| {
+                keyed_providers
+                    .iter()
+                    .any(|provider| provider.name == target.provider)
+            });
+            match fallback.targets.is_empty() {
+                true => info!("No provider set for fallback {}, skipping", fallback.name),
+                false => fallbacks.push(fallback),
+            }
         }
-        self
+
+        let mut models = file.models;
+        models.retain(|route| {
+            keyed_providers
+                .iter()
+                .any(|provider| provider.name == route.provider)
+        });
+
+        Ok(Self {
+            providers: keyed_providers,
+            fallbacks,
+            models,
+        })
     }
 
-    pub fn replicate(mut self) -> Self {
-        if let Some(key) = get_optional_env("REPLICATE_API_KEY") {
-            self.replicate = Some(
-                ApiParams::new()
-                    .key(key)
-                    .host("api.replicate.com")
-                    .path("/replicate")
-                    .timeout(None),
-            );
+    /// Parses the providers file, with the `builtin` provider added before the
+    /// checks, so it cannot clash with a provider of the file either.
+    fn parse_providers(content: &str, builtin: Option<ApiParams>) -> Result<ProvidersFile> {
+        let mut file: ProvidersFile = toml::from_str(content)?;
+        file.providers.extend(builtin);
+
+        let mut names = HashSet::new();
+        let mut paths = HashSet::new();
+        let reserved_paths = RESERVED_PATHS.map(String::from);
+        paths.extend(&reserved_paths);
+        for provider in &file.providers {
+            provider.validate()?;
+            if !names.insert(&provider.name) || !paths.insert(&provider.path) {
+                return Err(anyhow!(
+                    "provider {}: duplicate name or path",
+                    provider.name
+                ));
+            }
         }
-        self
-    }
 
-    pub fn anthropic(mut self) -> Self {
-        if let Some(key) = get_optional_env("ANTHROPIC_API_KEY") {
-            self.anthropic = Some(
-                ApiParams::new()
-                    .key(key)
-                    .host("api.anthropic.com")
-                    .path("/anthropic")
-                    .timeout(None),
-            );
+        for fallback in &file.fallbacks {
+            validate_route_path(&fallback.path)
+                .map_err(|e| anyhow!("fallback {}: {}", fallback.name, e))?;
+            if !names.insert(&fallback.name) || !paths.insert(&fallback.path) {
+                return Err(anyhow!(
+                    "fallback {}: duplicate name or path",
+                    fallback.name
+                ));
+            }
+            if fallback.targets.is_empty() {
+                return Err(anyhow!("fallback {}: no targets", fallback.name));
+            }
+            fallback
+                .pricing
+                .validate()
+                .map_err(|e| anyhow!("fallback {}: {}", fallback.name, e))?;
+            for target in &fallback.targets {
+                if !target.path.starts_with('/') {
+                    return Err(anyhow!(
+                        "fallback {}: target path must start with '/'",
+                        fallback.name
+                    ));
+                }
+                if !file.providers.iter().any(|p| p.name == target.provider) {
+                    return Err(anyhow!(
+                        "fallback {}: unknown provider {}",
+                        fallback.name,
+                        target.provider
+                    ));
+                }
+            }
         }
-        self
-    }
 
-    pub fn stability(mut self) -> Self {
-        if let Some(key) = get_optional_env("STABILITY_API_KEY") {
-            self.stability = Some(
-                ApiParams::new()
-                    .key(key)
-                    .host("api.stability.ai")
-                    .path("/stability")
-                    .timeout(None),
-            );
+        for route in &file.models {
+            if !file.providers.iter().any(|p| p.name == route.provider) {
+                return Err(anyhow!(
+                    "model {}: unknown provider {}",
+                    route.pattern,
+                    route.provider
+                ));
+            }
         }
-        self
+
+        Ok(file)
     }
 
-    pub fn goose(mut self) -> Self {
-        if let Some(key) = get_optional_env("GOOSE_API_KEY") {
-            self.goose = Some(
-                ApiParams::new()
-                    .key(key)
-                    .host("api.goose.ai")
-                    .path("/goose")
-                    .timeout(None),
-            );
-        }
-        self
+    /// Params of the provider named `name`.
+    pub fn get_provider(&self, name: &str) -> Option<&ApiParams> {
+        self.providers.iter().find(|provider| provider.name == name)
     }
 
-    pub fn cohere(mut self) -> Self {
-        if let Some(key) = get_optional_env("COHERE_API_KEY") {
-            self.cohere = Some(
-                ApiParams::new()
-                    .key(key)
-                    .host("api.cohere.ai")
-                    .path("/cohere")
-                    .timeout(None),
-            );
-        }
-        self
+    /// Params of the provider served under `/<route>`.
+    pub fn get_params(&self, route: &str) -> Option<ApiParams> {
+        self.providers
+            .iter()
+            .find(|provider| provider.path.trim_start_matches('/') == route)
+            .cloned()
     }
 
-    pub fn ai21(mut self) -> Self {
-        if let Some(key) = get_optional_env("AI21_API_KEY") {
-            self.ai21 = Some(
-                ApiParams::new()
-                    .key(key)
-                    .host("api.ai21.com")
-                    .path("/ai21")
-                    .timeout(None),
-            );
-        }
-        self
+    /// Params of the provider serving `model` on the unified endpoint, the
+    /// first matching route with a keyed provider, as `UnifiedService` picks.
+    pub fn get_model_provider(&self, model: &str) -> Option<ApiParams> {
+        self.models
+            .iter()
+            .filter(|route| route.matches(model))
+            .find_map(|route| self.get_provider(&route.provider))
+            .cloned()
     }
 
-    pub fn together(mut self) -> Self {
-        if let Some(key) = get_optional_env("TOGETHER_API_KEY") {
-            self.together = Some(
-                ApiParams::new()
-                    .key(key)
-                    .host("api.together.xyz")
-                    .path("/together")
-                    .timeout(None),
-            );
-        }
-        self
+    /// Params of the fallback route served under `/<route>`.
+    pub fn get_fallback(&self, route: &str) -> Option<FallbackParams> {
+        self.fallbacks
+            .iter()
+            .find(|fallback| fallback.path.trim_start_matches('/') == route)
+            .cloned()
     }
+}
 
-    pub fn scenario(mut self) -> Self {
-        if let Some(key) = get_optional_env("SCENARIO_API_KEY") {
-            self.scenario = Some(
-                ApiParams::new()
-                    .key(key)
-                    .host("api.cloud.scenario.gg")
-                    .path("/scenario")
-                    .timeout(None),
-            );
-        }
+pub static APIS_CONFIG: Lazy<ArcSwap<ApisConfig>> = Lazy::new(|| {
+    info!("Building ApisConfig");
+    let apis_config = ApisConfig::load(None)
+        .unwrap_or_else(|ex| panic!("FATAL - WHILE LOADING PROVIDERS - Cause: {ex:?}"));
+    ArcSwap::from_pointee(apis_config)
+});
 
-        self
-    }
+pub fn apis_config() -> Arc<ApisConfig> {
+    APIS_CONFIG.load_full()
+}
 
-    pub fn perplexity(mut self) -> Self {
-        if let Some(key) = get_optional_env("PERPLEXITY_API_KEY") {
-            self.perplexity = Some(
-                ApiParams::new()
-                    .key(key)
-                    .host("api.perplexity.ai")
-                    .path("/perplexity")
-                    .timeout(None),
-            );
-        }
+/// Replaces the providers, requests in flight keep the ones they started with.
+pub fn swap_apis_config(apis_config: ApisConfig) {
+    APIS_CONFIG.store(Arc::new(apis_config));
+}
 
-        self
-    }
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
 
-    pub fn anyscale(mut self) -> Self {
-        if let Some(key) = get_optional_env("ANYSCALE_API_KEY") {
-            self.anyscale = Some(
-                ApiParams::new()
-                    .key(key)
-                    .host("api.endpoints.anyscale.com")
-                    .path("/anyscale")
-                    .timeout(None),
-            );
-        }
+    use super::*;
 
-        self
+    #[test]
+    fn test_parse_default_providers_ok() -> Result<()> {
+        // -- Exec
+        let file = ApisConfig::parse_providers(DEFAULT_PROVIDERS, None)?;
+
+        // -- Check
+        let anthropic = file
+            .providers
+            .iter()
+            .find(|provider| provider.name == "anthropic")
+            .ok_or(anyhow!("anthropic not found"))?;
+        assert_eq!(anthropic.auth, AuthKind::XApiKey);
+        assert_eq!(anthropic.headers["anthropic-version"], "2023-06-01");
+        let chat = &file.fallbacks[0];
+        assert_eq!(chat.targets[0].provider, "together");
+        assert_eq!(chat.targets[0].path, "/v1/chat/completions");
+        let claude = file
+            .models
+            .iter()
+            .find(|route| route.matches("claude-2.1"))
+            .ok_or(anyhow!("claude route not found"))?;
+        assert_eq!(claude.format, ApiFormat::Anthropic);
+
+        Ok(())
     }
 
-    pub fn replit(mut self) -> Self {
-        if let Some(replit) = get_optional_replit() {
-            self.replit = Some(replit);
-        }
+    #[test]
+    fn test_parse_providers_err_duplicate() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_content = r#"
+            [[providers]]
+            name = "openai"
+            path = "/openai"
+            host = "api.openai.com"
+            auth = "bearer"
+
+            [[providers]]
+            name = "openai-2"
+            path = "/openai"
+            host = "api.openai.com"
+            auth = "bearer"
+        "#;
+
+        // -- Exec
+        let res = ApisConfig::parse_providers(fx_content, None);
+
+        // -- Check
+        assert!(res.is_err());
+
+        Ok(())
+    }
 
-        self
+    #[test]
+    fn test_parse_providers_err_builtin_clash() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_content = r#"
+            [[providers]]
+            name = "replit"
+            path = "/modelfarm"
+            host = "example.com"
+            auth = "bearer"
+        "#;
+        let fx_builtin = ApiParams::new()
+            .name("replit")
+            .host("production-modelfarm.replit.com")
+            .path("/replit");
+
+        // -- Exec
+        let res = ApisConfig::parse_providers(fx_content, Some(fx_builtin));
+
+        // -- Check
+        assert!(res.is_err());
+
+        Ok(())
     }
 
-    pub fn bing(mut self) -> Self {
-        if let Some(key) = get_optional_env("BING_API_KEY") {
-            self.bing = Some(
-                ApiParams::new()
-                    .key(key)
-                    .host("api.bing.microsoft.com")
-                    .path("/bing")
-                    .timeout(None),
+    #[test]
+    fn test_parse_providers_err_path() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_provider = |path: &str| {
+            format!(
+                r#"
+                [[providers]]
+                name = "openai"
+                path = "{path}"
+                host = "api.openai.com"
+                auth = "bearer"
+                "#
+            )
+        };
+
+        // -- Exec & Check
+        assert!(ApisConfig::parse_providers(&fx_provider("/openai"), None).is_ok());
+        for fx_path in ["/foo/bar", "/", "openai", "/l402", "/admin", "/open%20ai"] {
+            assert!(
+                ApisConfig::parse_providers(&fx_provider(fx_path), None).is_err(),
+                "{fx_path} should be rejected"
             );
         }
 
-        self
+        Ok(())
     }
 
-    pub fn build(self) -> ApisConfig {
-        info!("Building ApisConfig");
-        ApisConfig {
-            openai: self.openai,
-            clipdrop: self.clipdrop,
-            palm: self.palm,
-            replicate: self.replicate,
-            anthropic: self.anthropic,
-            stability: self.stability,
-            goose: self.goose,
-            cohere: self.cohere,
-            ai21: self.ai21,
-            together: self.together,
-            scenario: self.scenario,
-            perplexity: self.perplexity,
-            anyscale: self.anyscale,
-            replit: self.replit,
-            bing: self.bing,
-        }
+    #[test]
+    fn test_get_model_provider_ok() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_content = r#"
+            [[providers]]
+            name = "openai"
+            path = "/openai"
+            host = "api.openai.com"
+            auth = "bearer"
+            pricing = { price_msat = 3000 }
+
+            [[providers]]
+            name = "together"
+            path = "/together"
+            host = "api.together.xyz"
+            auth = "bearer"
+
+            [[models]]
+            pattern = "gpt-*"
+            provider = "openai"
+
+            [[models]]
+            pattern = "*"
+            provider = "together"
+        "#;
+        let file = ApisConfig::parse_providers(fx_content, None)?;
+        let fx_apis_config = ApisConfig {
+            providers: file.providers,
+            fallbacks: file.fallbacks,
+            models: file.models,
+        };
+
+        // -- Exec
+        let gpt = fx_apis_config
+            .get_model_provider("gpt-4")
+            .ok_or(anyhow!("gpt-4 not routed"))?;
+        let mixtral = fx_apis_config
+            .get_model_provider("mixtral-8x7b-instruct")
+            .ok_or(anyhow!("mixtral not routed"))?;
+
+        // -- Check
+        assert_eq!(gpt.name, "openai");
+        assert_eq!(gpt.pricing.price_msat, 3000);
+        assert_eq!(mixtral.name, "together");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_providers_err_fallback_unknown_provider() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_content = r#"
+            [[providers]]
+            name = "openai"
+            path = "/openai"
+            host = "api.openai.com"
+            auth = "bearer"
+
+            [[fallbacks]]
+            name = "chat"
+            path = "/chat"
+            targets = [{ provider = "openai" }, { provider = "together" }]
+        "#;
+
+        // -- Exec
+        let res = ApisConfig::parse_providers(fx_content, None);
+
+        // -- Check
+        assert!(res.is_err());
+
+        Ok(())
     }
 }
+// endregion: --- Tests
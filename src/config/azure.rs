@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+/// Azure OpenAI, reached with OpenAI paths mapped to the deployment of the
+/// requested model.
+#[derive(Clone, Debug, Deserialize)]
+pub struct AzureParams {
+    pub api_version: String,
+    /// Deployment of each model, the model name being used when missing.
+    #[serde(default)]
+    pub deployments: HashMap<String, String>,
+}
+
+impl AzureParams {
+    /// Azure path of an OpenAI path, e.g. `/v1/chat/completions` for `gpt-4`
+    /// to `/openai/deployments/<deployment>/chat/completions`. Requests
+    /// without a model, e.g. `/v1/models`, go to the resource itself.
+    pub fn path(&self, path: &str, model: Option<&str>) -> String {
+        let path = path.strip_prefix("/v1").unwrap_or(path);
+        match model {
+            Some(model) => {
+                let deployment = self.deployments.get(model).map_or(model, String::as_str);
+                format!("/openai/deployments/{deployment}{path}")
+            }
+            None => format!("/openai{path}"),
+        }
+    }
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_azure_path() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_azure = AzureParams {
+            api_version: "2023-12-01-preview".to_string(),
+            deployments: HashMap::from([("gpt-4".to_string(), "gpt4-prod".to_string())]),
+        };
+
+        // -- Exec & Check
+        assert_eq!(
+            fx_azure.path("/v1/chat/completions", Some("gpt-4")),
+            "/openai/deployments/gpt4-prod/chat/completions"
+        );
+        assert_eq!(
+            fx_azure.path("/embeddings", Some("text-embedding-ada-002")),
+            "/openai/deployments/text-embedding-ada-002/embeddings"
+        );
+        assert_eq!(fx_azure.path("/v1/models", None), "/openai/models");
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
@@ -0,0 +1,75 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use arc_swap::ArcSwap;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::config::products::{Product, ProductsConfig};
+use crate::lightning::{check_services, Caveat};
+
+/// A bundle product: one invoice for a fixed number of requests.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BundleParams {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Services covered by the bundle, every service if empty.
+    #[serde(default)]
+    pub services: Vec<String>,
+    pub uses: i64,
+    pub price_msat: u64,
+}
+
+impl Product for BundleParams {
+    const KIND: &'static str = "bundle";
+    const KINDS: &'static str = "bundles";
+    const PATH_ENV: &'static str = "BUNDLES_CONFIG_PATH";
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn price_msat(&self) -> u64 {
+        self.price_msat
+    }
+
+    fn caveats(&self) -> Vec<Caveat> {
+        let mut caveats = vec![Caveat::Bundle(self.id.clone()), Caveat::Uses(self.uses)];
+        if !self.services.is_empty() {
+            caveats.push(Caveat::Services(self.services.clone()));
+        }
+
+        caveats
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.uses <= 0 {
+            return Err(anyhow!("uses must be positive"));
+        }
+
+        check_services(&self.services).map_err(|e| anyhow!(e))
+    }
+
+    fn config() -> Arc<BundlesConfig> {
+        bundles_config()
+    }
+}
+
+pub type BundlesConfig = ProductsConfig<BundleParams>;
+
+static BUNDLES_CONFIG: Lazy<ArcSwap<BundlesConfig>> = Lazy::new(|| {
+    let bundles_config = BundlesConfig::load_from_env()
+        .unwrap_or_else(|ex| panic!("FATAL - WHILE LOADING BUNDLES - Cause: {ex:?}"));
+    ArcSwap::from_pointee(bundles_config)
+});
+
+pub fn bundles_config() -> Arc<BundlesConfig> {
+    BUNDLES_CONFIG.load_full()
+}
+
+/// Replaces the bundles, tokens already sold keep their caveats.
+pub fn swap_bundles_config(bundles_config: BundlesConfig) {
+    BUNDLES_CONFIG.store(Arc::new(bundles_config));
+}
@@ -2,23 +2,37 @@ use std::collections::HashMap;
 use std::env;
 use std::process::Command;
 use std::str::FromStr;
+use std::sync::Arc;
 
+use arc_swap::ArcSwap;
 use macaroon::MacaroonKey;
 use once_cell::sync::Lazy;
 use reqwest::Url;
 use serde_json::Value;
 use time::OffsetDateTime;
 
-use super::{get_env, get_env_b64u_as_u8s, get_env_parse, get_env_parse_to_macaroon_key};
+use super::{
+    get_env, get_env_b64u_as_u8s, get_env_parse, get_env_parse_to_macaroon_key, get_optional_env,
+};
 use crate::{Error, Result};
 
-static INSTANCE: Lazy<Config> = Lazy::new(|| {
-    Config::load_from_env()
-        .unwrap_or_else(|ex| panic!("FATAL - WHILE LOADING CONF - Cause: {ex:?}"))
+/// Largest request body read when `SERVICE_MAX_BODY_BYTES` is not set, the
+/// upload limit of the OpenAI audio endpoints.
+const DEFAULT_MAX_BODY_BYTES: usize = 25 * 1024 * 1024;
+
+static INSTANCE: Lazy<ArcSwap<Config>> = Lazy::new(|| {
+    let config = Config::load_from_env()
+        .unwrap_or_else(|ex| panic!("FATAL - WHILE LOADING CONF - Cause: {ex:?}"));
+    ArcSwap::from_pointee(config)
 });
 
-pub fn config() -> &'static Config {
-    &INSTANCE
+pub fn config() -> Arc<Config> {
+    INSTANCE.load_full()
+}
+
+/// Replaces the config, requests in flight keep the one they started with.
+pub fn swap_config(config: Config) {
+    INSTANCE.store(Arc::new(config));
 }
 
 #[allow(non_snake_case)]
@@ -34,14 +48,22 @@ pub struct Config {
 
     // -- Web
     pub WEB_FOLDER: String,
+    /// Largest request body buffered to be checked and priced.
+    pub MAX_BODY_BYTES: usize,
+
+    // -- Admin
+    pub ADMIN_API_KEY: Option<String>,
 
     // -- Lightning
     pub LIGHTNING_ADDRESS: String,
     pub CASHU_MINT_URL: Url,
+    /// Static price of a bitcoin set by the operator, for the fiat
+    /// equivalents of the quotes. No fiat equivalent when not set.
+    pub BTC_PRICE_USD: Option<f64>,
 }
 
 impl Config {
-    fn load_from_env() -> Result<Config> {
+    pub fn load_from_env() -> Result<Config> {
         Ok(Config {
             // -- Crypt
             PWD_KEY: get_env_b64u_as_u8s("SERVICE_PWD_KEY")?,
@@ -55,10 +77,23 @@ impl Config {
 
             // -- Web
             WEB_FOLDER: get_env("SERVICE_WEB_FOLDER")?,
+            MAX_BODY_BYTES: match get_optional_env("SERVICE_MAX_BODY_BYTES") {
+                Some(_) => get_env_parse("SERVICE_MAX_BODY_BYTES")?,
+                None => DEFAULT_MAX_BODY_BYTES,
+            },
+
+            // -- Admin
+            // Empty as in the example config, the admin routes stay disabled.
+            ADMIN_API_KEY: get_optional_env("SERVICE_ADMIN_API_KEY")
+                .filter(|key| !key.trim().is_empty()),
 
             // -- Lightning
             LIGHTNING_ADDRESS: get_env("SERVICE_LIGHTNING_ADDRESS")?,
             CASHU_MINT_URL: get_env_parse("SERVICE_CASHU_MINT_URL")?,
+            BTC_PRICE_USD: match get_optional_env("SERVICE_BTC_PRICE_USD") {
+                Some(_) => Some(get_env_parse("SERVICE_BTC_PRICE_USD")?),
+                None => None,
+            },
         })
     }
 }
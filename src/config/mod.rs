@@ -1,18 +1,59 @@
 pub mod apis;
+pub mod azure;
+pub mod bundles;
 pub mod config;
+pub mod passes;
+pub mod products;
 pub mod replit;
 
-use anyhow::{anyhow, Error, Result};
-use macaroon::MacaroonKey;
+use std::collections::HashMap;
 use std::env;
 use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Error, Result};
+use arc_swap::ArcSwap;
+use macaroon::MacaroonKey;
+use once_cell::sync::Lazy;
+
+/// Variables of the .env file, looked up after the process environment, which
+/// they never override, as with `dotenv`. The process environment is never
+/// written, as other threads read it.
+static DOTENV: Lazy<ArcSwap<HashMap<String, String>>> =
+    Lazy::new(|| ArcSwap::from_pointee(read_dotenv()));
+
+fn read_dotenv() -> HashMap<String, String> {
+    match dotenvy::dotenv_iter() {
+        Ok(vars) => vars.flatten().collect(),
+        Err(_) => HashMap::new(),
+    }
+}
+
+/// Re-reads the .env file, so a reload picks up rotated keys.
+///
+/// Returns the previous variables, restored with `restore_dotenv` if the
+/// reload fails.
+pub fn reload_dotenv() -> Arc<HashMap<String, String>> {
+    DOTENV.swap(Arc::new(read_dotenv()))
+}
+
+pub fn restore_dotenv(vars: Arc<HashMap<String, String>>) {
+    DOTENV.store(vars);
+}
+
+/// Variable of the process environment, else of the .env file.
+pub fn env_var(name: &str) -> Option<String> {
+    env::var(name)
+        .ok()
+        .or_else(|| DOTENV.load().get(name).cloned())
+}
 
 pub fn get_env(name: &'static str) -> Result<String> {
-    env::var(name).map_err(|e| anyhow!("{}: {}", name, e))
+    env_var(name).ok_or_else(|| anyhow!("{}: not set", name))
 }
 
 pub fn get_optional_env(name: &'static str) -> Option<String> {
-    env::var(name).ok()
+    env_var(name)
 }
 
 pub fn get_env_parse<T: FromStr>(name: &'static str) -> Result<T> {
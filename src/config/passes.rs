@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use arc_swap::ArcSwap;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::config::products::{Product, ProductsConfig};
+use crate::lightning::{check_services, Caveat};
+
+/// A pass product: pay once for unlimited access to some services during a
+/// time window, optionally capped to a number of requests.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PassParams {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub duration_sec: i64,
+    /// Services covered by the pass, every service if empty.
+    #[serde(default)]
+    pub services: Vec<String>,
+    #[serde(default)]
+    pub max_requests: Option<i64>,
+    pub price_msat: u64,
+}
+
+impl Product for PassParams {
+    const KIND: &'static str = "pass";
+    const KINDS: &'static str = "passes";
+    const PATH_ENV: &'static str = "PASSES_CONFIG_PATH";
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn price_msat(&self) -> u64 {
+        self.price_msat
+    }
+
+    /// The time window starts with the first request made with the token, not
+    /// when it is bought.
+    fn caveats(&self) -> Vec<Caveat> {
+        let mut caveats = vec![
+            Caveat::Pass(self.id.clone()),
+            Caveat::Window(self.duration_sec),
+        ];
+        if !self.services.is_empty() {
+            caveats.push(Caveat::Services(self.services.clone()));
+        }
+        if let Some(max_requests) = self.max_requests {
+            caveats.push(Caveat::Uses(max_requests));
+        }
+
+        caveats
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.duration_sec <= 0 {
+            return Err(anyhow!("duration_sec must be positive"));
+        }
+        if self
+            .max_requests
+            .is_some_and(|max_requests| max_requests <= 0)
+        {
+            return Err(anyhow!("max_requests must be positive"));
+        }
+
+        check_services(&self.services).map_err(|e| anyhow!(e))
+    }
+
+    fn config() -> Arc<PassesConfig> {
+        passes_config()
+    }
+}
+
+pub type PassesConfig = ProductsConfig<PassParams>;
+
+static PASSES_CONFIG: Lazy<ArcSwap<PassesConfig>> = Lazy::new(|| {
+    let passes_config = PassesConfig::load_from_env()
+        .unwrap_or_else(|ex| panic!("FATAL - WHILE LOADING PASSES - Cause: {ex:?}"));
+    ArcSwap::from_pointee(passes_config)
+});
+
+pub fn passes_config() -> Arc<PassesConfig> {
+    PASSES_CONFIG.load_full()
+}
+
+/// Replaces the passes, tokens already sold keep their caveats.
+pub fn swap_passes_config(passes_config: PassesConfig) {
+    PASSES_CONFIG.store(Arc::new(passes_config));
+}
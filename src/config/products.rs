@@ -0,0 +1,95 @@
+// Products sold for an upfront payment (passes, bundles): a JSON file of
+// products, each bought with an L402 challenge for a token carrying its
+// caveats.
+
+use std::fs;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tracing::info;
+
+use crate::config::get_optional_env;
+use crate::lightning::Caveat;
+
+pub trait Product: Clone + Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// Name of a product, e.g. `pass`.
+    const KIND: &'static str;
+    /// Name of the list of products, in the file and the routes, e.g. `passes`.
+    const KINDS: &'static str;
+    /// Variable holding the path of the file of products.
+    const PATH_ENV: &'static str;
+
+    fn id(&self) -> &str;
+
+    fn price_msat(&self) -> u64;
+
+    /// Caveats of the token bought with the product.
+    fn caveats(&self) -> Vec<Caveat>;
+
+    fn validate(&self) -> Result<()>;
+
+    /// The products currently configured.
+    fn config() -> Arc<ProductsConfig<Self>>;
+}
+
+#[derive(Debug)]
+pub struct ProductsConfig<P> {
+    pub products: Vec<P>,
+}
+
+impl<P> Default for ProductsConfig<P> {
+    fn default() -> Self {
+        Self {
+            products: Vec::new(),
+        }
+    }
+}
+
+impl<P: Product> ProductsConfig<P> {
+    pub fn get(&self, id: &str) -> Option<&P> {
+        self.products.iter().find(|product| product.id() == id)
+    }
+
+    /// Products of the file pointed to by `P::PATH_ENV`, none without it.
+    pub fn load_from_env() -> Result<Self> {
+        match get_optional_env(P::PATH_ENV) {
+            Some(path) => {
+                info!("Loading {} from {}", P::KINDS, path);
+                Self::load_from_file(&path)
+            }
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Reads `{ "<kinds>": [...] }`.
+    fn load_from_file(path: &str) -> Result<Self> {
+        let content = fs::read_to_string(path).map_err(|e| anyhow!("{}: {}", path, e))?;
+        let mut content: Map<String, Value> =
+            serde_json::from_str(&content).map_err(|e| anyhow!("{}: {}", path, e))?;
+        let products: Vec<P> = match content.remove(P::KINDS) {
+            Some(products) => {
+                serde_json::from_value(products).map_err(|e| anyhow!("{}: {}", path, e))?
+            }
+            None => Vec::new(),
+        };
+
+        for product in &products {
+            // A free product would mint tokens for nothing.
+            if product.price_msat() == 0 {
+                return Err(anyhow!(
+                    "{} {}: price_msat must be positive",
+                    P::KIND,
+                    product.id()
+                ));
+            }
+            product
+                .validate()
+                .map_err(|e| anyhow!("{} {}: {}", P::KIND, product.id(), e))?;
+        }
+
+        Ok(Self { products })
+    }
+}
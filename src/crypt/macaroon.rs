@@ -1,47 +1,132 @@
-use macaroon::{Macaroon, MacaroonKey, Verifier};
+use macaroon::{ByteString, Caveat as MacaroonCaveat, Macaroon, MacaroonKey, Verifier};
 
 use super::error::{Error, Result};
 use crate::config::config::config;
+use crate::lightning::Caveat;
 
-pub fn generate_macaroon(payment_hash: String, timeout: i64) -> Macaroon {
-    _generate_macaroon(payment_hash, timeout, &config().MACAROON_KEY)
+pub fn generate_macaroon(identifier: &str, caveats: Vec<Caveat>) -> Macaroon {
+    _generate_macaroon(identifier, caveats, &config().MACAROON_KEY)
 }
 
-pub fn validate_macaroon(macaroon: Macaroon, preimage_hash: Vec<u8>) -> Result<bool> {
+/// Validates the macaroon signature and caveats, `preimage_hash` being `None`
+/// for complimentary tokens which carry no payment hash.
+pub fn validate_macaroon(macaroon: Macaroon, preimage_hash: Option<Vec<u8>>) -> Result<bool> {
     _validate_macaroon(macaroon, preimage_hash, &config().MACAROON_KEY)
 }
 
-fn _generate_macaroon(payment_hash: String, timeout: i64, key: &MacaroonKey) -> Macaroon {
-    let mut macaroon = Macaroon::create(Some("location".into()), &key, "id".into()).unwrap();
-    let time_now = chrono::Utc::now().timestamp();
-    macaroon.add_first_party_caveat(format!("payment_hash = {}", payment_hash).as_bytes().into());
-    // macaroon.add_first_party_caveat(format!("time < {}", time_now +
-    // timeout).as_bytes().into());
+/// First party caveats of the macaroon, each with the signature the macaroon
+/// had right after it was added. Only meaningful for a validated macaroon.
+pub fn caveat_signatures(macaroon: &Macaroon) -> Vec<(ByteString, MacaroonKey)> {
+    _caveat_signatures(macaroon, &config().MACAROON_KEY)
+}
+
+fn _generate_macaroon(identifier: &str, caveats: Vec<Caveat>, key: &MacaroonKey) -> Macaroon {
+    let mut macaroon = Macaroon::create(Some("location".into()), key, identifier.into()).unwrap();
+    for caveat in caveats {
+        macaroon.add_first_party_caveat(caveat.to_string().as_bytes().into());
+    }
+
+    macaroon
+}
+
+fn _caveat_signatures(macaroon: &Macaroon, key: &MacaroonKey) -> Vec<(ByteString, MacaroonKey)> {
+    let mut prefix = Macaroon::create(macaroon.location(), key, macaroon.identifier()).unwrap();
 
     macaroon
+        .first_party_caveats()
+        .into_iter()
+        .filter_map(|caveat| match caveat {
+            MacaroonCaveat::FirstParty(fp) => Some(fp.predicate()),
+            MacaroonCaveat::ThirdParty(_) => None,
+        })
+        .map(|predicate| {
+            prefix.add_first_party_caveat(predicate.clone());
+            (predicate, prefix.signature())
+        })
+        .collect()
 }
 
 fn _validate_macaroon(
     macaroon: Macaroon,
-    preimage_hash: Vec<u8>,
+    preimage_hash: Option<Vec<u8>>,
     key: &MacaroonKey,
 ) -> Result<bool> {
     let mut verifier = Verifier::default();
-    verifier.satisfy_exact(
-        format!(
-            "payment_hash = {}",
-            hex::encode(preimage_hash).to_lowercase()
-        )
-        .as_bytes()
-        .into(),
-    );
-    // verifier.satisfy_exact(
-    //     format!("time < {}", chrono::Utc::now().timestamp())
-    //         .as_bytes()
-    //         .into(),
-    // );
+    if let Some(preimage_hash) = preimage_hash {
+        verifier.satisfy_exact(
+            format!(
+                "payment_hash = {}",
+                hex::encode(preimage_hash).to_lowercase()
+            )
+            .as_bytes()
+            .into(),
+        );
+    }
+    verifier.satisfy_general(satisfy_caveat);
     verifier
-        .verify(&macaroon, &key, Default::default())
+        .verify(&macaroon, key, Default::default())
         .map_err(|_| Error::MacaroonCaveatFail)?;
     Ok(true)
 }
+
+/// Satisfies the caveats which can be checked without the request.
+/// Request scoped caveats (services, uses, spend caps, windows) and comp token revocation are
+/// enforced by the L402 middleware.
+fn satisfy_caveat(predicate: &ByteString) -> bool {
+    let caveat = match std::str::from_utf8(&predicate.0).map(str::parse::<Caveat>) {
+        Ok(Ok(caveat)) => caveat,
+        _ => return false,
+    };
+
+    match caveat {
+        // Only ever satisfied by the exact preimage hash.
+        Caveat::PaymentHash(_) => false,
+        Caveat::Expires(time) => chrono::Utc::now().timestamp() < time,
+        Caveat::Services(_)
+        | Caveat::Pass(_)
+        | Caveat::Bundle(_)
+        | Caveat::Uses(_)
+        | Caveat::Comp(_)
+        | Caveat::Budget(_)
+        | Caveat::MaxCost(_)
+        | Caveat::MaxPerRequest(_)
+        | Caveat::Window(_) => true,
+    }
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_caveat_signatures_delegate_ok() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_key = MacaroonKey::generate(b"matador-test-key");
+        let fx_parent = _generate_macaroon("token", vec![Caveat::Budget(1000)], &fx_key);
+        let fx_delegate = |max_cost: i64| {
+            let mut delegate = fx_parent.clone();
+            delegate
+                .add_first_party_caveat(Caveat::MaxCost(max_cost).to_string().as_bytes().into());
+            delegate
+        };
+        let (fx_delegate_a, fx_delegate_b) = (fx_delegate(100), fx_delegate(200));
+
+        // -- Exec
+        let parent = _caveat_signatures(&fx_parent, &fx_key);
+        let delegate_a = _caveat_signatures(&fx_delegate_a, &fx_key);
+        let delegate_b = _caveat_signatures(&fx_delegate_b, &fx_key);
+
+        // -- Check
+        // The delegates share the budget of the parent, each with its own cap.
+        assert_eq!(delegate_a[0].1, parent[0].1);
+        assert_eq!(delegate_b[0].1, parent[0].1);
+        assert_eq!(delegate_a[1].1, fx_delegate_a.signature());
+        assert_ne!(delegate_a[1].1, delegate_b[1].1);
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
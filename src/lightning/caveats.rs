@@ -0,0 +1,345 @@
+use std::fmt;
+use std::str::FromStr;
+
+use macaroon::{Caveat as MacaroonCaveat, Macaroon};
+use serde::Serialize;
+
+use super::error::{Error, Result};
+
+/// First party caveats understood by matador.
+///
+/// String format: `<name> <op> <value>`, e.g. `time < 1700000000`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Caveat {
+    /// Hex encoded payment hash of the invoice unlocking the token.
+    PaymentHash(String),
+    /// Unix timestamp (seconds) after which the token is rejected.
+    Expires(i64),
+    /// Services (first path segment, e.g. `openai`) the token is scoped to.
+    Services(Vec<String>),
+    /// Id of the pass the token was minted for.
+    Pass(String),
+    /// Id of the bundle the token was minted for.
+    Bundle(String),
+    /// Maximum number of requests the token can be forwarded for.
+    Uses(i64),
+    /// Label of a complimentary token, minted by the operator without payment.
+    Comp(String),
+    /// Maximum amount (msat) the token can spend across all its requests.
+    Budget(i64),
+    /// Spend cap (msat) across all requests, added by a holder delegating the
+    /// token.
+    MaxCost(i64),
+    /// Maximum price (msat) of a single request.
+    MaxPerRequest(i64),
+    /// Seconds the token can be used for, from its first request.
+    Window(i64),
+}
+
+impl fmt::Display for Caveat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PaymentHash(hash) => write!(f, "payment_hash = {hash}"),
+            Self::Expires(time) => write!(f, "time < {time}"),
+            Self::Services(services) => write!(f, "services = {}", services.join(",")),
+            Self::Pass(id) => write!(f, "pass = {id}"),
+            Self::Bundle(id) => write!(f, "bundle = {id}"),
+            Self::Uses(uses) => write!(f, "uses = {uses}"),
+            Self::Comp(label) => write!(f, "comp = {label}"),
+            Self::Budget(msat) => write!(f, "budget_msat = {msat}"),
+            Self::MaxCost(msat) => write!(f, "max_cost = {msat}"),
+            Self::MaxPerRequest(msat) => write!(f, "max_per_request = {msat}"),
+            Self::Window(sec) => write!(f, "window_sec = {sec}"),
+        }
+    }
+}
+
+impl FromStr for Caveat {
+    type Err = Error;
+
+    fn from_str(caveat: &str) -> std::result::Result<Self, Self::Err> {
+        let splits: Vec<&str> = caveat.splitn(3, ' ').collect();
+        if splits.len() != 3 {
+            return Err(Error::L402CaveatFail);
+        }
+        let (name, op, value) = (splits[0], splits[1], splits[2]);
+
+        let caveat = match (name, op) {
+            ("payment_hash", "=") => Self::PaymentHash(value.to_string()),
+            ("time", "<") => Self::Expires(parse_int(value)?),
+            ("services", "=") => Self::Services(value.split(',').map(String::from).collect()),
+            ("pass", "=") => Self::Pass(value.to_string()),
+            ("bundle", "=") => Self::Bundle(value.to_string()),
+            ("uses", "=") => Self::Uses(parse_int(value)?),
+            ("comp", "=") => Self::Comp(value.to_string()),
+            ("budget_msat", "=") => Self::Budget(parse_int(value)?),
+            ("max_cost", "=") => Self::MaxCost(parse_int(value)?),
+            ("max_per_request", "=") => Self::MaxPerRequest(parse_int(value)?),
+            ("window_sec", "=") => Self::Window(parse_int(value)?),
+            _ => return Err(Error::L402CaveatFail),
+        };
+
+        Ok(caveat)
+    }
+}
+
+impl Caveat {
+    /// Caveats of a token good for a single request to `service` of up to
+    /// `price_msat`, as challenged and quoted.
+    pub fn single_request(service: &str, price_msat: u64) -> Vec<Self> {
+        vec![
+            Self::Services(vec![service.to_string()]),
+            Self::Uses(1),
+            Self::MaxPerRequest(price_msat as i64),
+        ]
+    }
+}
+
+/// A cumulative limit of a token, shared by the tokens delegated from it.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Meter {
+    /// Hash of the signature the token had when the limit was added, so
+    /// delegates count against the limits of their parent and their own.
+    pub id: String,
+    pub max_uses: Option<i64>,
+    pub max_spent_msat: Option<i64>,
+    pub window_sec: Option<i64>,
+}
+
+impl Meter {
+    /// The meter of a limiting caveat, `None` for the other caveats.
+    pub fn new(id: String, caveat: &Caveat) -> Option<Self> {
+        let meter = Self {
+            id,
+            ..Default::default()
+        };
+        let meter = match *caveat {
+            Caveat::Uses(uses) => Self {
+                max_uses: Some(uses),
+                ..meter
+            },
+            Caveat::Budget(msat) | Caveat::MaxCost(msat) => Self {
+                max_spent_msat: Some(msat),
+                ..meter
+            },
+            Caveat::Window(sec) => Self {
+                window_sec: Some(sec),
+                ..meter
+            },
+            _ => return None,
+        };
+
+        Some(meter)
+    }
+}
+
+fn min_or(current: Option<i64>, value: i64) -> i64 {
+    current.map_or(value, |current| current.min(value))
+}
+
+/// Checks that the service names fit the comma separated list of a
+/// `services` caveat, which would otherwise read as other services.
+pub fn check_services(services: &[String]) -> core::result::Result<(), String> {
+    let invalid = services.iter().find(|service| {
+        service.is_empty() || service.contains(|c: char| c == ',' || c.is_whitespace())
+    });
+    match invalid {
+        Some(service) => Err(format!("invalid service {service:?}")),
+        None => Ok(()),
+    }
+}
+
+fn parse_int(value: &str) -> Result<i64> {
+    value.parse::<i64>().map_err(|_| Error::L402CaveatFail)
+}
+
+/// The effective restrictions of a token, all its caveats combined.
+///
+/// Caveats can only attenuate a token, so when the same caveat appears more
+/// than once the most restrictive one wins.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Caveats {
+    pub payment_hash: Option<String>,
+    pub expires_at: Option<i64>,
+    pub services: Option<Vec<String>>,
+    pub pass: Option<String>,
+    pub bundle: Option<String>,
+    pub uses: Option<i64>,
+    pub comp: Option<String>,
+    pub budget_msat: Option<i64>,
+    pub max_cost_msat: Option<i64>,
+    pub max_per_request_msat: Option<i64>,
+    pub window_sec: Option<i64>,
+}
+
+impl FromIterator<Caveat> for Caveats {
+    fn from_iter<I: IntoIterator<Item = Caveat>>(caveats: I) -> Self {
+        let mut combined = Self::default();
+        for caveat in caveats {
+            combined.add(caveat);
+        }
+
+        combined
+    }
+}
+
+impl Caveats {
+    pub fn from_macaroon(macaroon: &Macaroon) -> Result<Self> {
+        let mut caveats = Self::default();
+
+        for caveat in macaroon.first_party_caveats() {
+            if let MacaroonCaveat::FirstParty(fp) = caveat {
+                let predicate =
+                    String::from_utf8(fp.predicate().0).map_err(|_| Error::L402CaveatFail)?;
+                caveats.add(predicate.parse()?);
+            }
+        }
+
+        Ok(caveats)
+    }
+
+    fn add(&mut self, caveat: Caveat) {
+        match caveat {
+            Caveat::PaymentHash(hash) => self.payment_hash = Some(hash),
+            Caveat::Expires(time) => self.expires_at = Some(min_or(self.expires_at, time)),
+            Caveat::Services(services) => {
+                self.services = Some(match self.services.take() {
+                    Some(current) => current
+                        .into_iter()
+                        .filter(|s| services.contains(s))
+                        .collect(),
+                    None => services,
+                });
+            }
+            Caveat::Pass(id) => self.pass = Some(id),
+            Caveat::Bundle(id) => self.bundle = Some(id),
+            Caveat::Uses(uses) => self.uses = Some(min_or(self.uses, uses)),
+            Caveat::Comp(label) => self.comp = Some(label),
+            Caveat::Budget(msat) => self.budget_msat = Some(min_or(self.budget_msat, msat)),
+            Caveat::MaxCost(msat) => self.max_cost_msat = Some(min_or(self.max_cost_msat, msat)),
+            Caveat::MaxPerRequest(msat) => {
+                self.max_per_request_msat = Some(min_or(self.max_per_request_msat, msat));
+            }
+            Caveat::Window(sec) => self.window_sec = Some(min_or(self.window_sec, sec)),
+        }
+    }
+
+    /// Returns true if the usage of the token has to be recorded.
+    pub fn is_metered(&self) -> bool {
+        self.uses.is_some() || self.spend_limit_msat().is_some() || self.window_sec.is_some()
+    }
+
+    /// Maximum cumulative spend (msat), the lowest of the budget and max cost.
+    pub fn spend_limit_msat(&self) -> Option<i64> {
+        match (self.budget_msat, self.max_cost_msat) {
+            (Some(budget), Some(max_cost)) => Some(budget.min(max_cost)),
+            (budget, max_cost) => budget.or(max_cost),
+        }
+    }
+
+    /// Returns true if a single request of `price_msat` is allowed.
+    pub fn allows_price(&self, price_msat: i64) -> bool {
+        self.max_per_request_msat
+            .is_none_or(|max_per_request| price_msat <= max_per_request)
+    }
+
+    /// Returns true if the token may be used against `service`.
+    pub fn covers_service(&self, service: &str) -> bool {
+        match &self.services {
+            Some(services) => services.iter().any(|s| s == service),
+            None => true,
+        }
+    }
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_check_services() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_services = |services: &[&str]| -> Vec<String> {
+            services.iter().map(|service| service.to_string()).collect()
+        };
+
+        // -- Exec & Check
+        assert!(check_services(&fx_services(&["openai", "stability-ai"])).is_ok());
+        assert!(check_services(&fx_services(&["openai,anthropic"])).is_err());
+        assert!(check_services(&fx_services(&["open ai"])).is_err());
+        assert!(check_services(&fx_services(&["openai\t"])).is_err());
+        assert!(check_services(&fx_services(&[""])).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_caveat_roundtrip_ok() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_caveats = [
+            Caveat::PaymentHash("abcd".to_string()),
+            Caveat::Expires(1_700_000_000),
+            Caveat::Services(vec!["openai".to_string(), "stability".to_string()]),
+            Caveat::Pass("stability-1h".to_string()),
+            Caveat::Bundle("openai-100".to_string()),
+            Caveat::Uses(100),
+            Caveat::Comp("partner acme".to_string()),
+            Caveat::Budget(1_000_000),
+            Caveat::MaxCost(500_000),
+            Caveat::MaxPerRequest(10_000),
+            Caveat::Window(3600),
+        ];
+
+        for fx_caveat in fx_caveats {
+            // -- Exec
+            let res: Caveat = fx_caveat.to_string().parse()?;
+
+            // -- Check
+            assert_eq!(res, fx_caveat);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_caveats_attenuate_ok() -> Result<()> {
+        // -- Setup & Fixtures
+        let mut caveats = Caveats::default();
+
+        // -- Exec
+        caveats.add(Caveat::Services(vec!["openai".into(), "cohere".into()]));
+        caveats.add(Caveat::Services(vec!["cohere".into()]));
+        caveats.add(Caveat::Expires(20));
+        caveats.add(Caveat::Expires(10));
+
+        // -- Check
+        assert!(caveats.covers_service("cohere"));
+        assert!(!caveats.covers_service("openai"));
+        assert_eq!(caveats.expires_at, Some(10));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_caveats_spend_limits_ok() -> Result<()> {
+        // -- Setup & Fixtures
+        let mut caveats = Caveats::default();
+
+        // -- Exec
+        caveats.add(Caveat::Budget(1_000_000));
+        caveats.add(Caveat::MaxCost(50_000));
+        caveats.add(Caveat::MaxPerRequest(5_000));
+
+        // -- Check
+        assert!(caveats.is_metered());
+        assert_eq!(caveats.spend_limit_msat(), Some(50_000));
+        assert!(caveats.allows_price(5_000));
+        assert!(!caveats.allows_price(5_001));
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
@@ -3,13 +3,14 @@ use macaroon::{Format, Macaroon};
 use sha2::Digest;
 
 use super::error::{Error, Result};
-use super::LightningAddress;
+use super::{Caveat, Caveats, LightningAddress, Meter};
 use crate::config::config::config;
 use crate::crypt;
 
 pub struct L402Builder {
     amount: Option<u64>,
     timeout: Option<u64>,
+    caveats: Vec<Caveat>,
 }
 
 impl L402Builder {
@@ -17,6 +18,7 @@ impl L402Builder {
         Self {
             amount: None,
             timeout: None,
+            caveats: Vec::new(),
         }
     }
 
@@ -30,14 +32,22 @@ impl L402Builder {
         self
     }
 
+    /// Adds caveats on top of the payment hash one.
+    pub fn caveats(mut self, caveats: Vec<Caveat>) -> Self {
+        self.caveats.extend(caveats);
+        self
+    }
+
     pub async fn build(self) -> Result<L402> {
         println!("L402Builder::build");
         let lnaddress = LightningAddress::new(&config().LIGHTNING_ADDRESS).await;
         let invoice_amount: i64 = self.amount.unwrap_or(1000) as i64;
         let invoice: Bolt11Invoice = lnaddress.get_invoice(invoice_amount).await;
         let payment_hash = invoice.payment_hash();
-        let timeout = self.timeout.unwrap_or(60 * 60 * 24) as i64;
-        let token = crypt::macaroon::generate_macaroon(payment_hash.to_string(), timeout);
+        let mut caveats = vec![Caveat::PaymentHash(payment_hash.to_string())];
+        caveats.extend(self.caveats);
+        let token_id = uuid::Uuid::new_v4().to_string();
+        let token = crypt::macaroon::generate_macaroon(&token_id, caveats);
         Ok(L402 {
             token,
             invoice: Some(invoice),
@@ -62,14 +72,89 @@ impl L402 {
         }
     }
 
+    /// Mints a complimentary token: no invoice, no payment hash, only the
+    /// `comp` marker and the given caveats.
+    pub fn mint_comp(label: &str, caveats: Vec<Caveat>) -> Self {
+        let mut comp_caveats = vec![Caveat::Comp(label.to_string())];
+        comp_caveats.extend(caveats);
+        let token_id = uuid::Uuid::new_v4().to_string();
+        let token = crypt::macaroon::generate_macaroon(&token_id, comp_caveats);
+
+        L402 {
+            token,
+            invoice: None,
+            preimage: None,
+        }
+    }
+
+    /// Paid tokens need the invoice preimage, complimentary tokens are valid
+    /// on their signature alone (revocation is checked by the middleware).
     pub fn is_valid(&self) -> Result<bool> {
-        let preimage_hash = get_preimage_hash(self.preimage.as_ref().unwrap());
+        let caveats = self.caveats()?;
+        let preimage_hash = match (&self.preimage, &caveats.payment_hash) {
+            (Some(preimage), _) => Some(get_preimage_hash(preimage)?),
+            (None, None) if caveats.comp.is_some() => None,
+            _ => return Ok(false),
+        };
         Ok(crypt::macaroon::validate_macaroon(
             self.token.clone(),
             preimage_hash,
         )?)
     }
 
+    /// Returns true if the preimage settles the payment hash of the token.
+    pub fn is_settled(&self) -> Result<bool> {
+        let (Some(preimage), Some(payment_hash)) = (&self.preimage, self.caveats()?.payment_hash)
+        else {
+            return Ok(false);
+        };
+        Ok(hex::encode(get_preimage_hash(preimage)?) == payment_hash.to_lowercase())
+    }
+
+    /// Identifier of the macaroon, unique per minted token.
+    pub fn token_id(&self) -> String {
+        String::from_utf8_lossy(&self.token.identifier().0).to_string()
+    }
+
+    pub fn caveats(&self) -> Result<Caveats> {
+        Caveats::from_macaroon(&self.token)
+    }
+
+    /// Meters of the uses, spend and window limits of the token and of the
+    /// tokens it was delegated from, outermost first.
+    pub fn meters(&self) -> Result<Vec<Meter>> {
+        let mut meters = Vec::new();
+        for (predicate, signature) in crypt::macaroon::caveat_signatures(&self.token) {
+            let caveat: Caveat = String::from_utf8(predicate.0)
+                .map_err(|_| Error::L402CaveatFail)?
+                .parse()?;
+            let id = hex::encode(sha2::Sha256::digest(signature.as_ref() as &[u8]));
+            meters.extend(Meter::new(id, &caveat));
+        }
+
+        Ok(meters)
+    }
+
+    /// Adds caveats, as a holder delegating the token does.
+    pub fn attenuate(&self, caveats: Vec<Caveat>) -> Self {
+        let mut token = self.token.clone();
+        for caveat in caveats {
+            token.add_first_party_caveat(caveat.to_string().as_bytes().into());
+        }
+
+        Self::new(token, self.invoice.clone(), self.preimage.clone())
+    }
+
+    /// The `Authorization` header value to use the token, for tokens which
+    /// need no preimage.
+    pub fn to_authorization_string(&self) -> String {
+        format!("L402 {}:", self.token.serialize(Format::V2).unwrap())
+    }
+
+    pub fn invoice(&self) -> Option<String> {
+        self.invoice.as_ref().map(ToString::to_string)
+    }
+
     pub fn to_authenticate_string(&self) -> String {
         format!(
             "L402 token=\"{}\", invoice=\"{}\"",
@@ -94,7 +179,7 @@ impl L402 {
             Err(_) => return Err(Error::L402AuthHeaderInvalidFail),
         };
 
-        let preimage = Some(macaroon_preimage[1].to_string());
+        let preimage = Some(macaroon_preimage[1].to_string()).filter(|p| !p.is_empty());
 
         Ok(L402 {
             token: token,
@@ -104,8 +189,9 @@ impl L402 {
     }
 }
 
-fn get_preimage_hash(preimage: &str) -> Vec<u8> {
+fn get_preimage_hash(preimage: &str) -> Result<Vec<u8>> {
+    let preimage = hex::decode(preimage).map_err(|_| Error::L402AuthHeaderInvalidFail)?;
     let mut hasher = sha2::Sha256::new();
-    hasher.update(hex::decode(preimage).unwrap());
-    hasher.finalize().to_vec()
+    hasher.update(preimage);
+    Ok(hasher.finalize().to_vec())
 }
@@ -1,9 +1,11 @@
 pub mod cashu402;
+pub mod caveats;
 pub mod error;
 pub mod l402;
 pub mod lightning_address;
 
 pub use cashu402::*;
+pub use caveats::*;
 pub use error::{Error, Result};
 pub use l402::*;
 pub use lightning_address::*;
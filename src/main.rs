@@ -7,12 +7,19 @@ use tracing_subscriber::EnvFilter;
 
 use crate::model::ModelManager;
 
+#[cfg(test)]
+mod _dev_utils;
+mod auth;
+mod cli;
 mod config;
 mod crypt;
 mod ctx;
 mod lightning;
 mod log;
 mod model;
+mod policy;
+mod pricing;
+mod translate;
 mod utils;
 mod web;
 
@@ -30,13 +37,20 @@ async fn main() -> Result<()> {
         .with_env_filter(EnvFilter::from_default_env())
         .init();
 
+    // Commands, e.g. `price-expr`, run instead of the server.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| cli::is_command(arg)) {
+        return cli::run(&args).await;
+    }
+
     // -- FOR DEV ONLY
     // _dev_utils::init_dev().await;
 
     // Initialize ModelManager.
-    // let _mm = ModelManager::new().await?;
+    let mm = ModelManager::new().await?;
 
-    let router = web::router::setup_router()?;
+    let router = web::router::setup_router(mm.clone())?;
+    tokio::spawn(web::router::reload_on_sighup(mm));
 
     // Apply middleware conditionally
     // if env::var("LNADDRESS").is_ok() && env::var("MACAROON_SECRET").is_ok() {
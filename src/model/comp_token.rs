@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+use sqlb::{Fields, HasFields};
+use sqlx::FromRow;
+
+use super::base::{self, DbBmc};
+use super::error::Result;
+use super::ModelManager;
+use crate::ctx::Ctx;
+
+// region:    --- CompToken Types
+#[derive(Debug, Clone, Fields, FromRow, Serialize)]
+pub struct CompToken {
+    pub id: i64,
+    pub token_id: String,
+    pub label: String,
+    pub revoked: bool,
+}
+
+#[derive(Fields, Deserialize)]
+pub struct CompTokenForCreate {
+    pub token_id: String,
+    pub label: String,
+}
+// endregion: --- CompToken Types
+
+// region:    --- CompTokenBmc
+pub struct CompTokenBmc;
+
+impl DbBmc for CompTokenBmc {
+    const TABLE: &'static str = "comp_token";
+}
+
+#[allow(dead_code)]
+impl CompTokenBmc {
+    pub async fn create(
+        ctx: &Ctx,
+        mm: &ModelManager,
+        comp_token_c: CompTokenForCreate,
+    ) -> Result<i64> {
+        base::create::<Self, _>(ctx, mm, comp_token_c).await
+    }
+
+    pub async fn get_by_token_id(
+        _ctx: &Ctx,
+        mm: &ModelManager,
+        token_id: &str,
+    ) -> Result<Option<CompToken>> {
+        let db = mm.db();
+
+        let entity = sqlb::select()
+            .table(Self::TABLE)
+            .columns(CompToken::field_names())
+            .and_where("token_id", "=", token_id.to_string())
+            .fetch_optional(db)
+            .await?;
+
+        Ok(entity)
+    }
+
+    pub async fn list(ctx: &Ctx, mm: &ModelManager) -> Result<Vec<CompToken>> {
+        base::list::<Self, _>(ctx, mm).await
+    }
+
+    /// Returns false if no comp token has this id.
+    pub async fn revoke(_ctx: &Ctx, mm: &ModelManager, token_id: &str) -> Result<bool> {
+        let db = mm.db();
+
+        let count = sqlb::update()
+            .table(Self::TABLE)
+            .data(vec![("revoked", true).into()])
+            .and_where("token_id", "=", token_id.to_string())
+            .exec(db)
+            .await?;
+
+        Ok(count > 0)
+    }
+}
+// endregion: --- CompTokenBmc
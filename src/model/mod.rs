@@ -5,6 +5,8 @@ mod error;
 mod store;
 
 pub mod balance;
+pub mod comp_token;
+pub mod token_usage;
 
 pub use self::error::{Error, Result};
 use crate::model::store::{new_db_pool, Db};
@@ -13,9 +13,18 @@ use crate::config::config::config;
 pub type Db = Pool<Postgres>;
 
 pub async fn new_db_pool() -> Result<Db> {
-    PgPoolOptions::new()
+    let db = PgPoolOptions::new()
         .max_connections(5)
         .connect(&config().DB_URL)
         .await
-        .map_err(|ex| Error::FailToCreatePool(ex.to_string()))
+        .map_err(|ex| Error::FailToCreatePool(ex.to_string()))?;
+
+    // Tables of the token usage and the comp tokens, created or updated at
+    // startup.
+    sqlx::migrate!("sql/migrations")
+        .run(&db)
+        .await
+        .map_err(|ex| Error::FailToMigrate(ex.to_string()))?;
+
+    Ok(db)
 }
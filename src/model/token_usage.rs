@@ -0,0 +1,363 @@
+use serde::Serialize;
+use sqlb::{Fields, HasFields};
+use sqlx::FromRow;
+
+use super::base::DbBmc;
+use super::error::Result;
+use super::ModelManager;
+use crate::ctx::Ctx;
+use crate::lightning::Meter;
+
+// region:    --- TokenUsage Types
+#[derive(Debug, Clone, Fields, FromRow, Serialize)]
+pub struct TokenUsage {
+    pub meter_id: String,
+    pub uses: i64,
+    pub spent_msat: i64,
+    pub first_used_at: i64,
+}
+
+/// What the meters of a token leave, the lowest of them, `None` where no meter
+/// limits it.
+#[derive(Debug, Default, Serialize)]
+pub struct UsageLeft {
+    pub uses: Option<i64>,
+    pub budget_msat: Option<i64>,
+    pub window_ends_at: Option<i64>,
+}
+
+impl UsageLeft {
+    pub fn new(meters: &[Meter], usages: &[TokenUsage]) -> Self {
+        let mut left = Self::default();
+        for meter in meters {
+            let usage = usages.iter().find(|usage| usage.meter_id == meter.id);
+            let (uses, spent_msat) = usage.map_or((0, 0), |usage| (usage.uses, usage.spent_msat));
+            if let Some(max_uses) = meter.max_uses {
+                left.uses = Some(min_or(left.uses, (max_uses - uses).max(0)));
+            }
+            if let Some(max_spent_msat) = meter.max_spent_msat {
+                left.budget_msat = Some(min_or(
+                    left.budget_msat,
+                    (max_spent_msat - spent_msat).max(0),
+                ));
+            }
+            if let (Some(window_sec), Some(usage)) = (meter.window_sec, usage) {
+                let ends_at = usage.first_used_at.saturating_add(window_sec);
+                left.window_ends_at = Some(min_or(left.window_ends_at, ends_at));
+            }
+        }
+
+        left
+    }
+
+    /// No use, budget or time left at `now`, so the token pays for nothing
+    /// more.
+    pub fn is_exhausted(&self, now: i64) -> bool {
+        self.uses == Some(0)
+            || self.budget_msat == Some(0)
+            || self.window_ends_at.is_some_and(|ends_at| ends_at <= now)
+    }
+}
+
+fn min_or(current: Option<i64>, value: i64) -> i64 {
+    current.map_or(value, |current| current.min(value))
+}
+// endregion: --- TokenUsage Types
+
+// region:    --- TokenUsageBmc
+pub struct TokenUsageBmc;
+
+impl DbBmc for TokenUsageBmc {
+    const TABLE: &'static str = "token_usage";
+}
+
+#[allow(dead_code)]
+impl TokenUsageBmc {
+    pub async fn list_by_meters(
+        _ctx: &Ctx,
+        mm: &ModelManager,
+        meters: &[Meter],
+    ) -> Result<Vec<TokenUsage>> {
+        let db = mm.db();
+        let meter_ids: Vec<&str> = meters.iter().map(|meter| meter.id.as_str()).collect();
+
+        let usages = sqlx::query_as(
+            "SELECT meter_id, uses, spent_msat, first_used_at FROM token_usage
+             WHERE meter_id = ANY($1)",
+        )
+        .bind(meter_ids)
+        .fetch_all(db)
+        .await?;
+
+        Ok(usages)
+    }
+
+    /// Atomically records one more use costing `price_msat` on every meter of
+    /// the token, unless it would exceed the uses or the spend of one of them,
+    /// or come `window_sec` or more after its first use.
+    ///
+    /// Returns the usage of the meters after the request, or `None` if the
+    /// token is exhausted, nothing being recorded then.
+    pub async fn record_use(
+        _ctx: &Ctx,
+        mm: &ModelManager,
+        meters: &[Meter],
+        price_msat: i64,
+    ) -> Result<Option<Vec<TokenUsage>>> {
+        let db = mm.db();
+        let now = chrono::Utc::now().timestamp();
+
+        let mut tx = db.begin().await?;
+        let mut usages = Vec::new();
+        for meter in meters {
+            let usage = sqlx::query_as(
+                "INSERT INTO token_usage (meter_id, uses, spent_msat, first_used_at)
+                 SELECT $1, 1, $2, $5 WHERE 1 <= $3 AND $2 <= $4 AND 0 < $6
+                 ON CONFLICT (meter_id) DO UPDATE
+                 SET uses = token_usage.uses + 1, spent_msat = token_usage.spent_msat + $2
+                 WHERE token_usage.uses < $3 AND token_usage.spent_msat + $2 <= $4
+                    AND $5 - token_usage.first_used_at < $6
+                 RETURNING meter_id, uses, spent_msat, first_used_at",
+            )
+            .bind(&meter.id)
+            .bind(price_msat)
+            .bind(meter.max_uses.unwrap_or(i64::MAX))
+            .bind(meter.max_spent_msat.unwrap_or(i64::MAX))
+            .bind(now)
+            .bind(meter.window_sec.unwrap_or(i64::MAX))
+            .fetch_optional(&mut *tx)
+            .await?;
+
+            match usage {
+                Some(usage) => usages.push(usage),
+                None => {
+                    tx.rollback().await?;
+                    return Ok(None);
+                }
+            }
+        }
+        tx.commit().await?;
+
+        Ok(Some(usages))
+    }
+
+    /// Takes back a use recorded by `record_use`, for a request the provider
+    /// failed to serve.
+    ///
+    /// Returns the usage of the meters after the refund. The start of the
+    /// time windows is kept.
+    pub async fn refund_use(
+        _ctx: &Ctx,
+        mm: &ModelManager,
+        meters: &[Meter],
+        price_msat: i64,
+    ) -> Result<Vec<TokenUsage>> {
+        let db = mm.db();
+
+        let mut tx = db.begin().await?;
+        let mut usages = Vec::new();
+        for meter in meters {
+            let usage = sqlx::query_as(
+                "UPDATE token_usage
+                 SET uses = uses - 1, spent_msat = spent_msat - $2
+                 WHERE meter_id = $1 AND 0 < uses AND $2 <= spent_msat
+                 RETURNING meter_id, uses, spent_msat, first_used_at",
+            )
+            .bind(&meter.id)
+            .bind(price_msat)
+            .fetch_optional(&mut *tx)
+            .await?;
+            usages.extend(usage);
+        }
+        tx.commit().await?;
+
+        Ok(usages)
+    }
+}
+// endregion: --- TokenUsageBmc
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use serial_test::serial;
+
+    use super::*;
+    use crate::_dev_utils;
+    use crate::config::bundles::BundleParams;
+    use crate::config::products::Product;
+    use crate::lightning::{Caveat, L402};
+
+    /// Meters of a freshly bought bundle of 2 uses.
+    fn fx_bundle_meters() -> Vec<Meter> {
+        let bundle = BundleParams {
+            id: "openai-2".to_string(),
+            name: "2 OpenAI calls".to_string(),
+            description: String::new(),
+            services: vec!["openai".to_string()],
+            uses: 2,
+            price_msat: 1000,
+        };
+        let meter_id = uuid::Uuid::new_v4().simple().to_string();
+
+        bundle
+            .caveats()
+            .iter()
+            .filter_map(|caveat| Meter::new(meter_id.clone(), caveat))
+            .collect()
+    }
+
+    #[test]
+    fn test_usage_left_exhausted() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_now = chrono::Utc::now().timestamp();
+        let fx_bundle_meters = fx_bundle_meters();
+        let fx_usage = |meter: &Meter, uses: i64, first_used_at: i64| TokenUsage {
+            meter_id: meter.id.clone(),
+            uses,
+            spent_msat: 0,
+            first_used_at,
+        };
+        let fx_pass_meters: Vec<Meter> = Meter::new("pass".to_string(), &Caveat::Window(60))
+            .into_iter()
+            .collect();
+
+        // -- Exec
+        let bundle_left = UsageLeft::new(
+            &fx_bundle_meters,
+            &[fx_usage(&fx_bundle_meters[0], 1, fx_now)],
+        );
+        let bundle_used = UsageLeft::new(
+            &fx_bundle_meters,
+            &[fx_usage(&fx_bundle_meters[0], 2, fx_now)],
+        );
+        let pass_left = UsageLeft::new(
+            &fx_pass_meters,
+            &[fx_usage(&fx_pass_meters[0], 5, fx_now - 30)],
+        );
+        let pass_expired = UsageLeft::new(
+            &fx_pass_meters,
+            &[fx_usage(&fx_pass_meters[0], 5, fx_now - 60)],
+        );
+
+        // -- Check
+        assert!(!bundle_left.is_exhausted(fx_now));
+        assert!(bundle_used.is_exhausted(fx_now));
+        assert!(!pass_left.is_exhausted(fx_now));
+        assert!(pass_expired.is_exhausted(fx_now));
+
+        Ok(())
+    }
+
+    async fn fx_record_use(
+        mm: &ModelManager,
+        meters: &[Meter],
+        price_msat: i64,
+    ) -> Result<Option<Vec<TokenUsage>>> {
+        Ok(TokenUsageBmc::record_use(&Ctx::root_ctx(), mm, meters, price_msat).await?)
+    }
+
+    #[serial]
+    #[tokio::test]
+    #[ignore = "needs the dev postgres, see _dev_utils"]
+    async fn test_bundle_runs_out_ok() -> Result<()> {
+        // -- Setup & Fixtures
+        let mm = _dev_utils::init_test().await;
+        let fx_meters = fx_bundle_meters();
+
+        // -- Exec
+        let first = fx_record_use(&mm, &fx_meters, 100).await?;
+        let second = fx_record_use(&mm, &fx_meters, 100).await?;
+        let third = fx_record_use(&mm, &fx_meters, 100).await?;
+
+        // -- Check
+        assert_eq!(
+            UsageLeft::new(&fx_meters, &first.unwrap_or_default()).uses,
+            Some(1)
+        );
+        assert_eq!(
+            UsageLeft::new(&fx_meters, &second.unwrap_or_default()).uses,
+            Some(0)
+        );
+        assert!(third.is_none());
+
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    #[ignore = "needs the dev postgres, see _dev_utils"]
+    async fn test_bundle_refund_ok() -> Result<()> {
+        // -- Setup & Fixtures
+        let mm = _dev_utils::init_test().await;
+        let ctx = Ctx::root_ctx();
+        let fx_meters = fx_bundle_meters();
+        fx_record_use(&mm, &fx_meters, 100).await?;
+        fx_record_use(&mm, &fx_meters, 100).await?;
+
+        // -- Exec
+        let refunded = TokenUsageBmc::refund_use(&ctx, &mm, &fx_meters, 100).await?;
+        let after_refund = fx_record_use(&mm, &fx_meters, 100).await?;
+
+        // -- Check
+        assert_eq!(UsageLeft::new(&fx_meters, &refunded).uses, Some(1));
+        assert!(after_refund.is_some());
+
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    #[ignore = "needs the dev postgres, see _dev_utils"]
+    async fn test_bundle_top_up_ok() -> Result<()> {
+        // -- Setup & Fixtures
+        let mm = _dev_utils::init_test().await;
+        let fx_meters = fx_bundle_meters();
+        let fx_top_up_meters = fx_bundle_meters();
+        for _ in 0..2 {
+            fx_record_use(&mm, &fx_meters, 100).await?;
+        }
+
+        // -- Exec
+        let exhausted = fx_record_use(&mm, &fx_meters, 100).await?;
+        let topped_up = fx_record_use(&mm, &fx_top_up_meters, 100).await?;
+
+        // -- Check
+        assert!(exhausted.is_none());
+        assert_eq!(
+            UsageLeft::new(&fx_top_up_meters, &topped_up.unwrap_or_default()).uses,
+            Some(1)
+        );
+
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    #[ignore = "needs the dev postgres, see _dev_utils"]
+    async fn test_delegate_spend_caps_ok() -> Result<()> {
+        // -- Setup & Fixtures
+        let mm = _dev_utils::init_test().await;
+        let fx_parent = L402::mint_comp("test", vec![Caveat::Budget(1000)]);
+        let fx_delegate = fx_parent.attenuate(vec![Caveat::MaxCost(300)]);
+        let fx_other_delegate = fx_parent.attenuate(vec![Caveat::MaxCost(400)]);
+        let (parent, delegate, other_delegate) = (
+            fx_parent.meters()?,
+            fx_delegate.meters()?,
+            fx_other_delegate.meters()?,
+        );
+
+        // -- Exec & Check
+        // Each delegate spends up to its own cap...
+        assert!(fx_record_use(&mm, &delegate, 200).await?.is_some());
+        assert!(fx_record_use(&mm, &delegate, 200).await?.is_none());
+        assert!(fx_record_use(&mm, &other_delegate, 300).await?.is_some());
+        // ...its spend counting against the budget of the parent.
+        let usages = fx_record_use(&mm, &parent, 500).await?.unwrap_or_default();
+        assert_eq!(UsageLeft::new(&parent, &usages).budget_msat, Some(0));
+        assert!(fx_record_use(&mm, &other_delegate, 1).await?.is_none());
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
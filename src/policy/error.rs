@@ -0,0 +1,75 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Json, Response};
+use serde::Serialize;
+use tracing::info;
+
+use crate::translate;
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[derive(Debug, Serialize, strum_macros::AsRefStr)]
+#[serde(tag = "type", content = "data")]
+pub enum Error {
+    // -- Forbidden
+    EndpointBanned { method: String, path: String },
+    ModelNotAllowed { model: String },
+
+    // -- Invalid
+    ModelMissing,
+    MaxTokensMissing { limit: u64 },
+    MaxTokensExceeded { max_tokens: u64, limit: u64 },
+    NExceeded { n: u64, limit: u64 },
+}
+
+// region:    --- Axum IntoResponse
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        info!("{:<12} - policy::Error {self:?}", "INTO_RES");
+
+        let (status, kind, message) = match &self {
+            Self::EndpointBanned { method, path } => (
+                StatusCode::FORBIDDEN,
+                "permission_error",
+                format!("{method} {path} is not available on this provider"),
+            ),
+            Self::ModelNotAllowed { model } => (
+                StatusCode::FORBIDDEN,
+                "permission_error",
+                format!("The model {model} is not available on this provider"),
+            ),
+            Self::ModelMissing => (
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                "model is required on this provider".to_string(),
+            ),
+            Self::MaxTokensMissing { limit } => (
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                format!("max_tokens is required, up to {limit}"),
+            ),
+            Self::MaxTokensExceeded { max_tokens, limit } => (
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                format!("max_tokens {max_tokens} is above the limit of {limit}"),
+            ),
+            Self::NExceeded { n, limit } => (
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                format!("n {n} is above the limit of {limit}"),
+            ),
+        };
+
+        (status, Json(translate::error(&message, kind))).into_response()
+    }
+}
+// endregion: --- Axum IntoResponse
+
+// region:    --- Error Boilerplate
+impl core::fmt::Display for Error {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::result::Result<(), core::fmt::Error> {
+        write!(fmt, "{self:?}")
+    }
+}
+
+impl std::error::Error for Error {}
+// endregion: --- Error Boilerplate
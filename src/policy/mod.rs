@@ -0,0 +1,525 @@
+// Guards of the requests to a provider, checked before they are priced and
+// forwarded, so buyers only reach the models and the limits the operator
+// priced.
+
+// region:    --- Modules
+
+mod error;
+
+pub use self::error::{Error, Result};
+
+use anyhow::anyhow;
+use axum::http::Method;
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::config::apis::{
+    apis_config, ApisConfig, FallbackParams, CHAT_COMPLETIONS_PATH, UNIFIED_ROUTE,
+};
+use crate::pricing::{body_model, body_params, matches_pattern, PricedRequest};
+
+// endregion: --- Modules
+
+/// Request body fields bounded by `max_tokens`.
+const MAX_TOKENS_PARAMS: [&str; 2] = ["max_tokens", "max_completion_tokens"];
+
+/// Default `max_tokens_paths`, the OpenAI and Anthropic generations.
+const DEFAULT_MAX_TOKENS_PATHS: [&str; 3] =
+    ["/v1/chat/completions", "/v1/completions", "/v1/messages"];
+
+/// Checks a request to `service` with the policy of the provider it is
+/// forwarded to, its body rewritten if the policy clamps it. Requests to
+/// other routes pass.
+pub async fn check_service_request(
+    service: &str,
+    req: &PricedRequest<'_>,
+) -> Result<Option<Vec<u8>>> {
+    let apis_config = apis_config();
+    if service == UNIFIED_ROUTE {
+        // Forwarded to the provider of the model, as an OpenAI chat completion
+        // whatever its format.
+        let Some(api_params) =
+            body_model(req.body).and_then(|model| apis_config.get_model_provider(&model))
+        else {
+            return Ok(None);
+        };
+        let req = PricedRequest {
+            path: CHAT_COMPLETIONS_PATH,
+            ..*req
+        };
+        return api_params.policy.check(&req).await;
+    }
+
+    if let Some(api_params) = apis_config.get_params(service) {
+        return api_params.policy.check(req).await;
+    }
+    match apis_config.get_fallback(service) {
+        Some(fallback) => check_fallback_request(&apis_config, &fallback, req).await,
+        None => Ok(None),
+    }
+}
+
+/// Checks a request to a fallback route with the policy of each of its
+/// targets, at their path and with their `model`, as it may reach any of
+/// them. A body clamped for a target is checked by the next ones, so the body
+/// forwarded passes them all.
+async fn check_fallback_request(
+    apis_config: &ApisConfig,
+    fallback: &FallbackParams,
+    req: &PricedRequest<'_>,
+) -> Result<Option<Vec<u8>>> {
+    let model = serde_json::from_slice::<Map<String, Value>>(req.body)
+        .ok()
+        .and_then(|mut body| body.remove("model"));
+
+    let mut rewritten: Option<Vec<u8>> = None;
+    for target in &fallback.targets {
+        let Some(api_params) = apis_config.get_provider(&target.provider) else {
+            continue;
+        };
+        let body = rewritten.as_deref().unwrap_or(req.body);
+        let target_body = target
+            .model
+            .as_ref()
+            .and_then(|model| with_model(body, Some(Value::from(model.as_str()))));
+        let target_req = PricedRequest {
+            path: &target.path,
+            body: target_body.as_deref().unwrap_or(body),
+            ..*req
+        };
+        let Some(clamped) = api_params.policy.check(&target_req).await? else {
+            continue;
+        };
+
+        // Forwarded with the `model` of the request, the target replacing it.
+        rewritten = match target.model {
+            Some(_) => Some(with_model(&clamped, model.clone()).unwrap_or(clamped)),
+            None => Some(clamped),
+        };
+    }
+
+    Ok(rewritten)
+}
+
+/// The JSON object body with its `model` replaced, or removed if `None`.
+fn with_model(body: &[u8], model: Option<Value>) -> Option<Vec<u8>> {
+    let mut body: Map<String, Value> = serde_json::from_slice(body).ok()?;
+    match model {
+        Some(model) => body.insert("model".to_string(), model),
+        None => body.remove("model"),
+    };
+
+    serde_json::to_vec(&body).ok()
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct PolicyParams {
+    /// Model patterns, exact or a prefix ending with `*`. All the models when
+    /// empty, else requests with a body and no `model` are rejected.
+    #[serde(default)]
+    pub allowed_models: Vec<String>,
+    pub max_tokens: Option<u64>,
+    /// Paths on which `max_tokens` or `max_completion_tokens` is required,
+    /// under `max_tokens`, exact or a prefix ending with `*`.
+    #[serde(default = "default_max_tokens_paths")]
+    pub max_tokens_paths: Vec<String>,
+    pub max_n: Option<u64>,
+    #[serde(default)]
+    pub over_limit: OverLimit,
+    #[serde(default)]
+    pub banned_endpoints: Vec<BannedEndpoint>,
+}
+
+fn default_max_tokens_paths() -> Vec<String> {
+    DEFAULT_MAX_TOKENS_PATHS.map(String::from).to_vec()
+}
+
+/// What is done with a request above `max_tokens` or `max_n`, or without the
+/// `max_tokens` it requires.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverLimit {
+    #[default]
+    Reject,
+    /// Lowered to the limit, or set to it when missing, in JSON bodies only,
+    /// forms being rejected.
+    Clamp,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct BannedEndpoint {
+    /// All the methods when missing.
+    pub method: Option<String>,
+    /// Path relative to the provider, exact or a prefix ending with `*`.
+    pub path: String,
+}
+
+impl BannedEndpoint {
+    fn matches(&self, method: &Method, path: &str) -> bool {
+        self.method
+            .as_deref()
+            .is_none_or(|banned| banned.eq_ignore_ascii_case(method.as_str()))
+            && matches_pattern(&self.path, &normalize_path(path))
+    }
+}
+
+/// Path as the upstream may resolve it: percent-decoded, until nothing is
+/// left to decode, without empty, `.` and `..` segments, so no spelling of a
+/// banned path gets past its ban.
+fn normalize_path(path: &str) -> String {
+    let mut decoded = path.to_string();
+    loop {
+        let next = percent_decode_str(&decoded)
+            .decode_utf8_lossy()
+            .into_owned();
+        if next == decoded {
+            break;
+        }
+        decoded = next;
+    }
+
+    let mut segments = Vec::new();
+    for segment in decoded.split(['/', '\\']) {
+        match segment {
+            "" | "." => (),
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    let mut normalized = format!("/{}", segments.join("/"));
+    if decoded.ends_with('/') && !segments.is_empty() {
+        normalized.push('/');
+    }
+
+    normalized
+}
+
+impl PolicyParams {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for endpoint in &self.banned_endpoints {
+            if let Some(method) = &endpoint.method {
+                method
+                    .parse::<Method>()
+                    .map_err(|_| anyhow!("policy: invalid method {method}"))?;
+            }
+            if !endpoint.path.starts_with('/') {
+                return Err(anyhow!(
+                    "policy: path {} must start with '/'",
+                    endpoint.path
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the endpoint and the JSON or multipart form body, the body
+    /// rewritten if clamped.
+    pub async fn check(&self, req: &PricedRequest<'_>) -> Result<Option<Vec<u8>>> {
+        if self
+            .banned_endpoints
+            .iter()
+            .any(|endpoint| endpoint.matches(req.method, req.path))
+        {
+            return Err(Error::EndpointBanned {
+                method: req.method.to_string(),
+                path: req.path.to_string(),
+            });
+        }
+        if self.allowed_models.is_empty() && self.max_tokens.is_none() && self.max_n.is_none() {
+            return Ok(None);
+        }
+
+        let Some(mut params) = body_params(req).await else {
+            return Ok(None);
+        };
+        if !self.allowed_models.is_empty() {
+            let Some(model) = params.get("model").and_then(Value::as_str) else {
+                return Err(Error::ModelMissing);
+            };
+            if !self
+                .allowed_models
+                .iter()
+                .any(|pattern| matches_pattern(pattern, model))
+            {
+                return Err(Error::ModelNotAllowed {
+                    model: model.to_string(),
+                });
+            }
+        }
+
+        // Only JSON bodies are rewritten, forms over a limit are rejected.
+        let clamp = self.over_limit == OverLimit::Clamp
+            && serde_json::from_slice::<Value>(req.body).is_ok_and(|body| body.is_object());
+        let mut clamped = false;
+        if let Some(limit) = self.max_tokens {
+            for name in MAX_TOKENS_PARAMS {
+                match number(&params, name) {
+                    Some(max_tokens) if max_tokens > limit && clamp => {
+                        params.insert(name.to_string(), limit.into());
+                        clamped = true;
+                    }
+                    Some(max_tokens) if max_tokens > limit => {
+                        return Err(Error::MaxTokensExceeded { max_tokens, limit });
+                    }
+                    _ => (),
+                }
+            }
+
+            let missing = MAX_TOKENS_PARAMS
+                .iter()
+                .all(|name| number(&params, name).is_none());
+            let required = self
+                .max_tokens_paths
+                .iter()
+                .any(|pattern| matches_pattern(pattern, req.path));
+            if missing && required && clamp {
+                params.insert(MAX_TOKENS_PARAMS[0].to_string(), limit.into());
+                clamped = true;
+            } else if missing && required {
+                return Err(Error::MaxTokensMissing { limit });
+            }
+        }
+        if let Some(limit) = self.max_n {
+            match number(&params, "n") {
+                Some(n) if n > limit && clamp => {
+                    params.insert("n".to_string(), limit.into());
+                    clamped = true;
+                }
+                Some(n) if n > limit => return Err(Error::NExceeded { n, limit }),
+                _ => (),
+            }
+        }
+
+        match clamped {
+            true => Ok(serde_json::to_vec(&params).ok()),
+            false => Ok(None),
+        }
+    }
+}
+
+/// JSON number, or number in a string as sent in forms. Fractions are
+/// rounded up, so they cannot pass below a limit.
+fn number(params: &Map<String, Value>, name: &str) -> Option<u64> {
+    let number = match params.get(name)? {
+        Value::Number(number) => number.as_f64()?,
+        Value::String(number) => number.trim().parse().ok()?,
+        _ => return None,
+    };
+
+    Some(number.max(0.0).ceil() as u64)
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use axum::http::HeaderMap;
+    use serde_json::json;
+
+    use super::*;
+
+    async fn fx_check(
+        policy: &PolicyParams,
+        method: Method,
+        path: &str,
+        body: &str,
+    ) -> super::Result<Option<Vec<u8>>> {
+        let req = PricedRequest {
+            method: &method,
+            path,
+            headers: &HeaderMap::new(),
+            body: body.as_bytes(),
+        };
+        policy.check(&req).await
+    }
+
+    fn fx_policy() -> Result<PolicyParams> {
+        Ok(toml::from_str(
+            r#"
+            allowed_models = ["gpt-4o-mini", "gpt-3.5-turbo*"]
+            max_tokens = 1000
+            max_n = 1
+            banned_endpoints = [{ path = "/v1/fine_tuning/*" }, { method = "DELETE", path = "/v1/files/*" }]
+            "#,
+        )?)
+    }
+
+    fn fx_clamp_policy() -> Result<PolicyParams> {
+        Ok(PolicyParams {
+            over_limit: OverLimit::Clamp,
+            ..fx_policy()?
+        })
+    }
+
+    const FX_PATH: &str = "/v1/chat/completions";
+
+    #[tokio::test]
+    async fn test_policy_check_models() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_policy = fx_policy()?;
+        let fx_chat =
+            |model: &str| json!({ "model": model, "messages": [], "max_tokens": 500 }).to_string();
+        let fx_no_model = json!({ "messages": [], "max_tokens": 500 }).to_string();
+
+        // -- Exec
+        let allowed = fx_check(
+            &fx_policy,
+            Method::POST,
+            FX_PATH,
+            &fx_chat("gpt-3.5-turbo-0125"),
+        )
+        .await;
+        let expensive = fx_check(&fx_policy, Method::POST, FX_PATH, &fx_chat("gpt-4")).await;
+        let missing = fx_check(&fx_policy, Method::POST, FX_PATH, &fx_no_model).await;
+
+        // -- Check
+        assert!(matches!(allowed, Ok(None)));
+        assert!(matches!(expensive, Err(Error::ModelNotAllowed { .. })));
+        assert!(matches!(missing, Err(Error::ModelMissing)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_policy_check_max_tokens() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_policy = fx_policy()?;
+        let fx_clamp_policy = fx_clamp_policy()?;
+        let fx_long =
+            json!({ "model": "gpt-4o-mini", "messages": [], "max_tokens": 4000 }).to_string();
+        let fx_unbounded = json!({ "model": "gpt-4o-mini", "messages": [] }).to_string();
+        let fx_embedding = json!({ "model": "gpt-4o-mini", "input": "Hello" }).to_string();
+
+        // -- Exec
+        let long = fx_check(&fx_policy, Method::POST, FX_PATH, &fx_long).await;
+        let unbounded = fx_check(&fx_policy, Method::POST, FX_PATH, &fx_unbounded).await;
+        let embedding = fx_check(&fx_policy, Method::POST, "/v1/embeddings", &fx_embedding).await;
+        let clamped = fx_check(&fx_clamp_policy, Method::POST, FX_PATH, &fx_long)
+            .await?
+            .unwrap_or_default();
+        let inserted = fx_check(&fx_clamp_policy, Method::POST, FX_PATH, &fx_unbounded)
+            .await?
+            .unwrap_or_default();
+
+        // -- Check
+        assert!(matches!(
+            long,
+            Err(Error::MaxTokensExceeded {
+                max_tokens: 4000,
+                limit: 1000
+            })
+        ));
+        assert!(matches!(
+            unbounded,
+            Err(Error::MaxTokensMissing { limit: 1000 })
+        ));
+        assert!(matches!(embedding, Ok(None)));
+        assert_eq!(
+            serde_json::from_slice::<Value>(&clamped)?["max_tokens"],
+            1000
+        );
+        assert_eq!(
+            serde_json::from_slice::<Value>(&inserted)?["max_tokens"],
+            1000
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_policy_check_banned_endpoints() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_policy = fx_policy()?;
+        let fx_banned = [
+            (Method::DELETE, "/v1/files/file-abc"),
+            (Method::POST, "/v1/fine_tuning/jobs"),
+            (Method::POST, "//v1/fine_tuning/jobs"),
+            (Method::POST, "/v1/./fine_tuning/jobs"),
+            (Method::POST, "/v1/models/../fine_tuning/jobs"),
+            (Method::POST, "/v1/fine%5Ftuning/jobs"),
+            (Method::POST, "/v1%2Ffine_tuning%2Fjobs"),
+            (Method::POST, "/v1/fine%255Ftuning/jobs"),
+        ];
+
+        // -- Exec & Check
+        for (method, path) in fx_banned {
+            let res = fx_check(&fx_policy, method, path, "").await;
+            assert!(
+                matches!(res, Err(Error::EndpointBanned { .. })),
+                "{path} should be banned"
+            );
+        }
+        let get = fx_check(&fx_policy, Method::GET, "/v1/files/file-abc", "").await;
+        assert!(matches!(get, Ok(None)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_check_fallback_request_target_models() -> Result<()> {
+        // -- Setup & Fixtures
+        #[derive(Deserialize)]
+        struct FxRoutes {
+            providers: Vec<crate::config::apis::ApiParams>,
+            fallbacks: Vec<FallbackParams>,
+        }
+        let fx_routes: FxRoutes = toml::from_str(
+            r#"
+            [[providers]]
+            name = "openai"
+            path = "/openai"
+            auth = "bearer"
+            policy = { allowed_models = ["gpt-*"], max_tokens = 2000, over_limit = "clamp" }
+
+            [[providers]]
+            name = "groq"
+            path = "/groq"
+            auth = "bearer"
+            policy = { allowed_models = ["mixtral-*"], max_tokens = 1000, over_limit = "clamp" }
+
+            [[fallbacks]]
+            name = "chat"
+            path = "/chat"
+            targets = [{ provider = "openai" }, { provider = "groq", model = "mixtral-8x7b" }]
+            "#,
+        )?;
+        let fx_apis_config = ApisConfig {
+            providers: fx_routes.providers,
+            fallbacks: Vec::new(),
+            models: Vec::new(),
+        };
+        let fx_fallback = &fx_routes.fallbacks[0];
+        let fx_headers = HeaderMap::new();
+        let fx_allowed = br#"{"model": "gpt-4o", "messages": []}"#;
+        let fx_refused = br#"{"model": "llama-3", "messages": []}"#;
+        let fx_req = |body: &'static [u8]| PricedRequest {
+            method: &Method::POST,
+            path: "/",
+            headers: &fx_headers,
+            body,
+        };
+
+        // -- Exec
+        let allowed = check_fallback_request(&fx_apis_config, fx_fallback, &fx_req(fx_allowed))
+            .await?
+            .unwrap_or_default();
+        let refused =
+            check_fallback_request(&fx_apis_config, fx_fallback, &fx_req(fx_refused)).await;
+
+        // -- Check
+        // Clamped by both targets, and sent with its own model.
+        let allowed: Value = serde_json::from_slice(&allowed)?;
+        assert_eq!(
+            (&allowed["model"], &allowed["max_tokens"]),
+            (&json!("gpt-4o"), &json!(1000))
+        );
+        assert!(matches!(refused, Err(Error::ModelNotAllowed { .. })));
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
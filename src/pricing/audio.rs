@@ -0,0 +1,591 @@
+// Pricing by the duration of an uploaded audio file, e.g. for transcriptions.
+// The duration is measured on the frames or packets the file holds, the
+// durations its headers declare being up to the client. The audio is never
+// decoded.
+
+use serde::{Deserialize, Serialize};
+
+use super::{multipart_file, PricedRequest};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PerSecondPricing {
+    #[serde(default)]
+    pub base_msat: u64,
+    /// Charged per started second.
+    pub msat_per_sec: u64,
+    /// Multipart field of the audio file.
+    #[serde(default = "default_field")]
+    pub field: String,
+}
+
+impl PerSecondPricing {
+    /// `None` if the request has no audio file of a measurable format, or if
+    /// its price overflows.
+    pub async fn price_msat(&self, req: &PricedRequest<'_>) -> Option<u64> {
+        let audio = multipart_file(req, &self.field).await?;
+        let duration_sec = duration_sec(&audio)?;
+
+        // Saturates, so the overflow is caught below.
+        let started_sec = duration_sec.ceil() as u64;
+        self.msat_per_sec
+            .checked_mul(started_sec)?
+            .checked_add(self.base_msat)
+    }
+}
+
+fn default_field() -> String {
+    "file".to_string()
+}
+
+/// Duration of a wav (PCM), mp3, ogg (Vorbis or Opus) or webm (Opus) file,
+/// `None` for the other formats.
+pub fn duration_sec(audio: &[u8]) -> Option<f64> {
+    let duration_sec = if audio.starts_with(b"RIFF") {
+        wav_duration_sec(audio)
+    } else if audio.starts_with(b"OggS") {
+        ogg_duration_sec(audio)
+    } else if audio.starts_with(&EBML_HEADER.to_be_bytes()) {
+        webm_duration_sec(audio)
+    } else {
+        mp3_duration_sec(audio)
+    }?;
+
+    (duration_sec.is_finite() && duration_sec >= 0.0).then_some(duration_sec)
+}
+
+// region:    --- Wav
+
+/// PCM, IEEE float, A-law and mu-law, whose size is proportional to their
+/// duration.
+const WAV_FORMATS: [u16; 4] = [1, 3, 6, 7];
+const WAV_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// Size of the data over the sample rate and the block size the decoders use,
+/// the byte rate of the header being unused by them.
+fn wav_duration_sec(audio: &[u8]) -> Option<f64> {
+    if audio.get(8..12)? != b"WAVE" {
+        return None;
+    }
+
+    let mut bytes_per_sec = None;
+    let mut pos = 12;
+    while let Some(chunk) = audio.get(pos..pos + 8) {
+        let size = u32::from_le_bytes(chunk[4..8].try_into().ok()?) as usize;
+        match &chunk[..4] {
+            b"fmt " => bytes_per_sec = Some(wav_bytes_per_sec(audio, pos + 8)?),
+            // The size of a streamed file may be left unset, the data then
+            // runs to the end of the file.
+            b"data" => {
+                let size = size.min(audio.len() - pos - 8);
+                return Some(size as f64 / bytes_per_sec? as f64);
+            }
+            _ => (),
+        }
+        // Chunks are padded to an even size.
+        pos = pos.checked_add(8 + size + size % 2)?;
+    }
+
+    None
+}
+
+/// Bytes per second of the `fmt ` chunk starting at `fmt`, `None` for the
+/// compressed formats and the inconsistent blocks.
+fn wav_bytes_per_sec(audio: &[u8], fmt: usize) -> Option<u64> {
+    let format = match le_u16(audio, fmt)? {
+        // The format is the first 2 bytes of the sub format GUID.
+        WAV_FORMAT_EXTENSIBLE => le_u16(audio, fmt + 24)?,
+        format => format,
+    };
+    let channels = le_u16(audio, fmt + 2)? as u64;
+    let sample_rate = le_u32(audio, fmt + 4)? as u64;
+    let block_align = le_u16(audio, fmt + 12)? as u64;
+    let bits = le_u16(audio, fmt + 14)? as u64;
+    if !WAV_FORMATS.contains(&format)
+        || block_align == 0
+        || block_align != channels * bits.div_ceil(8)
+    {
+        return None;
+    }
+
+    Some(sample_rate * block_align).filter(|bytes_per_sec| *bytes_per_sec > 0)
+}
+
+// endregion: --- Wav
+
+// region:    --- Mp3
+
+/// Longest run of bytes skipped between the ID3 tag and the first frame.
+const MP3_MAX_SYNC_SKIP: usize = 4096;
+const MP3_SAMPLE_RATES: [[u32; 3]; 2] = [[44100, 48000, 32000], [22050, 24000, 16000]];
+/// Layer III bitrates in kbps, for MPEG 1 then MPEG 2 and 2.5.
+const MP3_BITRATES: [[u32; 15]; 2] = [
+    [
+        0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+    ],
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+
+/// Samples of all the frames of the file, skipping what is between them as
+/// decoders resync, rather than the frame count of a Xing header.
+fn mp3_duration_sec(audio: &[u8]) -> Option<f64> {
+    let start = match audio.get(..3) {
+        Some(b"ID3") => {
+            let size = audio
+                .get(6..10)?
+                .iter()
+                .fold(0, |size, byte| (size << 7) | (*byte as usize & 0x7f));
+            let footer = if audio[5] & 0x10 != 0 { 10 } else { 0 };
+            10 + size + footer
+        }
+        _ => 0,
+    };
+    // Without an ID3 tag the file starts with a frame, so that other formats
+    // are not taken for mp3. A frame followed by another one, to skip the
+    // false syncs.
+    let max_skip = if start == 0 { 0 } else { MP3_MAX_SYNC_SKIP };
+    let mut pos = (start..audio.len().min(start + max_skip + 1)).find(|pos| {
+        mp3_frame(&audio[*pos..]).is_some_and(|frame| {
+            let next = pos + frame.len;
+            next >= audio.len() || mp3_frame(&audio[next..]).is_some()
+        })
+    })?;
+
+    let mut duration_sec = 0.0;
+    while pos < audio.len() {
+        match mp3_frame(&audio[pos..]) {
+            Some(frame) => {
+                duration_sec += frame.samples as f64 / frame.sample_rate as f64;
+                pos += frame.len;
+            }
+            None => pos += 1,
+        }
+    }
+
+    Some(duration_sec)
+}
+
+struct Mp3Frame {
+    len: usize,
+    sample_rate: u32,
+    samples: u32,
+}
+
+/// Layer III frame header at the start of `audio`.
+fn mp3_frame(audio: &[u8]) -> Option<Mp3Frame> {
+    let header = audio.get(..4)?;
+    if header[0] != 0xff || header[1] & 0xe0 != 0xe0 {
+        return None;
+    }
+    let version = (header[1] >> 3) & 0b11;
+    let layer = (header[1] >> 1) & 0b11;
+    let bitrate_index = (header[2] >> 4) as usize;
+    let sample_rate_index = ((header[2] >> 2) & 0b11) as usize;
+    // Version 1 is reserved, layer 1 is Layer III.
+    if version == 1 || layer != 1 || bitrate_index == 0 || bitrate_index == 15 {
+        return None;
+    }
+
+    let mpeg1 = version == 3;
+    let table = if mpeg1 { 0 } else { 1 };
+    let mut sample_rate = *MP3_SAMPLE_RATES[table].get(sample_rate_index)?;
+    // MPEG 2.5
+    if version == 0 {
+        sample_rate /= 2;
+    }
+    let padding = ((header[2] >> 1) & 1) as u32;
+    let bitrate_kbps = MP3_BITRATES[table][bitrate_index];
+    let samples = if mpeg1 { 1152 } else { 576 };
+
+    Some(Mp3Frame {
+        len: (samples / 8 * bitrate_kbps * 1000 / sample_rate + padding) as usize,
+        sample_rate,
+        samples,
+    })
+}
+
+// endregion: --- Mp3
+
+// region:    --- Ogg
+
+/// Bytes kept of the first packet, enough for the identification header.
+const OGG_HEADER_LEN: usize = 32;
+
+/// Samples of the audio packets of the first stream, rather than the granule
+/// positions of its pages.
+fn ogg_duration_sec(audio: &[u8]) -> Option<f64> {
+    let mut header = Vec::new();
+    let mut packets = 0;
+    let mut opus_samples_sum = 0;
+    let mut vorbis_audio_packets = 0;
+    ogg_packets(audio, |packet| {
+        if packets == 0 {
+            header = packet.to_vec();
+        }
+        // Opus has 2 header packets, Vorbis 3, all with their first bit set.
+        if packets >= 2 {
+            opus_samples_sum += opus_samples(packet);
+        }
+        if packets >= 3 && packet.first().is_some_and(|byte| byte & 1 == 0) {
+            vorbis_audio_packets += 1;
+        }
+        packets += 1;
+    });
+
+    if header.starts_with(b"OpusHead") {
+        // Opus always counts at 48 kHz, after the pre-skip.
+        let pre_skip = le_u16(&header, 10)? as u64;
+        Some(opus_samples_sum.saturating_sub(pre_skip) as f64 / 48000.0)
+    } else if header.starts_with(b"\x01vorbis") {
+        vorbis_duration_sec(&header, vorbis_audio_packets)
+    } else {
+        None
+    }
+}
+
+/// At most half a long block per audio packet, the block size of each packet
+/// being set by modes which are not parsed.
+fn vorbis_duration_sec(header: &[u8], audio_packets: u64) -> Option<f64> {
+    let sample_rate = le_u32(header, 12).filter(|rate| *rate > 0)?;
+    let long_block_exp = header.get(28)? >> 4;
+    if !(6..=13).contains(&long_block_exp) {
+        return None;
+    }
+
+    let samples = audio_packets * (1u64 << long_block_exp) / 2;
+    Some(samples as f64 / sample_rate as f64)
+}
+
+/// Calls `on_packet` with the first bytes of each packet of the first logical
+/// stream, finding the pages after any junk as decoders resync.
+fn ogg_packets(audio: &[u8], mut on_packet: impl FnMut(&[u8])) {
+    let mut packet = Vec::new();
+    let mut serial = None;
+    let mut pos = 0;
+    while let Some(found) = audio[pos..].windows(4).position(|bytes| bytes == b"OggS") {
+        pos += found;
+        let (Some(page_serial), Some(&segments)) =
+            (audio.get(pos + 14..pos + 18), audio.get(pos + 26))
+        else {
+            return;
+        };
+        let data = pos + 27 + segments as usize;
+        let Some(lacing) = audio.get(pos + 27..data) else {
+            return;
+        };
+        let page_end = data + lacing.iter().map(|size| *size as usize).sum::<usize>();
+        if *serial.get_or_insert(page_serial) == page_serial {
+            let mut segment_pos = data;
+            for &size in lacing {
+                let segment = audio.get(segment_pos..).unwrap_or_default();
+                let kept = OGG_HEADER_LEN
+                    .saturating_sub(packet.len())
+                    .min(segment.len())
+                    .min(size as usize);
+                packet.extend(&segment[..kept]);
+                segment_pos += size as usize;
+                // A packet ends on a segment shorter than 255 bytes.
+                if size < 255 {
+                    on_packet(&packet);
+                    packet.clear();
+                }
+            }
+        }
+        pos = page_end.min(audio.len());
+    }
+}
+
+/// Samples at 48 kHz of an Opus packet, from its table of contents.
+fn opus_samples(packet: &[u8]) -> u64 {
+    let Some(&toc) = packet.first() else {
+        return 0;
+    };
+    let config = (toc >> 3) as usize;
+    let frame_samples = match config {
+        // SILK, 10 to 60 ms
+        0..=11 => [480, 960, 1920, 2880][config % 4],
+        // Hybrid, 10 or 20 ms
+        12..=15 => [480, 960][config % 2],
+        // CELT, 2.5 to 20 ms
+        _ => [120, 240, 480, 960][config % 4],
+    };
+    let frames = match toc & 0b11 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => packet.get(1).map_or(0, |count| count & 0x3f) as u64,
+    };
+
+    frame_samples * frames
+}
+
+// endregion: --- Ogg
+
+// region:    --- Webm
+
+const EBML_HEADER: u32 = 0x1a45_dfa3;
+const SEGMENT: u32 = 0x1853_8067;
+const TRACKS: u32 = 0x1654_ae6b;
+const TRACK_ENTRY: u32 = 0xae;
+const CODEC_ID: u32 = 0x86;
+const CLUSTER: u32 = 0x1f43_b675;
+const BLOCK_GROUP: u32 = 0xa0;
+const BLOCK: u32 = 0xa1;
+const SIMPLE_BLOCK: u32 = 0xa3;
+
+/// Samples of the Opus packets of the blocks of a single track file, rather
+/// than its `Duration` or the timecodes of its blocks.
+fn webm_duration_sec(audio: &[u8]) -> Option<f64> {
+    let mut tracks = 0;
+    let mut codec_id = None;
+    let mut samples = 0;
+    let mut pos = 0;
+    while pos < audio.len() {
+        let Some((id, id_len)) = ebml_vint(audio, pos, true) else {
+            break;
+        };
+        let Some((size, size_len)) = ebml_vint(audio, pos + id_len, false) else {
+            break;
+        };
+        let data = pos + id_len + size_len;
+        // The elements holding the blocks are walked into, so their size
+        // matters not, as the unknown size of live recordings.
+        match id as u32 {
+            SEGMENT | TRACKS | CLUSTER | BLOCK_GROUP => {
+                pos = data;
+                continue;
+            }
+            TRACK_ENTRY => {
+                tracks += 1;
+                pos = data;
+                continue;
+            }
+            _ => (),
+        }
+
+        // All the bits set mark an unknown size, only allowed above.
+        if size == (1 << (7 * size_len)) - 1 {
+            return None;
+        }
+        let data_end = data.checked_add(size as usize)?.min(audio.len());
+        match id as u32 {
+            CODEC_ID => codec_id = audio.get(data..data_end),
+            BLOCK | SIMPLE_BLOCK => samples += webm_block_samples(audio.get(data..data_end)?)?,
+            _ => (),
+        }
+        pos = data_end;
+    }
+
+    if tracks != 1 || codec_id != Some(b"A_OPUS") {
+        return None;
+    }
+
+    Some(samples as f64 / 48000.0)
+}
+
+/// Samples of the Opus packet of a block, `None` for the laced blocks.
+fn webm_block_samples(block: &[u8]) -> Option<u64> {
+    let (_, track_len) = ebml_vint(block, 0, false)?;
+    let flags = *block.get(track_len + 2)?;
+    if flags & 0b110 != 0 {
+        return None;
+    }
+
+    Some(opus_samples(block.get(track_len + 3..)?))
+}
+
+/// Variable length integer at `pos` and its length, with its length marker
+/// for the element ids.
+fn ebml_vint(audio: &[u8], pos: usize, keep_marker: bool) -> Option<(u64, usize)> {
+    let first = *audio.get(pos)?;
+    let len = first.leading_zeros() as usize + 1;
+    if len > 8 {
+        return None;
+    }
+
+    let first = match keep_marker {
+        true => first as u64,
+        false => (first as u64) & (0xff >> len),
+    };
+    let value = audio
+        .get(pos + 1..pos + len)?
+        .iter()
+        .fold(first, |value, byte| (value << 8) | *byte as u64);
+
+    Some((value, len))
+}
+
+// endregion: --- Webm
+
+// region:    --- Helpers
+
+fn le_u16(bytes: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(pos..pos + 2)?.try_into().ok()?,
+    ))
+}
+
+fn le_u32(bytes: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(pos..pos + 4)?.try_into().ok()?,
+    ))
+}
+
+// endregion: --- Helpers
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::{anyhow, Result};
+    use axum::http::Method;
+
+    use super::*;
+
+    /// Mono 8 kHz 8 bit wav of `sec` seconds of silence.
+    fn fx_wav(sec: u32) -> Vec<u8> {
+        let data_size = 8000 * sec;
+        let mut wav = Vec::new();
+        wav.extend(b"RIFF");
+        wav.extend((36 + data_size).to_le_bytes());
+        wav.extend(b"WAVEfmt ");
+        wav.extend(16u32.to_le_bytes());
+        wav.extend(1u16.to_le_bytes()); // PCM
+        wav.extend(1u16.to_le_bytes()); // channels
+        wav.extend(8000u32.to_le_bytes()); // sample rate
+        wav.extend(8000u32.to_le_bytes()); // byte rate
+        wav.extend(1u16.to_le_bytes()); // block align
+        wav.extend(8u16.to_le_bytes()); // bits per sample
+        wav.extend(b"data");
+        wav.extend(data_size.to_le_bytes());
+        wav.extend(vec![0x80; data_size as usize]);
+        wav
+    }
+
+    #[tokio::test]
+    async fn test_per_second_pricing_multipart_wav() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_pricing = PerSecondPricing {
+            base_msat: 100,
+            msat_per_sec: 360,
+            field: default_field(),
+        };
+        let mut fx_body = b"--b\r\n\
+            Content-Disposition: form-data; name=\"model\"\r\n\r\n\
+            whisper-1\r\n\
+            --b\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"a.wav\"\r\n\
+            Content-Type: audio/wav\r\n\r\n"
+            .to_vec();
+        fx_body.extend(fx_wav(3));
+        fx_body.extend(b"\r\n--b--\r\n");
+        let fx_headers = [("content-type", "multipart/form-data; boundary=b")]
+            .into_iter()
+            .map(|(name, value)| Ok((name.parse()?, value.parse()?)))
+            .collect::<Result<_>>()?;
+
+        // -- Exec
+        let price_msat = fx_pricing
+            .price_msat(&PricedRequest {
+                method: &Method::POST,
+                path: "/v1/audio/transcriptions",
+                headers: &fx_headers,
+                body: &fx_body,
+            })
+            .await;
+
+        // -- Check
+        assert_eq!(price_msat, Some(100 + 3 * 360));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_duration_webm_and_mp3() -> Result<()> {
+        // -- Setup & Fixtures
+        let mut fx_webm = vec![0x1a, 0x45, 0xdf, 0xa3, 0x80]; // empty EBML header
+        fx_webm.extend([
+            0x18, 0x53, 0x80, 0x67, 0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        ]);
+        // Info declaring 1 ms, ignored.
+        fx_webm.extend([0x15, 0x49, 0xa9, 0x66, 0x87, 0x44, 0x89, 0x84]);
+        fx_webm.extend(1f32.to_be_bytes());
+        // Tracks of a single Opus track.
+        fx_webm.extend([0x16, 0x54, 0xae, 0x6b, 0x8a, 0xae, 0x88, 0x86, 0x86]);
+        fx_webm.extend(b"A_OPUS");
+        // Live recorded cluster, of 125 blocks of 20 ms.
+        fx_webm.extend([
+            0x1f, 0x43, 0xb6, 0x75, 0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        ]);
+        fx_webm.extend([0xe7, 0x81, 0x00]);
+        for _ in 0..125 {
+            fx_webm.extend([0xa3, 0x85, 0x81, 0x00, 0x00, 0x80, 0xf8]);
+        }
+        // 100 frames of MPEG 1 Layer III, 128 kbps, 44.1 kHz, 417 bytes each.
+        let mut fx_mp3 = Vec::new();
+        for _ in 0..100 {
+            fx_mp3.extend([0xff, 0xfb, 0x90, 0x00]);
+            fx_mp3.resize(fx_mp3.len() + 413, 0);
+        }
+
+        // -- Exec
+        let webm = duration_sec(&fx_webm).ok_or(anyhow!("webm not measured"))?;
+        let mp3 = duration_sec(&fx_mp3).ok_or(anyhow!("mp3 not measured"))?;
+
+        // -- Check
+        assert!((webm - 2.5).abs() < 1e-9);
+        assert!((mp3 - 100.0 * 1152.0 / 44100.0).abs() < 1e-9);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_duration_ogg_opus() -> Result<()> {
+        // -- Setup & Fixtures
+        // Page with a granule position of 0, ignored.
+        let fx_page = |packets: &[Vec<u8>]| {
+            let mut page = b"OggS\0\0".to_vec();
+            page.extend([0; 8]); // granule position
+            page.extend(7u32.to_le_bytes()); // serial
+            page.extend([0; 8]); // sequence and checksum
+            page.push(packets.len() as u8);
+            page.extend(packets.iter().map(|packet| packet.len() as u8));
+            page.extend(packets.concat());
+            page
+        };
+        let mut fx_head = b"OpusHead\x01\x01".to_vec();
+        fx_head.extend(312u16.to_le_bytes()); // pre-skip
+        fx_head.extend(48000u32.to_le_bytes());
+        fx_head.extend([0, 0, 0]);
+        let mut fx_ogg = fx_page(&[fx_head]);
+        fx_ogg.extend(fx_page(&[b"OpusTags".to_vec()]));
+        // 50 packets of 20 ms, past some junk.
+        fx_ogg.extend(b"junk");
+        fx_ogg.extend(fx_page(&vec![vec![0xf8, 0x00, 0x00]; 50]));
+
+        // -- Exec
+        let ogg = duration_sec(&fx_ogg).ok_or(anyhow!("ogg not measured"))?;
+
+        // -- Check
+        assert!((ogg - (50.0 * 960.0 - 312.0) / 48000.0).abs() < 1e-9);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_duration_err_unmeasurable() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_flac = b"fLaC\0\0\0\x22".to_vec();
+        // ADPCM, whose size is not proportional to its duration.
+        let mut fx_adpcm_wav = fx_wav(3);
+        fx_adpcm_wav[20..22].copy_from_slice(&2u16.to_le_bytes());
+        // A byte rate declaring a thousandth of the duration, ignored.
+        let mut fx_lying_wav = fx_wav(3);
+        fx_lying_wav[28..32].copy_from_slice(&8_000_000u32.to_le_bytes());
+
+        // -- Exec & Check
+        assert_eq!(duration_sec(&fx_flac), None);
+        assert_eq!(duration_sec(&fx_adpcm_wav), None);
+        assert_eq!(duration_sec(&fx_lying_wav), Some(3.0));
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
@@ -0,0 +1,264 @@
+// Byte pair encoding tokenizer, counting the tokens of a text with the vocab
+// of a model, in the `.tiktoken` format of OpenAI.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use base64_url::base64::engine::general_purpose::STANDARD;
+use base64_url::base64::Engine as _;
+use once_cell::sync::Lazy;
+
+use super::{Error, Result};
+
+/// Vocabs by path, loaded once per config load.
+static VOCABS: Lazy<Mutex<HashMap<String, Arc<Bpe>>>> = Lazy::new(Default::default);
+
+#[derive(Debug)]
+pub struct Bpe {
+    /// Rank of each token, the lowest ranked pairs merging first.
+    ranks: HashMap<Vec<u8>, u32>,
+}
+
+impl Bpe {
+    /// Reads the vocab at `path`, replacing the cached one.
+    pub fn load(path: &str) -> Result<Arc<Self>> {
+        let vocab = std::fs::read_to_string(path).map_err(|e| Error::VocabRead {
+            path: path.to_string(),
+            error: e.to_string(),
+        })?;
+        let bpe = Arc::new(
+            Self::from_tiktoken(&vocab).map_err(|line| Error::VocabInvalid {
+                path: path.to_string(),
+                line,
+            })?,
+        );
+
+        let mut vocabs = VOCABS.lock().unwrap_or_else(|e| e.into_inner());
+        vocabs.insert(path.to_string(), bpe.clone());
+
+        Ok(bpe)
+    }
+
+    /// Cached vocab at `path`, read on first use.
+    pub fn cached(path: &str) -> Result<Arc<Self>> {
+        let cached = VOCABS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(path)
+            .cloned();
+        match cached {
+            Some(bpe) => Ok(bpe),
+            None => Self::load(path),
+        }
+    }
+
+    /// Lines of `<base64 token> <rank>`, the number of the invalid line as
+    /// error.
+    fn from_tiktoken(vocab: &str) -> core::result::Result<Self, usize> {
+        let mut ranks = HashMap::new();
+        for (index, line) in vocab.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (token, rank) = line.split_once(' ').ok_or(index + 1)?;
+            let token = STANDARD.decode(token).map_err(|_| index + 1)?;
+            let rank = rank.trim().parse().map_err(|_| index + 1)?;
+            ranks.insert(token, rank);
+        }
+
+        Ok(Self { ranks })
+    }
+
+    pub fn count_tokens(&self, text: &str) -> u64 {
+        pre_tokenize(text)
+            .into_iter()
+            .map(|piece| self.count_piece_tokens(piece.as_bytes()))
+            .sum()
+    }
+
+    /// Merges the lowest ranked pair of parts first, the leftmost on ties,
+    /// the pairs being kept in a heap so long pieces take `n log n`.
+    fn count_piece_tokens(&self, piece: &[u8]) -> u64 {
+        if piece.len() <= 1 || self.ranks.contains_key(piece) {
+            return piece.len().min(1) as u64;
+        }
+
+        // Parts as a linked list of their starts, `next` of the last one
+        // being the end of the piece.
+        let end = piece.len();
+        let mut next: Vec<usize> = (1..=end).collect();
+        let mut prev: Vec<Option<usize>> = (0..end).map(|i| i.checked_sub(1)).collect();
+        let mut merged = vec![false; end];
+        let pair_rank = |start: usize, next: &[usize]| {
+            let mid = next[start];
+            let pair_end = *next.get(mid)?;
+            self.ranks.get(&piece[start..pair_end]).copied()
+        };
+
+        let mut pairs: BinaryHeap<Reverse<(u32, usize)>> = (0..end)
+            .filter_map(|start| Some(Reverse((pair_rank(start, &next)?, start))))
+            .collect();
+        let mut parts = end as u64;
+        while let Some(Reverse((rank, start))) = pairs.pop() {
+            // Stale pairs, whose parts merged since.
+            if merged[start] || pair_rank(start, &next) != Some(rank) {
+                continue;
+            }
+
+            let mid = next[start];
+            merged[mid] = true;
+            next[start] = next[mid];
+            if let Some(following) = prev.get_mut(next[start]) {
+                *following = Some(start);
+            }
+            parts -= 1;
+
+            if let Some(rank) = pair_rank(start, &next) {
+                pairs.push(Reverse((rank, start)));
+            }
+            if let Some(previous) = prev[start] {
+                if let Some(rank) = pair_rank(previous, &next) {
+                    pairs.push(Reverse((rank, previous)));
+                }
+            }
+        }
+
+        parts
+    }
+}
+
+/// Upper bound when no vocab is set, a byte level BPE never making more
+/// tokens than bytes, whatever the script of the text.
+pub fn estimate_tokens(text: &str) -> u64 {
+    text.len() as u64
+}
+
+// region:    --- Pre Tokenizer
+
+/// Splits the text as the `cl100k_base` pattern of OpenAI does, the merges
+/// never crossing the pieces: contractions, words with an optional leading
+/// non letter, up to 3 digits, punctuation with an optional leading space,
+/// and whitespace.
+fn pre_tokenize(text: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let at = |i: usize| chars.get(i).map(|(_, c)| *c);
+    let is_newline = |c: char| c == '\r' || c == '\n';
+    let is_other = |c: char| !c.is_alphabetic() && !c.is_numeric();
+
+    let mut pieces = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i].1;
+        let next = at(i + 1);
+        let mut end = i + 1;
+
+        if c == '\'' && contraction_len(&chars[i + 1..]) > 0 {
+            end = i + 1 + contraction_len(&chars[i + 1..]);
+        } else if c.is_alphabetic()
+            || (is_other(c) && !is_newline(c) && next.is_some_and(char::is_alphabetic))
+        {
+            while at(end).is_some_and(char::is_alphabetic) {
+                end += 1;
+            }
+        } else if c.is_numeric() {
+            while end < i + 3 && at(end).is_some_and(char::is_numeric) {
+                end += 1;
+            }
+        } else if !c.is_whitespace() || (c == ' ' && next.is_some_and(|n| !n.is_whitespace())) {
+            // The leading space is taken, as a word would take it.
+            while at(end).is_some_and(|n| is_other(n) && !n.is_whitespace()) {
+                end += 1;
+            }
+            while at(end).is_some_and(is_newline) {
+                end += 1;
+            }
+        } else {
+            while at(end).is_some_and(char::is_whitespace) {
+                end += 1;
+            }
+            let last_newline = (i..end).rev().find(|j| is_newline(chars[*j].1));
+            if let Some(last_newline) = last_newline {
+                end = last_newline + 1;
+            } else if end < chars.len() && end - i > 1 {
+                // The last space goes with the next piece.
+                end -= 1;
+            }
+        }
+
+        let start_byte = chars[i].0;
+        let end_byte = chars.get(end).map_or(text.len(), |(byte, _)| *byte);
+        pieces.push(&text[start_byte..end_byte]);
+        i = end;
+    }
+
+    pieces
+}
+
+/// Length of the `'s`, `'t`, `'re`, `'ve`, `'m`, `'ll` or `'d` following an
+/// apostrophe, 0 if none.
+fn contraction_len(chars: &[(usize, char)]) -> usize {
+    let lower = |i: usize| chars.get(i).map(|(_, c)| c.to_ascii_lowercase());
+    match (lower(0), lower(1)) {
+        (Some('r'), Some('e')) | (Some('v'), Some('e')) | (Some('l'), Some('l')) => 2,
+        (Some('s' | 't' | 'm' | 'd'), _) => 1,
+        _ => 0,
+    }
+}
+
+// endregion: --- Pre Tokenizer
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_pre_tokenize_cl100k() -> Result<()> {
+        // -- Exec
+        let pieces = pre_tokenize("Hello world, it's 12345!\n\n  Bye  now");
+
+        // -- Check
+        assert_eq!(
+            pieces,
+            [
+                "Hello", " world", ",", " it", "'s", " ", "123", "45", "!\n\n", " ", " Bye", " ",
+                " now"
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bpe_count_tokens() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_vocab = [
+            (" ", 0),
+            ("a", 1),
+            ("b", 2),
+            ("c", 3),
+            ("ab", 4),
+            ("abc", 5),
+            (" a", 6),
+        ]
+        .iter()
+        .map(|(token, rank)| format!("{} {rank}", STANDARD.encode(token)))
+        .collect::<Vec<_>>()
+        .join("\n");
+        let bpe = Bpe::from_tiktoken(&fx_vocab).map_err(|line| anyhow::anyhow!("line {line}"))?;
+
+        // -- Exec & Check
+        // "abcab": both "ab" merge, then "abc".
+        assert_eq!(bpe.count_tokens("abcab"), 2);
+        // " abc": "ab" ranks before " a", leaving " " and "abc".
+        assert_eq!(bpe.count_tokens(" abc"), 2);
+        // A long piece merges in n log n.
+        assert_eq!(bpe.count_tokens(&"ab".repeat(50_000)), 50_000);
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
@@ -0,0 +1,80 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Json, Response};
+use serde::Serialize;
+use tracing::info;
+
+use crate::translate;
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[derive(Debug, Serialize)]
+pub enum Error {
+    // -- Vocab
+    VocabRead {
+        path: String,
+        error: String,
+    },
+    VocabInvalid {
+        path: String,
+        line: usize,
+    },
+
+    // -- Expr
+    ExprParse {
+        expr: String,
+        position: usize,
+        message: String,
+    },
+    ExprEval(String),
+
+    // -- Request
+    RequestUnpriced {
+        path: String,
+    },
+    ModelUnrouted {
+        model: String,
+    },
+}
+
+// region:    --- Axum IntoResponse
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        info!("{:<12} - pricing::Error {self:?}", "INTO_RES");
+
+        let (status, kind, message) = match &self {
+            Self::RequestUnpriced { path } => (
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                format!("The request to {path} lacks what it is priced on"),
+            ),
+            Self::ModelUnrouted { model } => (
+                StatusCode::NOT_FOUND,
+                "invalid_request_error",
+                format!("no provider for the model {model}"),
+            ),
+            Self::ExprEval(message) => (
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                format!("The request could not be priced: {message}"),
+            ),
+            _ => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "api_error",
+                "The request could not be priced".to_string(),
+            ),
+        };
+
+        (status, Json(translate::error(&message, kind))).into_response()
+    }
+}
+// endregion: --- Axum IntoResponse
+
+// region:    --- Error Boilerplate
+impl core::fmt::Display for Error {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::result::Result<(), core::fmt::Error> {
+        write!(fmt, "{self:?}")
+    }
+}
+
+impl std::error::Error for Error {}
+// endregion: --- Error Boilerplate
@@ -0,0 +1,493 @@
+// Pricing by an expression over the request, for the rules a table of prices
+// cannot express. The language has no loops nor user functions, so an
+// expression runs in time bounded by its size and the size of the request.
+
+// region:    --- Modules
+
+mod parser;
+
+use std::borrow::Cow;
+use std::cell::OnceCell;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tracing::debug;
+
+use self::parser::{parse, BinaryOp, Func, Node, UnaryOp};
+use super::bpe::Bpe;
+use super::tokens::{counter, prompt_tokens};
+use super::{body_params, Error, PricedRequest, Result};
+
+// endregion: --- Modules
+
+static NULL: Value = Value::Null;
+
+/// Price in msat computed by `expr`, rounded up, and 0 if negative.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ExprPricing {
+    pub expr: PricingExpr,
+    /// `.tiktoken` vocab counting `input_tokens` and `tokens(...)`, else they
+    /// are estimated from the length of the text.
+    pub vocab: Option<String>,
+}
+
+impl ExprPricing {
+    /// Reads the vocab, so a bad one fails the config load.
+    pub fn load_vocab(&self) -> Result<()> {
+        if let Some(vocab) = &self.vocab {
+            Bpe::load(vocab)?;
+        }
+
+        Ok(())
+    }
+
+    /// Fails if the expression fails on the request, e.g. on a missing field,
+    /// rather than falling back to another price.
+    pub async fn price_msat(&self, req: &PricedRequest<'_>) -> Result<u64> {
+        let scope = Scope::new(req, self.vocab.as_deref()).await;
+        self.expr.price_msat(&scope).inspect_err(|e| {
+            debug!("{:<12} - expr {:?}: {}", "PRICING", self.expr.source, e);
+        })
+    }
+}
+
+/// Expression parsed on config load, so a bad one fails the load.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PricingExpr {
+    source: String,
+    node: Node,
+}
+
+impl PricingExpr {
+    pub fn parse(source: &str) -> Result<Self> {
+        Ok(Self {
+            source: source.to_string(),
+            node: parse(source)?,
+        })
+    }
+
+    pub fn eval(&self, scope: &Scope) -> Result<Value> {
+        eval(&self.node, scope).map(Cow::into_owned)
+    }
+
+    pub fn price_msat(&self, scope: &Scope) -> Result<u64> {
+        match self.eval(scope)? {
+            Value::Number(price) => {
+                let price = price.as_f64().unwrap_or_default().ceil().max(0.0);
+                // Past the largest u64, the cast would saturate.
+                if price >= u64::MAX as f64 {
+                    return Err(eval_error(format!("price out of range: {price}")));
+                }
+                Ok(price as u64)
+            }
+            other => Err(eval_error(format!("price is not a number: {other}"))),
+        }
+    }
+}
+
+impl TryFrom<String> for PricingExpr {
+    type Error = Error;
+
+    fn try_from(source: String) -> Result<Self> {
+        Self::parse(&source)
+    }
+}
+
+impl From<PricingExpr> for String {
+    fn from(expr: PricingExpr) -> Self {
+        expr.source
+    }
+}
+
+/// Variables of an expression, read from the request.
+pub struct Scope {
+    method: Value,
+    path: Value,
+    headers: Value,
+    /// JSON body, text fields of a multipart form, or `null`.
+    body: Value,
+    max_tokens: Value,
+    vocab: Option<String>,
+    input_tokens: OnceCell<Value>,
+}
+
+impl Scope {
+    pub async fn new(req: &PricedRequest<'_>, vocab: Option<&str>) -> Self {
+        let headers = req
+            .headers
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), Value::from(value.to_str().ok()?))))
+            .collect::<Map<_, _>>();
+        let body: Value = match serde_json::from_slice(req.body) {
+            Ok(body) => body,
+            Err(_) => body_params(req).await.map_or(Value::Null, Value::Object),
+        };
+        let max_tokens = ["max_tokens", "max_completion_tokens"]
+            .iter()
+            .find_map(|name| body.get(name).filter(|value| value.is_number()))
+            .cloned()
+            .unwrap_or_default();
+
+        Self {
+            method: Value::from(req.method.as_str()),
+            path: Value::from(req.path),
+            headers: Value::Object(headers),
+            body,
+            max_tokens,
+            vocab: vocab.map(str::to_string),
+            input_tokens: OnceCell::new(),
+        }
+    }
+
+    fn var(&self, name: &str) -> &Value {
+        match name {
+            "method" => &self.method,
+            "path" => &self.path,
+            "headers" => &self.headers,
+            "body" => &self.body,
+            // Counted on first use only, as it may take a while.
+            "input_tokens" => self.input_tokens.get_or_init(|| {
+                prompt_tokens(&self.body, self.vocab.as_deref()).map_or(Value::Null, Value::from)
+            }),
+            "max_tokens" => &self.max_tokens,
+            _ => &NULL,
+        }
+    }
+}
+
+// region:    --- Eval
+
+/// Values of the request are borrowed, only the computed ones are owned.
+fn eval<'a>(node: &'a Node, scope: &'a Scope) -> Result<Cow<'a, Value>> {
+    let value = match node {
+        Node::Literal(value) => return Ok(Cow::Borrowed(value)),
+        Node::Var(name) => return Ok(Cow::Borrowed(scope.var(name))),
+        Node::Index(target, index) => {
+            let index = eval(index, scope)?;
+            match eval(target, scope)? {
+                Cow::Borrowed(target) => return Ok(Cow::Borrowed(index_of(target, &index))),
+                Cow::Owned(target) => index_of(&target, &index).clone(),
+            }
+        }
+        Node::Unary(UnaryOp::Neg, operand) => float(-number(eval(operand, scope)?.as_ref())?)?,
+        Node::Unary(UnaryOp::Not, operand) => Value::Bool(!truthy(eval(operand, scope)?.as_ref())),
+        Node::Binary(BinaryOp::And, lhs, rhs) => {
+            Value::Bool(truthy(eval(lhs, scope)?.as_ref()) && truthy(eval(rhs, scope)?.as_ref()))
+        }
+        Node::Binary(BinaryOp::Or, lhs, rhs) => {
+            Value::Bool(truthy(eval(lhs, scope)?.as_ref()) || truthy(eval(rhs, scope)?.as_ref()))
+        }
+        Node::Binary(op, lhs, rhs) => {
+            binary(*op, eval(lhs, scope)?.as_ref(), eval(rhs, scope)?.as_ref())?
+        }
+        Node::Cond(cond, then, otherwise) => {
+            return match truthy(eval(cond, scope)?.as_ref()) {
+                true => eval(then, scope),
+                false => eval(otherwise, scope),
+            }
+        }
+        Node::Call(func, args) => {
+            let args = args
+                .iter()
+                .map(|arg| eval(arg, scope))
+                .collect::<Result<Vec<_>>>()?;
+            call(*func, args, scope)?
+        }
+    };
+
+    Ok(Cow::Owned(value))
+}
+
+/// Field of an object or item of a list, `null` if missing.
+fn index_of<'a>(target: &'a Value, index: &Value) -> &'a Value {
+    let item = match (target, index) {
+        (Value::Object(fields), Value::String(name)) => fields.get(name),
+        (Value::Array(items), Value::Number(index)) => index
+            .as_u64()
+            .or_else(|| {
+                index
+                    .as_f64()
+                    .filter(|index| index.fract() == 0.0 && *index >= 0.0)
+                    .map(|index| index as u64)
+            })
+            .and_then(|index| items.get(index as usize)),
+        _ => None,
+    };
+
+    item.unwrap_or(&NULL)
+}
+
+fn binary(op: BinaryOp, lhs: &Value, rhs: &Value) -> Result<Value> {
+    let value = match (op, lhs, rhs) {
+        (BinaryOp::Eq, _, _) => Value::Bool(equal(lhs, rhs)),
+        (BinaryOp::Ne, _, _) => Value::Bool(!equal(lhs, rhs)),
+        (BinaryOp::Add, Value::String(lhs), Value::String(rhs)) => {
+            Value::from(format!("{lhs}{rhs}"))
+        }
+        (
+            BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge,
+            Value::String(lhs),
+            Value::String(rhs),
+        ) => Value::Bool(compare(op, lhs.cmp(rhs))),
+        _ => {
+            let (lhs, rhs) = (number(lhs)?, number(rhs)?);
+            match op {
+                BinaryOp::Add => float(lhs + rhs)?,
+                BinaryOp::Sub => float(lhs - rhs)?,
+                BinaryOp::Mul => float(lhs * rhs)?,
+                BinaryOp::Div | BinaryOp::Rem if rhs == 0.0 => {
+                    return Err(eval_error("division by zero"))
+                }
+                BinaryOp::Div => float(lhs / rhs)?,
+                BinaryOp::Rem => float(lhs % rhs)?,
+                _ => Value::Bool(
+                    lhs.partial_cmp(&rhs)
+                        .is_some_and(|ordering| compare(op, ordering)),
+                ),
+            }
+        }
+    };
+
+    Ok(value)
+}
+
+fn call(func: Func, mut args: Vec<Cow<Value>>, scope: &Scope) -> Result<Value> {
+    let text = |value: &Value| value.as_str().map(str::to_string);
+
+    let value = match func {
+        Func::Len => match args[0].as_ref() {
+            Value::Null => Value::from(0),
+            Value::String(text) => Value::from(text.chars().count()),
+            Value::Array(items) => Value::from(items.len()),
+            Value::Object(fields) => Value::from(fields.len()),
+            other => return Err(eval_error(format!("no length for {other}"))),
+        },
+        Func::Tokens => Value::from(tokens(&args[0], &counter(scope.vocab.as_deref()))),
+        Func::StartsWith => Value::Bool(matches!(
+            (text(&args[0]), text(&args[1])),
+            (Some(value), Some(prefix)) if value.starts_with(&prefix)
+        )),
+        Func::EndsWith => Value::Bool(matches!(
+            (text(&args[0]), text(&args[1])),
+            (Some(value), Some(suffix)) if value.ends_with(&suffix)
+        )),
+        Func::Contains => Value::Bool(match (args[0].as_ref(), args[1].as_ref()) {
+            (Value::String(value), Value::String(part)) => value.contains(part.as_str()),
+            (Value::Array(items), item) => items.iter().any(|other| equal(other, item)),
+            (Value::Object(fields), Value::String(name)) => fields.contains_key(name),
+            _ => false,
+        }),
+        Func::Lower => match args[0].as_ref() {
+            Value::String(text) => Value::from(text.to_lowercase()),
+            _ => args.swap_remove(0).into_owned(),
+        },
+        Func::Number => match args[0].as_ref() {
+            Value::Number(_) | Value::Null => args.swap_remove(0).into_owned(),
+            Value::Bool(value) => Value::from(u8::from(*value)),
+            Value::String(text) => text.trim().parse::<f64>().map_or(Value::Null, Value::from),
+            other => return Err(eval_error(format!("not a number: {other}"))),
+        },
+        Func::Default => match args[0].is_null() {
+            true => args.swap_remove(1).into_owned(),
+            false => args.swap_remove(0).into_owned(),
+        },
+        Func::Min | Func::Max => {
+            let numbers = args
+                .iter()
+                .map(|arg| number(arg))
+                .collect::<Result<Vec<_>>>()?;
+            let pick = if func == Func::Min {
+                f64::min
+            } else {
+                f64::max
+            };
+            float(numbers.into_iter().reduce(pick).unwrap_or_default())?
+        }
+        Func::Ceil => float(number(&args[0])?.ceil())?,
+        Func::Floor => float(number(&args[0])?.floor())?,
+        Func::Round => float(number(&args[0])?.round())?,
+        Func::Abs => float(number(&args[0])?.abs())?,
+    };
+
+    Ok(value)
+}
+
+/// Tokens of a text, of the items of a list, or of the `content` or the
+/// `text` of a message.
+fn tokens(value: &Value, count: &impl Fn(&str) -> u64) -> u64 {
+    match value {
+        Value::String(text) => count(text),
+        Value::Array(items) => items.iter().map(|item| tokens(item, count)).sum(),
+        Value::Object(fields) => ["content", "text"]
+            .iter()
+            .filter_map(|name| fields.get(*name))
+            .map(|field| tokens(field, count))
+            .sum(),
+        _ => 0,
+    }
+}
+
+/// Number value, failing on the infinities and NaN JSON cannot hold, which
+/// `Value::from` would turn into `null`.
+fn float(number: f64) -> Result<Value> {
+    match number.is_finite() {
+        true => Ok(Value::from(number)),
+        false => Err(eval_error(format!("not a finite number: {number}"))),
+    }
+}
+
+fn number(value: &Value) -> Result<f64> {
+    value
+        .as_f64()
+        .ok_or_else(|| eval_error(format!("not a number: {value}")))
+}
+
+/// `false`, `null`, 0 and empty values are false.
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(value) => *value,
+        Value::Number(number) => number.as_f64().is_some_and(|number| number != 0.0),
+        Value::String(text) => !text.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(fields) => !fields.is_empty(),
+    }
+}
+
+/// Equality, with `1` equal to `1.0`.
+fn equal(lhs: &Value, rhs: &Value) -> bool {
+    match (lhs.as_f64(), rhs.as_f64()) {
+        (Some(lhs), Some(rhs)) => lhs == rhs,
+        _ => lhs == rhs,
+    }
+}
+
+fn compare(op: BinaryOp, ordering: std::cmp::Ordering) -> bool {
+    match op {
+        BinaryOp::Lt => ordering.is_lt(),
+        BinaryOp::Le => ordering.is_le(),
+        BinaryOp::Gt => ordering.is_gt(),
+        BinaryOp::Ge => ordering.is_ge(),
+        _ => false,
+    }
+}
+
+fn eval_error(message: impl Into<String>) -> Error {
+    Error::ExprEval(message.into())
+}
+
+// endregion: --- Eval
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use axum::http::{HeaderMap, Method};
+    use serde_json::json;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_expr_pricing_chat() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_pricing: ExprPricing = toml::from_str(
+            r#"
+            expr = "input_tokens * 30 + (starts_with(body.model, 'gpt-4') ? 50000 : 0) + default(max_tokens, 0) / 2"
+            "#,
+        )?;
+        let fx_headers = HeaderMap::new();
+        let fx_chat = |model: &str| {
+            json!({
+                "model": model,
+                "messages": [{ "role": "user", "content": "Hello!!!" }],
+                "max_tokens": 101,
+            })
+            .to_string()
+        };
+        let fx_bodies = [
+            fx_chat("gpt-4-turbo"),
+            fx_chat("gpt-3.5-turbo"),
+            "{}".to_string(),
+        ];
+
+        // -- Exec
+        let mut prices_msat = Vec::new();
+        for fx_body in &fx_bodies {
+            let req = PricedRequest {
+                method: &Method::POST,
+                path: "/v1/chat/completions",
+                headers: &fx_headers,
+                body: fx_body.as_bytes(),
+            };
+            prices_msat.push(fx_pricing.price_msat(&req).await.ok());
+        }
+
+        // -- Check
+        // (3 + 4 + 8 + 3) input tokens, a token per byte, and 50.5 msat
+        // rounded up.
+        // No prompt in the last, so no `input_tokens` to multiply.
+        assert_eq!(
+            prices_msat,
+            [Some(18 * 30 + 50000 + 51), Some(18 * 30 + 51), None]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_expr_pricing_err_non_finite() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_headers = HeaderMap::new();
+        let fx_req = PricedRequest {
+            method: &Method::POST,
+            path: "/v1/chat/completions",
+            headers: &fx_headers,
+            body: b"{}",
+        };
+        let fx_exprs = [
+            "number('1e308') * 10",
+            "-(number('1e308') * 10) < 0 ? 1 : 2",
+            "number('1e308') * 10 / number('1e308')",
+            "number('1e20')",
+        ];
+
+        // -- Exec & Check
+        for fx_expr in fx_exprs {
+            let pricing = ExprPricing {
+                expr: PricingExpr::parse(fx_expr)?,
+                vocab: None,
+            };
+            let res = pricing.price_msat(&fx_req).await;
+            assert!(
+                matches!(res, Err(Error::ExprEval(_))),
+                "{fx_expr} should fail, got {res:?}"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expr_parse_err() -> Result<()> {
+        // -- Exec & Check
+        for fx_expr in [
+            "1 +",
+            "unknown * 2",
+            "sha256(body)",
+            "len(1, 2)",
+            "'open",
+            "((((1)",
+        ] {
+            assert!(
+                matches!(PricingExpr::parse(fx_expr), Err(Error::ExprParse { .. })),
+                "{fx_expr} should not parse"
+            );
+        }
+        let fx_deep = format!("{}1{}", "(".repeat(100), ")".repeat(100));
+        assert!(PricingExpr::parse(&fx_deep).is_err());
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
@@ -0,0 +1,401 @@
+// Tokenizer and recursive descent parser of the pricing expressions, checking
+// the variables and the functions up front so evaluation only fails on the
+// values of the request.
+
+use serde_json::Value;
+
+use crate::pricing::{Error, Result};
+
+/// Bounds keeping the parse, and so the evaluation, cheap.
+const MAX_EXPR_LEN: usize = 4096;
+const MAX_DEPTH: usize = 64;
+
+/// Variables describing the request.
+pub const VARIABLES: [&str; 6] = [
+    "method",
+    "path",
+    "headers",
+    "body",
+    "input_tokens",
+    "max_tokens",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Func {
+    Len,
+    Tokens,
+    StartsWith,
+    EndsWith,
+    Contains,
+    Lower,
+    Number,
+    Default,
+    Min,
+    Max,
+    Ceil,
+    Floor,
+    Round,
+    Abs,
+}
+
+impl Func {
+    fn from_name(name: &str) -> Option<(Self, usize, usize)> {
+        // Function, with its min and max number of arguments.
+        let func = match name {
+            "len" => (Self::Len, 1, 1),
+            "tokens" => (Self::Tokens, 1, 1),
+            "starts_with" => (Self::StartsWith, 2, 2),
+            "ends_with" => (Self::EndsWith, 2, 2),
+            "contains" => (Self::Contains, 2, 2),
+            "lower" => (Self::Lower, 1, 1),
+            "number" => (Self::Number, 1, 1),
+            "default" => (Self::Default, 2, 2),
+            "min" => (Self::Min, 1, usize::MAX),
+            "max" => (Self::Max, 1, usize::MAX),
+            "ceil" => (Self::Ceil, 1, 1),
+            "floor" => (Self::Floor, 1, 1),
+            "round" => (Self::Round, 1, 1),
+            "abs" => (Self::Abs, 1, 1),
+            _ => return None,
+        };
+
+        Some(func)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+    Literal(Value),
+    Var(&'static str),
+    /// `value.name` or `value[index]`.
+    Index(Box<Node>, Box<Node>),
+    Unary(UnaryOp, Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+    /// `cond ? then : else`.
+    Cond(Box<Node>, Box<Node>, Box<Node>),
+    Call(Func, Vec<Node>),
+}
+
+pub fn parse(expr: &str) -> Result<Node> {
+    if expr.len() > MAX_EXPR_LEN {
+        return Err(parse_error(expr, MAX_EXPR_LEN, "expression too long"));
+    }
+
+    let tokens = tokenize(expr)?;
+    let mut parser = Parser {
+        expr,
+        tokens,
+        pos: 0,
+        depth: 0,
+    };
+    let node = parser.expr()?;
+    match parser.peek() {
+        None => Ok(node),
+        Some(_) => Err(parser.error("unexpected token")),
+    }
+}
+
+fn parse_error(expr: &str, position: usize, message: &str) -> Error {
+    Error::ExprParse {
+        expr: expr.to_string(),
+        position,
+        message: message.to_string(),
+    }
+}
+
+// region:    --- Tokenizer
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Str(String),
+    Ident(String),
+    Punct(&'static str),
+}
+
+/// Longest first, so `<=` is not read as `<`.
+const PUNCTS: [&str; 22] = [
+    "==", "!=", "<=", ">=", "&&", "||", "+", "-", "*", "/", "%", "<", ">", "!", "?", ":", "(", ")",
+    "[", "]", ".", ",",
+];
+
+/// Tokens with their byte offset in the expression.
+fn tokenize(expr: &str) -> Result<Vec<(usize, Token)>> {
+    let mut tokens = Vec::new();
+    let mut chars = expr.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_ascii_digit() || c == '.' || c == '_') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let number = expr[start..end].replace('_', "");
+            let number = number
+                .parse()
+                .map_err(|_| parse_error(expr, start, "invalid number"))?;
+            tokens.push((start, Token::Number(number)));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            tokens.push((start, Token::Ident(expr[start..end].to_string())));
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, 'n')) => text.push('\n'),
+                        Some((_, 't')) => text.push('\t'),
+                        Some((_, escaped)) => text.push(escaped),
+                        None => return Err(parse_error(expr, start, "unterminated string")),
+                    },
+                    Some((_, end)) if end == c => break,
+                    Some((_, other)) => text.push(other),
+                    None => return Err(parse_error(expr, start, "unterminated string")),
+                }
+            }
+            tokens.push((start, Token::Str(text)));
+        } else {
+            let punct = PUNCTS
+                .iter()
+                .find(|punct| expr[start..].starts_with(**punct))
+                .ok_or_else(|| parse_error(expr, start, "unexpected character"))?;
+            for _ in 0..punct.len() {
+                chars.next();
+            }
+            tokens.push((start, Token::Punct(punct)));
+        }
+    }
+
+    Ok(tokens)
+}
+
+// endregion: --- Tokenizer
+
+// region:    --- Parser
+
+struct Parser<'a> {
+    expr: &'a str,
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(_, token)| token.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Punct(known)) if *known == punct) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<()> {
+        if self.eat(punct) {
+            return Ok(());
+        }
+        Err(self.error(&format!("expected `{punct}`")))
+    }
+
+    fn error(&self, message: &str) -> Error {
+        let position = self
+            .tokens
+            .get(self.pos)
+            .map_or(self.expr.len(), |(position, _)| *position);
+        parse_error(self.expr, position, message)
+    }
+
+    /// `cond ? then : else`, the lowest precedence.
+    fn expr(&mut self) -> Result<Node> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(self.error("expression too deep"));
+        }
+
+        let cond = self.binary(0)?;
+        let node = if self.eat("?") {
+            let then = self.expr()?;
+            self.expect(":")?;
+            let otherwise = self.expr()?;
+            Node::Cond(Box::new(cond), Box::new(then), Box::new(otherwise))
+        } else {
+            cond
+        };
+
+        self.depth -= 1;
+        Ok(node)
+    }
+
+    /// Binary operators of the `level` of precedence and above.
+    fn binary(&mut self, level: usize) -> Result<Node> {
+        const LEVELS: [&[(&str, BinaryOp)]; 5] = [
+            &[("||", BinaryOp::Or)],
+            &[("&&", BinaryOp::And)],
+            &[
+                ("==", BinaryOp::Eq),
+                ("!=", BinaryOp::Ne),
+                ("<=", BinaryOp::Le),
+                (">=", BinaryOp::Ge),
+                ("<", BinaryOp::Lt),
+                (">", BinaryOp::Gt),
+            ],
+            &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+            &[
+                ("*", BinaryOp::Mul),
+                ("/", BinaryOp::Div),
+                ("%", BinaryOp::Rem),
+            ],
+        ];
+
+        let Some(ops) = LEVELS.get(level) else {
+            return self.unary();
+        };
+        let mut node = self.binary(level + 1)?;
+        while let Some((_, op)) = ops.iter().find(|(punct, _)| self.eat(punct)) {
+            let rhs = self.binary(level + 1)?;
+            node = Node::Binary(*op, Box::new(node), Box::new(rhs));
+        }
+
+        Ok(node)
+    }
+
+    fn unary(&mut self) -> Result<Node> {
+        let op = if self.eat("-") {
+            UnaryOp::Neg
+        } else if self.eat("!") {
+            UnaryOp::Not
+        } else {
+            return self.postfix();
+        };
+
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(self.error("expression too deep"));
+        }
+        let node = Node::Unary(op, Box::new(self.unary()?));
+        self.depth -= 1;
+
+        Ok(node)
+    }
+
+    fn postfix(&mut self) -> Result<Node> {
+        let mut node = self.primary()?;
+        loop {
+            if self.eat(".") {
+                let Some(Token::Ident(name)) = self.next() else {
+                    self.pos -= 1;
+                    return Err(self.error("expected a field name"));
+                };
+                node = Node::Index(Box::new(node), Box::new(Node::Literal(Value::String(name))));
+            } else if self.eat("[") {
+                let index = self.expr()?;
+                self.expect("]")?;
+                node = Node::Index(Box::new(node), Box::new(index));
+            } else {
+                return Ok(node);
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Node> {
+        let start = self.pos;
+        match self.next() {
+            Some(Token::Number(number)) => Ok(Node::Literal(number.into())),
+            Some(Token::Str(text)) => Ok(Node::Literal(Value::String(text))),
+            Some(Token::Punct("(")) => {
+                let node = self.expr()?;
+                self.expect(")")?;
+                Ok(node)
+            }
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" => Ok(Node::Literal(Value::Bool(true))),
+                "false" => Ok(Node::Literal(Value::Bool(false))),
+                "null" => Ok(Node::Literal(Value::Null)),
+                _ if self.eat("(") => self.call(start, &name),
+                _ => match VARIABLES.iter().find(|var| **var == name) {
+                    Some(var) => Ok(Node::Var(var)),
+                    None => {
+                        self.pos = start;
+                        Err(self.error("unknown variable"))
+                    }
+                },
+            },
+            _ => {
+                self.pos = start;
+                Err(self.error("expected a value"))
+            }
+        }
+    }
+
+    fn call(&mut self, start: usize, name: &str) -> Result<Node> {
+        let Some((func, min_args, max_args)) = Func::from_name(name) else {
+            self.pos = start;
+            return Err(self.error("unknown function"));
+        };
+
+        let mut args = Vec::new();
+        if !self.eat(")") {
+            loop {
+                args.push(self.expr()?);
+                if self.eat(")") {
+                    break;
+                }
+                self.expect(",")?;
+            }
+        }
+        if args.len() < min_args || args.len() > max_args {
+            self.pos = start;
+            return Err(self.error("wrong number of arguments"));
+        }
+
+        Ok(Node::Call(func, args))
+    }
+}
+
+// endregion: --- Parser
@@ -0,0 +1,217 @@
+// Pricing of image generations by the parameters of the request, sent as JSON
+// or as a multipart form depending on the provider.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::{body_params, PricedRequest};
+
+/// Names of the image count across the providers: OpenAI, Stability,
+/// ClipDrop and Scenario.
+const COUNT_PARAMS: [&str; 4] = ["n", "samples", "num_images", "numSamples"];
+const STEPS_PARAMS: [&str; 2] = ["steps", "numInferenceSteps"];
+/// Most images of a request of OpenAI, the other providers allowing fewer.
+const DEFAULT_MAX_IMAGES: u64 = 10;
+/// Most steps of a request of Stability and Scenario.
+const DEFAULT_MAX_STEPS: u64 = 150;
+
+/// `base_msat + count * (image + size + steps)`, the price of an image being
+/// the one of its `model` if listed, else `msat_per_image`. The size and the
+/// steps are charged when sent, in `size` as `<width>x<height>` or in `width`
+/// and `height`. Requests above `max_images` or `max_steps` are not priced.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ImagePricing {
+    #[serde(default)]
+    pub base_msat: u64,
+    pub msat_per_image: u64,
+    /// Price of an image per model.
+    #[serde(default)]
+    pub models: HashMap<String, u64>,
+    #[serde(default)]
+    pub msat_per_megapixel: u64,
+    #[serde(default)]
+    pub msat_per_step: u64,
+    #[serde(default = "default_max_images")]
+    pub max_images: u64,
+    #[serde(default = "default_max_steps")]
+    pub max_steps: u64,
+}
+
+impl ImagePricing {
+    /// `None` if the body is neither JSON nor a multipart form, if it asks for
+    /// too many images or steps, or if its price overflows.
+    pub async fn price_msat(&self, req: &PricedRequest<'_>) -> Option<u64> {
+        let params = body_params(req).await?;
+
+        self.params_price_msat(&params)
+    }
+
+    fn params_price_msat(&self, params: &Map<String, Value>) -> Option<u64> {
+        let count = COUNT_PARAMS
+            .iter()
+            .find_map(|name| number(params.get(*name)))
+            .unwrap_or(1.0)
+            .max(1.0) as u64;
+
+        let model_msat = params
+            .get("model")
+            .and_then(Value::as_str)
+            .and_then(|model| self.models.get(model))
+            .copied()
+            .unwrap_or(self.msat_per_image);
+        let size_msat = megapixels(params)
+            .map(|megapixels| (megapixels * self.msat_per_megapixel as f64).ceil() as u64)
+            .unwrap_or_default();
+        let steps = STEPS_PARAMS
+            .iter()
+            .find_map(|name| number(params.get(*name)))
+            .unwrap_or_default()
+            .max(0.0) as u64;
+        if count > self.max_images || steps > self.max_steps {
+            return None;
+        }
+
+        // The casts above saturate, so the overflows are caught here.
+        let image_msat = steps
+            .checked_mul(self.msat_per_step)?
+            .checked_add(model_msat)?
+            .checked_add(size_msat)?;
+        count.checked_mul(image_msat)?.checked_add(self.base_msat)
+    }
+}
+
+fn default_max_images() -> u64 {
+    DEFAULT_MAX_IMAGES
+}
+
+fn default_max_steps() -> u64 {
+    DEFAULT_MAX_STEPS
+}
+
+fn megapixels(params: &Map<String, Value>) -> Option<f64> {
+    let (width, height) = match params.get("size").and_then(Value::as_str) {
+        Some(size) => {
+            let (width, height) = size.split_once('x')?;
+            (width.trim().parse().ok()?, height.trim().parse().ok()?)
+        }
+        None => (number(params.get("width"))?, number(params.get("height"))?),
+    };
+
+    Some((width * height / 1_000_000.0).max(0.0))
+}
+
+/// JSON number, or number in a string as sent in forms.
+fn number(value: Option<&Value>) -> Option<f64> {
+    match value? {
+        Value::Number(number) => number.as_f64(),
+        Value::String(number) => number.trim().parse().ok(),
+        _ => None,
+    }
+    .filter(|number| number.is_finite())
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use axum::http::{HeaderMap, Method};
+    use serde_json::json;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_image_pricing_json_and_form() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_pricing = ImagePricing {
+            base_msat: 0,
+            msat_per_image: 1000,
+            models: HashMap::from([("dall-e-3".to_string(), 5000)]),
+            msat_per_megapixel: 2000,
+            msat_per_step: 10,
+            max_images: DEFAULT_MAX_IMAGES,
+            max_steps: DEFAULT_MAX_STEPS,
+        };
+        let fx_json = json!({ "model": "dall-e-3", "n": 2, "size": "1000x500" }).to_string();
+        let fx_form = b"--b\r\n\
+            Content-Disposition: form-data; name=\"samples\"\r\n\r\n\
+            3\r\n\
+            --b\r\n\
+            Content-Disposition: form-data; name=\"width\"\r\n\r\n\
+            1000\r\n\
+            --b\r\n\
+            Content-Disposition: form-data; name=\"height\"\r\n\r\n\
+            1000\r\n\
+            --b\r\n\
+            Content-Disposition: form-data; name=\"steps\"\r\n\r\n\
+            30\r\n\
+            --b--\r\n";
+        let mut fx_form_headers = HeaderMap::new();
+        fx_form_headers.insert("content-type", "multipart/form-data; boundary=b".parse()?);
+
+        // -- Exec
+        let json_msat = fx_pricing
+            .price_msat(&PricedRequest {
+                method: &Method::POST,
+                path: "/v1/images/generations",
+                headers: &HeaderMap::new(),
+                body: fx_json.as_bytes(),
+            })
+            .await;
+        let form_msat = fx_pricing
+            .price_msat(&PricedRequest {
+                method: &Method::POST,
+                path: "/v1/generation/sdxl/text-to-image",
+                headers: &fx_form_headers,
+                body: fx_form,
+            })
+            .await;
+
+        // -- Check
+        assert_eq!(json_msat, Some(2 * (5000 + 1000)));
+        assert_eq!(form_msat, Some(3 * (1000 + 2000 + 30 * 10)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_image_pricing_err_limits() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_pricing = ImagePricing {
+            base_msat: 0,
+            msat_per_image: u64::MAX / 4,
+            models: HashMap::new(),
+            msat_per_megapixel: 0,
+            msat_per_step: 10,
+            max_images: DEFAULT_MAX_IMAGES,
+            max_steps: DEFAULT_MAX_STEPS,
+        };
+        let fx_params = |params: Value| match params {
+            Value::Object(params) => params,
+            _ => Map::new(),
+        };
+
+        // -- Exec & Check
+        assert!(fx_pricing
+            .params_price_msat(&fx_params(json!({ "n": 3 })))
+            .is_some());
+        // Overflowing the price.
+        assert_eq!(
+            fx_pricing.params_price_msat(&fx_params(json!({ "n": 5 }))),
+            None
+        );
+        // Above the caps.
+        assert_eq!(
+            fx_pricing.params_price_msat(&fx_params(json!({ "n": 1e30 }))),
+            None
+        );
+        assert_eq!(
+            fx_pricing.params_price_msat(&fx_params(json!({ "steps": 10000 }))),
+            None
+        );
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
@@ -0,0 +1,423 @@
+// region:    --- Modules
+
+mod audio;
+mod bpe;
+mod error;
+mod expr;
+mod image;
+mod text;
+mod tokens;
+
+pub use self::audio::PerSecondPricing;
+pub use self::error::{Error, Result};
+pub use self::expr::{ExprPricing, PricingExpr, Scope};
+pub use self::image::ImagePricing;
+pub use self::text::PerCharPricing;
+pub use self::tokens::{ModelTokenPricing, TokenPricing};
+
+use axum::body::{Body, Bytes};
+use axum::extract::{DefaultBodyLimit, FromRequest, Multipart};
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{HeaderMap, Method, Request};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tower::{service_fn, Layer, ServiceExt};
+
+use crate::config::apis::{apis_config, ApisConfig, CHAT_COMPLETIONS_PATH, UNIFIED_ROUTE};
+
+// endregion: --- Modules
+
+/// Price of a request when its provider does not set one.
+pub const DEFAULT_PRICE_MSAT: u64 = 1000;
+
+/// Request checked and priced before it is forwarded, its path relative to its
+/// provider.
+pub struct PricedRequest<'a> {
+    pub method: &'a Method,
+    pub path: &'a str,
+    pub headers: &'a HeaderMap,
+    pub body: &'a [u8],
+}
+
+/// Provider or fallback route of a request path, its first segment, and the
+/// path within it.
+pub fn split_service(path: &str) -> (&str, String) {
+    let mut segments = path.splitn(3, '/');
+    let service = segments.nth(1).unwrap_or_default();
+
+    (service, format!("/{}", segments.next().unwrap_or_default()))
+}
+
+/// Price of a request to the provider or fallback route `service`, `None` if
+/// there is no such route. Unified chat completions of a model without a
+/// provider are an error.
+pub async fn service_price_msat(service: &str, req: &PricedRequest<'_>) -> Result<Option<u64>> {
+    let Some((pricing, path)) = service_pricing(&apis_config(), service, req) else {
+        // Not routed by the unified endpoint either, refused before it is
+        // charged.
+        if is_unified_chat(service, req.path) {
+            let model = body_model(req.body).unwrap_or_default();
+            return Err(Error::ModelUnrouted { model });
+        }
+        return Ok(None);
+    };
+    let req = PricedRequest { path, ..*req };
+
+    pricing.price_msat(&req).await.map(Some)
+}
+
+/// Body of a request to `service` bounded as it is priced, see
+/// `PricingParams::bounded_body`. `None` when it is forwarded as is.
+pub fn service_bounded_body(service: &str, req: &PricedRequest<'_>) -> Option<Vec<u8>> {
+    let (pricing, path) = service_pricing(&apis_config(), service, req)?;
+    pricing.bounded_body(&PricedRequest { path, ..*req })
+}
+
+/// `path` of `service` is the unified chat completions.
+fn is_unified_chat(service: &str, path: &str) -> bool {
+    service == UNIFIED_ROUTE && format!("/{service}{path}") == CHAT_COMPLETIONS_PATH
+}
+
+/// Pricing of a request to `service`, and the path it is priced at.
+///
+/// Unified chat completions are priced by the provider of their model, as
+/// OpenAI chat completions whatever the format of the provider.
+fn service_pricing<'a>(
+    apis_config: &ApisConfig,
+    service: &str,
+    req: &PricedRequest<'a>,
+) -> Option<(PricingParams, &'a str)> {
+    if service == UNIFIED_ROUTE {
+        // Only the chat completions are served there.
+        if !is_unified_chat(service, req.path) {
+            return None;
+        }
+        let model = body_model(req.body)?;
+        let api_params = apis_config.get_model_provider(&model)?;
+        return Some((api_params.pricing, CHAT_COMPLETIONS_PATH));
+    }
+
+    let pricing = match apis_config.get_params(service) {
+        Some(api_params) => api_params.pricing,
+        None => apis_config.get_fallback(service)?.pricing,
+    };
+
+    Some((pricing, req.path))
+}
+
+/// Pricing of the requests to a provider.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PricingParams {
+    /// Flat price, for the requests no rule matches.
+    #[serde(default = "default_price_msat")]
+    pub price_msat: u64,
+    /// The first one matching the path prices the request.
+    #[serde(default)]
+    pub rules: Vec<PricingRule>,
+    /// Charged for the requests the matching rule cannot price, e.g. lacking
+    /// the fields it prices, which are rejected without it.
+    pub max_price_msat: Option<u64>,
+}
+
+impl Default for PricingParams {
+    fn default() -> Self {
+        Self {
+            price_msat: DEFAULT_PRICE_MSAT,
+            rules: Vec::new(),
+            max_price_msat: None,
+        }
+    }
+}
+
+impl PricingParams {
+    pub async fn price_msat(&self, req: &PricedRequest<'_>) -> Result<u64> {
+        let Some(rule) = self.rules.iter().find(|rule| rule.matches(req.path)) else {
+            return Ok(self.price_msat);
+        };
+
+        match rule.kind.price_msat(req).await? {
+            Some(price_msat) => Ok(price_msat),
+            None => self.max_price_msat.ok_or_else(|| Error::RequestUnpriced {
+                path: req.path.to_string(),
+            }),
+        }
+    }
+
+    /// Body of the request with the completion tokens the matching `tokens`
+    /// rule charges for set, when it sets none. `None` when it is forwarded as
+    /// is.
+    pub fn bounded_body(&self, req: &PricedRequest<'_>) -> Option<Vec<u8>> {
+        let rule = self.rules.iter().find(|rule| rule.matches(req.path))?;
+        match &rule.kind {
+            PricingKind::Tokens(pricing) => pricing.bounded_body(req.body),
+            _ => None,
+        }
+    }
+
+    /// Loads what the rules need, e.g. their vocabs.
+    pub fn validate(&self) -> Result<()> {
+        for rule in &self.rules {
+            match &rule.kind {
+                PricingKind::Tokens(pricing) => pricing.load_vocab()?,
+                PricingKind::Expr(pricing) => pricing.load_vocab()?,
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Price computed from the request, for the paths matching `path`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PricingRule {
+    /// Path relative to the provider, exact or a prefix ending with `*`. All
+    /// the paths when missing.
+    pub path: Option<String>,
+    #[serde(flatten)]
+    pub kind: PricingKind,
+}
+
+impl PricingRule {
+    fn matches(&self, path: &str) -> bool {
+        self.path
+            .as_deref()
+            .is_none_or(|pattern| matches_pattern(pattern, path))
+    }
+}
+
+/// `value` is the `pattern`, or starts with it when it ends with `*`.
+pub fn matches_pattern(pattern: &str, value: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => value.starts_with(prefix),
+        None => value == pattern,
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum PricingKind {
+    /// Per character of the text of a JSON body, e.g. for text to speech.
+    PerChar(PerCharPricing),
+    /// Per second of the audio file of a multipart upload, e.g. for
+    /// transcriptions.
+    PerSecond(PerSecondPricing),
+    /// Per image generated, by model, size and steps.
+    Image(ImagePricing),
+    /// Per prompt token counted locally and per completion token allowed, for
+    /// chats and completions.
+    Tokens(TokenPricing),
+    /// Computed by an expression over the method, the path, the headers and
+    /// the body.
+    Expr(ExprPricing),
+}
+
+impl PricingKind {
+    /// Price of the request, `None` if it lacks what the rule prices, and an
+    /// error if an expression fails on it.
+    async fn price_msat(&self, req: &PricedRequest<'_>) -> Result<Option<u64>> {
+        let price_msat = match self {
+            Self::PerChar(pricing) => pricing.price_msat(req.body),
+            Self::PerSecond(pricing) => pricing.price_msat(req).await,
+            Self::Image(pricing) => pricing.price_msat(req).await,
+            Self::Tokens(pricing) => pricing.price_msat(req.body),
+            Self::Expr(pricing) => Some(pricing.price_msat(req).await?),
+        };
+
+        Ok(price_msat)
+    }
+}
+
+// region:    --- Body
+
+/// Multipart body, `None` for the other content types.
+async fn multipart(req: &PricedRequest<'_>) -> Option<Multipart> {
+    let content_type = req.headers.get(CONTENT_TYPE)?;
+    let multipart_req = Request::builder()
+        .header(CONTENT_TYPE, content_type)
+        .body(Body::from(req.body.to_vec()))
+        .ok()?;
+    // Read up to `SERVICE_MAX_BODY_BYTES` already, past the 2 MB default limit
+    // of the extractor.
+    let extract = service_fn(|req: Request<Body>| Multipart::from_request(req, &()));
+    DefaultBodyLimit::disable()
+        .layer(extract)
+        .oneshot(multipart_req)
+        .await
+        .ok()
+}
+
+/// Content of the `field` file of a multipart body.
+async fn multipart_file(req: &PricedRequest<'_>, field: &str) -> Option<Bytes> {
+    let mut multipart = multipart(req).await?;
+    while let Some(multipart_field) = multipart.next_field().await.ok()? {
+        if multipart_field.name() == Some(field) {
+            return multipart_field.bytes().await.ok();
+        }
+    }
+
+    None
+}
+
+/// Top level fields of a JSON body, or text fields of a multipart form.
+pub async fn body_params(req: &PricedRequest<'_>) -> Option<Map<String, Value>> {
+    if let Ok(Value::Object(params)) = serde_json::from_slice(req.body) {
+        return Some(params);
+    }
+
+    let mut multipart = multipart(req).await?;
+    let mut params = Map::new();
+    while let Some(multipart_field) = multipart.next_field().await.ok()? {
+        let Some(name) = multipart_field.name().map(str::to_string) else {
+            continue;
+        };
+        if multipart_field.file_name().is_some() {
+            continue;
+        }
+        if let Ok(text) = multipart_field.text().await {
+            params.insert(name, Value::String(text));
+        }
+    }
+
+    Some(params)
+}
+
+/// `model` of a JSON body.
+pub fn body_model(body: &[u8]) -> Option<String> {
+    let params: Map<String, Value> = serde_json::from_slice(body).ok()?;
+    params.get("model")?.as_str().map(str::to_string)
+}
+
+// endregion: --- Body
+
+fn default_price_msat() -> u64 {
+    DEFAULT_PRICE_MSAT
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pricing_rules_by_path() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_pricing: PricingParams = toml::from_str(
+            r#"
+            price_msat = 2000
+            rules = [{ path = "/v1/audio/speech", type = "per-char", msat_per_char = 60 }]
+            "#,
+        )?;
+        let fx_body = br#"{"model": "tts-1", "input": "Hello, world!", "voice": "alloy"}"#;
+        let fx_headers = HeaderMap::new();
+
+        // -- Exec
+        let speech = fx_pricing
+            .price_msat(&PricedRequest {
+                method: &Method::POST,
+                path: "/v1/audio/speech",
+                headers: &fx_headers,
+                body: fx_body,
+            })
+            .await?;
+        let chat = fx_pricing
+            .price_msat(&PricedRequest {
+                method: &Method::POST,
+                path: "/v1/chat/completions",
+                headers: &fx_headers,
+                body: fx_body,
+            })
+            .await?;
+
+        // -- Check
+        assert_eq!(speech, 13 * 60);
+        assert_eq!(chat, 2000);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_service_pricing_unified() -> Result<()> {
+        // -- Setup & Fixtures
+        #[derive(Deserialize)]
+        struct FxProviders {
+            providers: Vec<crate::config::apis::ApiParams>,
+            models: Vec<crate::config::apis::ModelRoute>,
+        }
+        let fx_providers: FxProviders = toml::from_str(
+            r#"
+            [[providers]]
+            name = "openai"
+            path = "/openai"
+            host = "api.openai.com"
+            auth = "bearer"
+            pricing = { price_msat = 3000 }
+
+            [[models]]
+            pattern = "gpt-*"
+            provider = "openai"
+            "#,
+        )?;
+        let fx_apis_config = ApisConfig {
+            providers: fx_providers.providers,
+            fallbacks: Vec::new(),
+            models: fx_providers.models,
+        };
+        let fx_headers = HeaderMap::new();
+        let fx_req = |path| PricedRequest {
+            method: &Method::POST,
+            path,
+            headers: &fx_headers,
+            body: br#"{"model": "gpt-4", "messages": []}"#,
+        };
+
+        // -- Exec
+        let unified = service_pricing(&fx_apis_config, "v1", &fx_req("/chat/completions"));
+        let unified_other = service_pricing(&fx_apis_config, "v1", &fx_req("/models"));
+        let provider = service_pricing(&fx_apis_config, "openai", &fx_req("/v1/audio/speech"));
+
+        // -- Check
+        let (pricing, path) = unified.ok_or(anyhow::anyhow!("unified not priced"))?;
+        assert_eq!((pricing.price_msat, path), (3000, CHAT_COMPLETIONS_PATH));
+        assert!(unified_other.is_none());
+        assert_eq!(provider.map(|(_, path)| path), Some("/v1/audio/speech"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pricing_rules_unpriced_err() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_pricing = |max_price_msat: &str| -> Result<PricingParams> {
+            Ok(toml::from_str(&format!(
+                r#"
+                price_msat = 2000
+                {max_price_msat}
+                rules = [{{ path = "/v1/audio/speech", type = "per-char", msat_per_char = 60 }}]
+                "#
+            ))?)
+        };
+        let fx_headers = HeaderMap::new();
+        let fx_req = PricedRequest {
+            method: &Method::POST,
+            path: "/v1/audio/speech",
+            headers: &fx_headers,
+            body: br#"{"model": "tts-1", "voice": "alloy"}"#,
+        };
+
+        // -- Exec
+        let rejected = fx_pricing("")?.price_msat(&fx_req).await;
+        let capped = fx_pricing("max_price_msat = 90000")?
+            .price_msat(&fx_req)
+            .await?;
+
+        // -- Check
+        assert!(matches!(rejected, Err(Error::RequestUnpriced { .. })));
+        assert_eq!(capped, 90000);
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
@@ -0,0 +1,67 @@
+// Pricing by the length of the text sent, e.g. to text to speech APIs.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PerCharPricing {
+    #[serde(default)]
+    pub base_msat: u64,
+    pub msat_per_char: u64,
+    /// Top level fields of the JSON body holding the text, `input` for OpenAI
+    /// and `text` for ElevenLabs.
+    #[serde(default = "default_fields")]
+    pub fields: Vec<String>,
+}
+
+impl PerCharPricing {
+    /// `None` if the body has none of the fields, or if its price overflows.
+    pub fn price_msat(&self, body: &[u8]) -> Option<u64> {
+        let body: Value = serde_json::from_slice(body).ok()?;
+        let texts: Vec<&str> = self
+            .fields
+            .iter()
+            .filter_map(|field| body[field].as_str())
+            .collect();
+        if texts.is_empty() {
+            return None;
+        }
+
+        let chars = texts
+            .iter()
+            .map(|text| text.chars().count() as u64)
+            .sum::<u64>();
+        chars
+            .checked_mul(self.msat_per_char)?
+            .checked_add(self.base_msat)
+    }
+}
+
+fn default_fields() -> Vec<String> {
+    vec!["input".to_string(), "text".to_string()]
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_per_char_pricing_err_overflow() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_pricing: PerCharPricing =
+            toml::from_str(&format!("msat_per_char = {}", u64::MAX / 2))?;
+
+        // -- Exec & Check
+        assert_eq!(
+            fx_pricing.price_msat(br#"{"input": "a"}"#),
+            Some(u64::MAX / 2)
+        );
+        assert_eq!(fx_pricing.price_msat(br#"{"input": "abc"}"#), None);
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
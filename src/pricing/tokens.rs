@@ -0,0 +1,317 @@
+// Worst case pricing of LLM requests: the prompt tokens counted locally, plus
+// all the completion tokens the request allows.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+use super::bpe::{estimate_tokens, Bpe};
+use super::{matches_pattern, Result};
+
+/// Completion tokens charged when the request sets no `max_tokens`.
+const DEFAULT_MAX_TOKENS: u64 = 4096;
+/// Fields bounding the completion tokens, the largest one counting.
+const MAX_TOKENS_PARAMS: [&str; 2] = ["max_tokens", "max_completion_tokens"];
+/// Tokens added by OpenAI around each chat message, and to prime the reply.
+const TOKENS_PER_MESSAGE: u64 = 3;
+const TOKENS_PER_REPLY: u64 = 3;
+/// Most tokens OpenAI charges for an image at high detail, 8 tiles.
+const TOKENS_PER_IMAGE: u64 = 1445;
+/// Fields of a chat, besides its messages, which the providers put in the
+/// prompt.
+const PROMPT_PARAMS: [&str; 5] = [
+    "system",
+    "tools",
+    "functions",
+    "tool_choice",
+    "response_format",
+];
+/// Types of the image parts of OpenAI and Anthropic, charged per image rather
+/// than by the length of their data.
+const IMAGE_PART_TYPES: [&str; 2] = ["image_url", "image"];
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TokenPricing {
+    #[serde(default)]
+    pub base_msat: u64,
+    pub msat_per_1k_prompt: u64,
+    pub msat_per_1k_completion: u64,
+    /// Prices of the models matching a pattern, exact or a prefix ending with
+    /// `*`, the first match winning over the prices above.
+    #[serde(default)]
+    pub models: Vec<ModelTokenPricing>,
+    /// `.tiktoken` vocab counting the prompt tokens, else they are estimated
+    /// from the length of the prompt.
+    pub vocab: Option<String>,
+    #[serde(default = "default_max_tokens")]
+    pub default_max_tokens: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ModelTokenPricing {
+    pub pattern: String,
+    pub msat_per_1k_prompt: u64,
+    pub msat_per_1k_completion: u64,
+}
+
+impl TokenPricing {
+    /// Reads the vocab, so a bad one fails the config load.
+    pub fn load_vocab(&self) -> Result<()> {
+        if let Some(vocab) = &self.vocab {
+            Bpe::load(vocab)?;
+        }
+
+        Ok(())
+    }
+
+    /// `None` if the body is neither a chat nor a completion, or if its price
+    /// overflows.
+    pub fn price_msat(&self, body: &[u8]) -> Option<u64> {
+        let body: Value = serde_json::from_slice(body).ok()?;
+        let prompt_tokens = prompt_tokens(&body, self.vocab.as_deref())?;
+        let max_tokens = max_tokens(&body).unwrap_or(self.default_max_tokens);
+        let completion_tokens = max_tokens.checked_mul(body["n"].as_u64().unwrap_or(1).max(1))?;
+
+        let model = body["model"].as_str().unwrap_or_default();
+        let (msat_per_1k_prompt, msat_per_1k_completion) = self
+            .models
+            .iter()
+            .find(|pricing| matches_pattern(&pricing.pattern, model))
+            .map_or(
+                (self.msat_per_1k_prompt, self.msat_per_1k_completion),
+                |pricing| (pricing.msat_per_1k_prompt, pricing.msat_per_1k_completion),
+            );
+
+        let prompt_msat = prompt_tokens
+            .checked_mul(msat_per_1k_prompt)?
+            .div_ceil(1000);
+        let completion_msat = completion_tokens
+            .checked_mul(msat_per_1k_completion)?
+            .div_ceil(1000);
+        self.base_msat
+            .checked_add(prompt_msat)?
+            .checked_add(completion_msat)
+    }
+
+    /// The body with `max_tokens` set to the `default_max_tokens` it is priced
+    /// for when it has no valid bound, so the provider cannot generate more
+    /// than is charged. `None` if it has one, or is neither a chat nor a
+    /// completion.
+    pub fn bounded_body(&self, body: &[u8]) -> Option<Vec<u8>> {
+        let mut body: Value = serde_json::from_slice(body).ok()?;
+        let is_prompt = body["messages"].is_array()
+            || matches!(body["prompt"], Value::String(_) | Value::Array(_));
+        if !is_prompt || max_tokens(&body).is_some() {
+            return None;
+        }
+
+        // Invalid bounds, e.g. `null`, are replaced.
+        let fields = body.as_object_mut()?;
+        for name in MAX_TOKENS_PARAMS {
+            fields.remove(name);
+        }
+        fields.insert(
+            MAX_TOKENS_PARAMS[0].to_string(),
+            self.default_max_tokens.into(),
+        );
+        serde_json::to_vec(&body).ok()
+    }
+}
+
+/// Largest completion tokens the body allows, `None` if it sets no bound.
+fn max_tokens(body: &Value) -> Option<u64> {
+    MAX_TOKENS_PARAMS
+        .iter()
+        .filter_map(|name| body[name].as_u64())
+        .max()
+}
+
+/// Tokens of the `messages` of a chat, with its other prompt fields, or of the
+/// `prompt` of a completion, counted with the `vocab` if set.
+pub fn prompt_tokens(body: &Value, vocab: Option<&str>) -> Option<u64> {
+    let count = counter(vocab);
+
+    if let Some(messages) = body["messages"].as_array() {
+        let params = PROMPT_PARAMS
+            .iter()
+            .map(|name| value_tokens(&body[name], &count))
+            .sum::<u64>();
+        // All the fields of a message, its role, content parts, name and tool
+        // calls.
+        let tokens = messages
+            .iter()
+            .map(|message| match message {
+                Value::Object(message) => {
+                    let fields = message
+                        .values()
+                        .map(|value| value_tokens(value, &count))
+                        .sum::<u64>();
+                    TOKENS_PER_MESSAGE + fields
+                }
+                message => TOKENS_PER_MESSAGE + value_tokens(message, &count),
+            })
+            .sum::<u64>();
+        return Some(params + tokens + TOKENS_PER_REPLY);
+    }
+
+    match &body["prompt"] {
+        Value::String(prompt) => Some(count(prompt)),
+        Value::Array(prompts) => Some(prompts.iter().filter_map(Value::as_str).map(&count).sum()),
+        _ => None,
+    }
+}
+
+/// Tokens of the texts, and of the keys of the objects, of a JSON value, the
+/// image parts counting as a whole image.
+fn value_tokens(value: &Value, count: &impl Fn(&str) -> u64) -> u64 {
+    match value {
+        Value::Null => 0,
+        Value::String(text) => count(text),
+        Value::Array(values) => values.iter().map(|value| value_tokens(value, count)).sum(),
+        Value::Object(object) => match object.get("type").and_then(Value::as_str) {
+            Some(kind) if IMAGE_PART_TYPES.contains(&kind) => TOKENS_PER_IMAGE,
+            _ => object
+                .iter()
+                .map(|(key, value)| count(key) + value_tokens(value, count))
+                .sum(),
+        },
+        value => count(&value.to_string()),
+    }
+}
+
+/// Counts the tokens of a text with the `vocab`, estimating them without one.
+pub fn counter(vocab: Option<&str>) -> impl Fn(&str) -> u64 {
+    let bpe = vocab.and_then(|vocab| match Bpe::cached(vocab) {
+        Ok(bpe) => Some(bpe),
+        Err(e) => {
+            warn!("Vocab {} not loaded, estimating the tokens: {}", vocab, e);
+            None
+        }
+    });
+
+    move |text| match &bpe {
+        Some(bpe) => bpe.count_tokens(text),
+        None => estimate_tokens(text),
+    }
+}
+
+fn default_max_tokens() -> u64 {
+    DEFAULT_MAX_TOKENS
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_token_pricing_worst_case() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_pricing = TokenPricing {
+            base_msat: 0,
+            msat_per_1k_prompt: 1000,
+            msat_per_1k_completion: 2000,
+            models: vec![ModelTokenPricing {
+                pattern: "gpt-4*".to_string(),
+                msat_per_1k_prompt: 30000,
+                msat_per_1k_completion: 60000,
+            }],
+            vocab: None,
+            default_max_tokens: 1000,
+        };
+        // "Hello!!!" is estimated at 8 tokens, "user" at 4, a token per byte.
+        let fx_chat = json!({
+            "model": "gpt-4-turbo",
+            "messages": [{ "role": "user", "content": "Hello!!!" }],
+            "max_tokens": 100,
+            "n": 2,
+        });
+        let fx_completion = json!({ "model": "davinci-002", "prompt": "Hello!!!" });
+
+        // -- Exec
+        let chat_msat = fx_pricing.price_msat(fx_chat.to_string().as_bytes());
+        let completion_msat = fx_pricing.price_msat(fx_completion.to_string().as_bytes());
+
+        // -- Check
+        // (3 + 4 + 8 + 3) prompt tokens and 2 * 100 completion tokens.
+        assert_eq!(chat_msat, Some((18 * 30000 + 200 * 60000) / 1000));
+        assert_eq!(completion_msat, Some((8 * 1000 + 1000 * 2000) / 1000));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_token_pricing_bounded_body() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_pricing: TokenPricing = toml::from_str(
+            "msat_per_1k_prompt = 1000\nmsat_per_1k_completion = 2000\ndefault_max_tokens = 1000",
+        )?;
+        let fx_unbounded = json!({ "messages": [], "max_completion_tokens": null });
+        let fx_bounded = json!({ "messages": [], "max_tokens": 100 });
+        let fx_embedding = json!({ "input": "Hello" });
+
+        // -- Exec
+        let unbounded = fx_pricing
+            .bounded_body(fx_unbounded.to_string().as_bytes())
+            .unwrap_or_default();
+        let bounded = fx_pricing.bounded_body(fx_bounded.to_string().as_bytes());
+        let embedding = fx_pricing.bounded_body(fx_embedding.to_string().as_bytes());
+
+        // -- Check
+        // Forwarded with the completion tokens it is charged for.
+        assert_eq!(
+            serde_json::from_slice::<Value>(&unbounded)?,
+            json!({ "messages": [], "max_tokens": 1000 })
+        );
+        assert!(bounded.is_none());
+        assert!(embedding.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prompt_tokens_all_fields() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_chat = json!({
+            "system": [{ "type": "text", "text": "Hi" }],
+            "tools": [{ "type": "function", "function": { "name": "f" } }],
+            "messages": [{
+                "role": "user",
+                "content": [
+                    { "type": "image_url", "image_url": { "url": "data:image/png;base64,AAAA" } },
+                    { "type": "text", "text": "Hey" },
+                ],
+            }],
+            "max_tokens": u64::MAX,
+            "n": 2,
+        });
+        let fx_pricing = TokenPricing {
+            base_msat: 0,
+            msat_per_1k_prompt: 1000,
+            msat_per_1k_completion: 1000,
+            models: Vec::new(),
+            vocab: None,
+            default_max_tokens: 1000,
+        };
+
+        // -- Exec
+        let tokens = prompt_tokens(&fx_chat, None);
+        let price_msat = fx_pricing.price_msat(fx_chat.to_string().as_bytes());
+
+        // -- Check
+        // The system and tools with their keys, then the message with its
+        // image and text parts.
+        let system = 4 + 4 + 4 + 2;
+        let tools = 4 + 8 + 8 + 4 + 1;
+        let message = 3 + 4 + TOKENS_PER_IMAGE + (4 + 4 + 4 + 3);
+        assert_eq!(tokens, Some(system + tools + message + 3));
+        // `max_tokens * n` overflows.
+        assert_eq!(price_msat, None);
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
@@ -0,0 +1,223 @@
+// Anthropic messages API.
+
+use serde_json::{json, Map, Value};
+
+use super::openai::{self, ChatRequest, Content, ContentPart, Usage};
+use super::stream::{sse_data, StreamTranslator};
+use super::{Dialect, Error, Result};
+
+/// Anthropic needs a `max_tokens`, OpenAI does not.
+const DEFAULT_MAX_TOKENS: u32 = 1024;
+
+#[derive(Debug)]
+pub struct Anthropic;
+
+impl Dialect for Anthropic {
+    fn path(&self) -> &'static str {
+        "/v1/messages"
+    }
+
+    fn request(&self, req: &ChatRequest) -> Result<Value> {
+        let mut system = Vec::new();
+        let mut messages = Vec::new();
+        for message in &req.messages {
+            match message.role.as_str() {
+                "system" => system.push(message.text()),
+                "user" | "assistant" => messages.push(json!({
+                    "role": message.role,
+                    "content": content(message.content.as_ref())?,
+                })),
+                role => return Err(Error::UnsupportedContent(format!("role {role}"))),
+            }
+        }
+
+        let mut body = Map::new();
+        body.insert("model".into(), json!(req.model));
+        body.insert("messages".into(), json!(messages));
+        body.insert(
+            "max_tokens".into(),
+            json!(req.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS)),
+        );
+        if !system.is_empty() {
+            body.insert("system".into(), json!(system.join("\n")));
+        }
+        if let Some(temperature) = req.temperature {
+            body.insert("temperature".into(), json!(temperature));
+        }
+        if let Some(top_p) = req.top_p {
+            body.insert("top_p".into(), json!(top_p));
+        }
+        if let Some(stop) = &req.stop {
+            body.insert("stop_sequences".into(), json!(stop.to_vec()));
+        }
+        if req.stream {
+            body.insert("stream".into(), json!(true));
+        }
+
+        Ok(Value::Object(body))
+    }
+
+    fn response(&self, res: Value, model: &str) -> Result<Value> {
+        let content = res["content"]
+            .as_array()
+            .ok_or_else(|| Error::InvalidResponse("no content".to_string()))?
+            .iter()
+            .filter_map(|block| block["text"].as_str())
+            .collect::<String>();
+        let usage = Usage {
+            prompt_tokens: res["usage"]["input_tokens"].as_u64().unwrap_or_default(),
+            completion_tokens: res["usage"]["output_tokens"].as_u64().unwrap_or_default(),
+        };
+
+        Ok(openai::completion(
+            res["id"].as_str().unwrap_or_default(),
+            model,
+            &content,
+            finish_reason(res["stop_reason"].as_str()),
+            Some(usage),
+        ))
+    }
+
+    fn stream(&self, model: &str) -> Box<dyn StreamTranslator> {
+        Box::new(AnthropicStream {
+            id: String::new(),
+            model: model.to_string(),
+        })
+    }
+}
+
+/// Text and base64 images, Anthropic does not fetch image urls.
+fn content(content: Option<&Content>) -> Result<Value> {
+    let parts = match content {
+        None => return Ok(json!("")),
+        Some(Content::Text(text)) => return Ok(json!(text)),
+        Some(Content::Parts(parts)) => parts,
+    };
+
+    parts
+        .iter()
+        .map(|part| match part {
+            ContentPart::Text { text } => Ok(json!({ "type": "text", "text": text })),
+            ContentPart::ImageUrl { image_url } => {
+                let (media_type, data) = image_url
+                    .url
+                    .strip_prefix("data:")
+                    .and_then(|url| url.split_once(";base64,"))
+                    .ok_or_else(|| {
+                        Error::UnsupportedContent("image urls other than base64 data".to_string())
+                    })?;
+                Ok(json!({
+                    "type": "image",
+                    "source": { "type": "base64", "media_type": media_type, "data": data },
+                }))
+            }
+        })
+        .collect()
+}
+
+fn finish_reason(stop_reason: Option<&str>) -> &'static str {
+    match stop_reason {
+        Some("max_tokens") => "length",
+        _ => "stop",
+    }
+}
+
+struct AnthropicStream {
+    id: String,
+    model: String,
+}
+
+impl StreamTranslator for AnthropicStream {
+    fn line(&mut self, line: &str) -> Result<Vec<Value>> {
+        let Some(event) = sse_data(line)? else {
+            return Ok(Vec::new());
+        };
+
+        let chunk = match event["type"].as_str() {
+            Some("message_start") => {
+                self.id = event["message"]["id"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string();
+                let delta = json!({ "role": "assistant", "content": "" });
+                openai::chunk(&self.id, &self.model, delta, None)
+            }
+            Some("content_block_delta") => {
+                let text = event["delta"]["text"].as_str().unwrap_or_default();
+                openai::chunk(&self.id, &self.model, json!({ "content": text }), None)
+            }
+            Some("message_delta") => {
+                let finish_reason = finish_reason(event["delta"]["stop_reason"].as_str());
+                openai::chunk(&self.id, &self.model, json!({}), Some(finish_reason))
+            }
+            Some("error") => {
+                let message = event["error"]["message"].as_str().unwrap_or_default();
+                openai::error(message, "upstream_error")
+            }
+            _ => return Ok(Vec::new()),
+        };
+
+        Ok(vec![chunk])
+    }
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_anthropic_request_ok() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_req: ChatRequest = serde_json::from_value(json!({
+            "model": "claude-2.1",
+            "messages": [
+                { "role": "system", "content": "Be brief." },
+                { "role": "user", "content": "Hello!" },
+            ],
+            "stop": "\n\n",
+        }))?;
+
+        // -- Exec
+        let body = Anthropic.request(&fx_req)?;
+
+        // -- Check
+        assert_eq!(body["system"], "Be brief.");
+        assert_eq!(body["messages"][0]["content"], "Hello!");
+        assert_eq!(body["max_tokens"], DEFAULT_MAX_TOKENS);
+        assert_eq!(body["stop_sequences"], json!(["\n\n"]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_anthropic_stream_ok() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_lines = [
+            "event: message_start",
+            r#"data: {"type":"message_start","message":{"id":"msg_1","usage":{"input_tokens":9}}}"#,
+            "",
+            r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hi"}}"#,
+            r#"data: {"type":"message_delta","delta":{"stop_reason":"max_tokens"}}"#,
+            r#"data: {"type":"message_stop"}"#,
+        ];
+        let mut stream = Anthropic.stream("claude-2.1");
+
+        // -- Exec
+        let mut chunks = Vec::new();
+        for line in fx_lines {
+            chunks.extend(stream.line(line)?);
+        }
+
+        // -- Check
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0]["id"], "msg_1");
+        assert_eq!(chunks[1]["choices"][0]["delta"]["content"], "Hi");
+        assert_eq!(chunks[2]["choices"][0]["finish_reason"], "length");
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
@@ -0,0 +1,173 @@
+// Cohere chat API.
+
+use serde_json::{json, Map, Value};
+
+use super::openai::{self, ChatRequest, Usage};
+use super::stream::StreamTranslator;
+use super::{Dialect, Error, Result};
+
+#[derive(Debug)]
+pub struct Cohere;
+
+impl Dialect for Cohere {
+    fn path(&self) -> &'static str {
+        "/v1/chat"
+    }
+
+    fn request(&self, req: &ChatRequest) -> Result<Value> {
+        let mut preamble = Vec::new();
+        let mut chat_history = Vec::new();
+        for message in &req.messages {
+            let role = match message.role.as_str() {
+                "system" => {
+                    preamble.push(message.text());
+                    continue;
+                }
+                "user" => "USER",
+                "assistant" => "CHATBOT",
+                role => return Err(Error::UnsupportedContent(format!("role {role}"))),
+            };
+            chat_history.push(json!({ "role": role, "message": message.text() }));
+        }
+
+        // The last user message is the one answered.
+        let message = match chat_history.pop() {
+            Some(message) if message["role"] == "USER" => message["message"].clone(),
+            _ => {
+                return Err(Error::InvalidRequest(
+                    "the last message must be from the user".to_string(),
+                ))
+            }
+        };
+
+        let mut body = Map::new();
+        body.insert("model".into(), json!(req.model));
+        body.insert("message".into(), message);
+        body.insert("chat_history".into(), json!(chat_history));
+        if !preamble.is_empty() {
+            body.insert("preamble".into(), json!(preamble.join("\n")));
+        }
+        if let Some(max_tokens) = req.max_tokens {
+            body.insert("max_tokens".into(), json!(max_tokens));
+        }
+        if let Some(temperature) = req.temperature {
+            body.insert("temperature".into(), json!(temperature));
+        }
+        if let Some(top_p) = req.top_p {
+            body.insert("p".into(), json!(top_p));
+        }
+        if let Some(stop) = &req.stop {
+            body.insert("stop_sequences".into(), json!(stop.to_vec()));
+        }
+        if req.stream {
+            body.insert("stream".into(), json!(true));
+        }
+
+        Ok(Value::Object(body))
+    }
+
+    fn response(&self, res: Value, model: &str) -> Result<Value> {
+        let content = res["text"]
+            .as_str()
+            .ok_or_else(|| Error::InvalidResponse("no text".to_string()))?;
+        let billed_units = &res["meta"]["billed_units"];
+        let usage = Usage {
+            prompt_tokens: billed_units["input_tokens"].as_u64().unwrap_or_default(),
+            completion_tokens: billed_units["output_tokens"].as_u64().unwrap_or_default(),
+        };
+
+        Ok(openai::completion(
+            res["generation_id"].as_str().unwrap_or_default(),
+            model,
+            content,
+            finish_reason(res["finish_reason"].as_str()),
+            Some(usage),
+        ))
+    }
+
+    fn stream(&self, model: &str) -> Box<dyn StreamTranslator> {
+        Box::new(CohereStream {
+            id: String::new(),
+            model: model.to_string(),
+        })
+    }
+}
+
+fn finish_reason(finish_reason: Option<&str>) -> &'static str {
+    match finish_reason {
+        Some("MAX_TOKENS") => "length",
+        Some("ERROR_TOXIC") => "content_filter",
+        _ => "stop",
+    }
+}
+
+/// Cohere streams one JSON event per line.
+struct CohereStream {
+    id: String,
+    model: String,
+}
+
+impl StreamTranslator for CohereStream {
+    fn line(&mut self, line: &str) -> Result<Vec<Value>> {
+        if line.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        let event: Value =
+            serde_json::from_str(line).map_err(|e| Error::InvalidResponse(e.to_string()))?;
+
+        let chunk = match event["event_type"].as_str() {
+            Some("stream-start") => {
+                self.id = event["generation_id"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string();
+                let delta = json!({ "role": "assistant", "content": "" });
+                openai::chunk(&self.id, &self.model, delta, None)
+            }
+            Some("text-generation") => {
+                let text = event["text"].as_str().unwrap_or_default();
+                openai::chunk(&self.id, &self.model, json!({ "content": text }), None)
+            }
+            Some("stream-end") => {
+                let finish_reason = finish_reason(event["finish_reason"].as_str());
+                openai::chunk(&self.id, &self.model, json!({}), Some(finish_reason))
+            }
+            _ => return Ok(Vec::new()),
+        };
+
+        Ok(vec![chunk])
+    }
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_cohere_request_ok() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_req: ChatRequest = serde_json::from_value(json!({
+            "model": "command",
+            "messages": [
+                { "role": "system", "content": "Be brief." },
+                { "role": "user", "content": "Hello!" },
+                { "role": "assistant", "content": "Hi." },
+                { "role": "user", "content": [{ "type": "text", "text": "Bye!" }] },
+            ],
+        }))?;
+
+        // -- Exec
+        let body = Cohere.request(&fx_req)?;
+
+        // -- Check
+        assert_eq!(body["preamble"], "Be brief.");
+        assert_eq!(body["message"], "Bye!");
+        assert_eq!(body["chat_history"][1]["role"], "CHATBOT");
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
@@ -0,0 +1,75 @@
+// region:    --- Modules
+
+mod anthropic;
+mod cohere;
+mod error;
+mod openai;
+mod stream;
+
+pub use self::error::{Error, Result};
+pub use self::openai::ChatRequest;
+pub use self::stream::{translate_stream, StreamTranslator};
+
+use std::fmt::Debug;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use self::anthropic::Anthropic;
+use self::cohere::Cohere;
+
+// endregion: --- Modules
+
+/// Native format of the chat API of a provider.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiFormat {
+    #[default]
+    #[serde(rename = "openai")]
+    OpenAi,
+    Anthropic,
+    Cohere,
+}
+
+/// Translates OpenAI chat completions to and from the native format of a
+/// provider.
+pub trait Dialect: Debug + Send + Sync {
+    /// Path of the chat endpoint of the provider.
+    fn path(&self) -> &'static str;
+
+    fn request(&self, req: &ChatRequest) -> Result<Value>;
+
+    fn response(&self, res: Value, model: &str) -> Result<Value>;
+
+    fn stream(&self, model: &str) -> Box<dyn StreamTranslator>;
+}
+
+/// Dialect of the format, `None` for OpenAI compatible providers.
+pub fn dialect(format: ApiFormat) -> Option<Box<dyn Dialect>> {
+    match format {
+        ApiFormat::OpenAi => None,
+        ApiFormat::Anthropic => Some(Box::new(Anthropic)),
+        ApiFormat::Cohere => Some(Box::new(Cohere)),
+    }
+}
+
+/// OpenAI error of a failed response, with the message of the provider when
+/// it can be found.
+pub fn error_body(body: &[u8]) -> Value {
+    let message = serde_json::from_slice::<Value>(body)
+        .ok()
+        .and_then(|body| {
+            body["error"]["message"]
+                .as_str()
+                .or(body["message"].as_str())
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| String::from_utf8_lossy(body).into_owned());
+
+    openai::error(&message, "upstream_error")
+}
+
+/// OpenAI error raised by matador itself.
+pub fn error(message: &str, kind: &str) -> Value {
+    openai::error(message, kind)
+}
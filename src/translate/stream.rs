@@ -0,0 +1,102 @@
+// Streamed responses, translated line by line to OpenAI chunks.
+
+use axum::body::{Bytes, HttpBody};
+use futures_util::stream::{self, Stream};
+use serde_json::Value;
+
+use super::{Error, Result};
+
+/// Translates the stream of a provider, server-sent events or JSON lines.
+pub trait StreamTranslator: Send {
+    /// OpenAI chunks of a line of the stream, without its line ending.
+    fn line(&mut self, line: &str) -> Result<Vec<Value>>;
+}
+
+struct StreamState<B> {
+    body: B,
+    translator: Box<dyn StreamTranslator>,
+    buffer: Vec<u8>,
+    done: bool,
+}
+
+/// The body translated to OpenAI chunks as server-sent events, ending with
+/// `[DONE]`.
+pub fn translate_stream<B>(
+    body: B,
+    translator: Box<dyn StreamTranslator>,
+) -> impl Stream<Item = Result<Bytes>>
+where
+    B: HttpBody<Data = Bytes> + Send + Unpin,
+    B::Error: std::fmt::Display,
+{
+    let state = StreamState {
+        body,
+        translator,
+        buffer: Vec::new(),
+        done: false,
+    };
+
+    stream::unfold(state, |mut state| async move {
+        while !state.done {
+            let events = match state.body.data().await {
+                Some(Ok(data)) => {
+                    state.buffer.extend_from_slice(&data);
+                    state.complete_lines()
+                }
+                Some(Err(e)) => Err(Error::StreamBody(e.to_string())),
+                None => {
+                    state.done = true;
+                    let rest = std::mem::take(&mut state.buffer);
+                    state
+                        .translate(&String::from_utf8_lossy(&rest))
+                        .map(|events| events + "data: [DONE]\n\n")
+                }
+            };
+
+            match events {
+                Ok(events) if events.is_empty() => continue,
+                Ok(events) => return Some((Ok(Bytes::from(events)), state)),
+                Err(e) => {
+                    state.done = true;
+                    return Some((Err(e), state));
+                }
+            }
+        }
+
+        None
+    })
+}
+
+impl<B> StreamState<B> {
+    /// Events of the complete lines of the buffer, keeping the last partial one.
+    fn complete_lines(&mut self) -> Result<String> {
+        let Some(end) = self.buffer.iter().rposition(|byte| *byte == b'\n') else {
+            return Ok(String::new());
+        };
+        let lines: Vec<u8> = self.buffer.drain(..=end).collect();
+
+        self.translate(&String::from_utf8_lossy(&lines))
+    }
+
+    fn translate(&mut self, lines: &str) -> Result<String> {
+        let mut events = String::new();
+        for line in lines.lines() {
+            for chunk in self.translator.line(line.trim_end_matches('\r'))? {
+                events.push_str(&format!("data: {chunk}\n\n"));
+            }
+        }
+
+        Ok(events)
+    }
+}
+
+/// JSON of a `data:` line of server-sent events, `None` for the other lines.
+pub fn sse_data(line: &str) -> Result<Option<Value>> {
+    let Some(data) = line.strip_prefix("data:") else {
+        return Ok(None);
+    };
+
+    serde_json::from_str(data.trim())
+        .map(Some)
+        .map_err(|e| Error::InvalidResponse(e.to_string()))
+}
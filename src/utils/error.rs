@@ -7,6 +7,9 @@ pub enum Error {
 
     // -- Base64
     FailToB64uDecode,
+
+    // -- Request
+    InvalidUri(String),
 }
 
 // region:    --- Error Boilerplate
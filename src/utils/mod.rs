@@ -3,9 +3,8 @@
 mod error;
 
 use axum::http::uri::PathAndQuery;
-use axum::http::{self, HeaderValue, Request};
-use base64_url::base64::engine::{self, general_purpose};
-use base64_url::base64::{alphabet, Engine as _};
+use axum::http::{Request, Uri};
+use reqwest::Url;
 use time::format_description::well_known::Rfc3339;
 use time::{Duration, OffsetDateTime};
 
@@ -50,54 +49,50 @@ pub fn b64u_decode(b64u: &str) -> Result<String> {
 // region:    --- Request Manipulation
 
 const HOST: &str = "host";
-const X_API_KEY: &str = "x-api-key";
-const AUTHORIZATION: &str = "authorization";
 
 pub fn remove_host_header<B>(req: &mut Request<B>) {
     req.headers_mut().remove(HOST);
 }
 
-pub fn insert_x_api_key_header<B>(req: &mut Request<B>, auth: &str) {
-    req.headers_mut()
-        .insert(X_API_KEY, HeaderValue::from_str(auth).unwrap());
+/// The uri with the query param `name` set to `value`, the other params kept.
+pub fn set_query_param(uri: &Uri, name: &str, value: &str) -> Result<Uri> {
+    let path_and_query = uri
+        .path_and_query()
+        .map(PathAndQuery::as_str)
+        .unwrap_or("/");
+    // Only used to edit the query, the scheme and host are dropped below.
+    let mut url = Url::parse(&format!("http://localhost{path_and_query}"))
+        .map_err(|e| Error::InvalidUri(e.to_string()))?;
+
+    let params: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(param, _)| param != name)
+        .map(|(param, value)| (param.into_owned(), value.into_owned()))
+        .collect();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(params)
+        .append_pair(name, value);
+
+    set_path_and_query(
+        uri,
+        &format!("{}?{}", url.path(), url.query().unwrap_or_default()),
+    )
 }
 
-pub fn insert_auth_bearer_header<B>(req: &mut Request<B>, auth: &str) {
-    let auth = format!("Bearer {}", auth);
-    req.headers_mut()
-        .insert(AUTHORIZATION, HeaderValue::from_str(auth.as_str()).unwrap());
+/// The uri with its path replaced, the query kept.
+pub fn set_path(uri: &Uri, path: &str) -> Result<Uri> {
+    match uri.query() {
+        Some(query) => set_path_and_query(uri, &format!("{path}?{query}")),
+        None => set_path_and_query(uri, path),
+    }
 }
 
-pub fn insert_auth_basic_header<B>(req: &mut Request<B>, auth: &str) {
-    // base64 encode the auth string
-    let auth = format!(
-        "Basic {}",
-        general_purpose::STANDARD.encode(auth.as_bytes())
-    );
-    req.headers_mut()
-        .insert("authorization", HeaderValue::from_str(&auth).unwrap());
-}
-
-pub fn insert_auth_token_header<B>(req: &mut Request<B>, auth: &str) {
-    let auth = format!("Token {}", auth);
-    req.headers_mut()
-        .insert(AUTHORIZATION, HeaderValue::from_str(auth.as_str()).unwrap());
-}
+fn set_path_and_query(uri: &Uri, path_and_query: &str) -> Result<Uri> {
+    let mut uri_parts = uri.clone().into_parts();
+    uri_parts.path_and_query =
+        Some(PathAndQuery::try_from(path_and_query).map_err(|e| Error::InvalidUri(e.to_string()))?);
 
-pub fn add_key_query_param<B>(req: &mut Request<B>, auth: &str) {
-    let mut parts = req.uri().clone().into_parts();
-    let key_param = format!("key={}", auth);
-    parts.path_and_query = Some(
-        PathAndQuery::from_maybe_shared(format!(
-            "{}?{}",
-            parts
-                .path_and_query
-                .map(|pq| pq.path().to_string())
-                .unwrap_or_default(),
-            key_param
-        ))
-        .unwrap(),
-    );
-    *req.uri_mut() = http::Uri::from_parts(parts).unwrap();
+    Uri::from_parts(uri_parts).map_err(|e| Error::InvalidUri(e.to_string()))
 }
 // endregion: --- Request Manipulation
@@ -3,7 +3,7 @@ use axum::response::{IntoResponse, Response};
 use serde::Serialize;
 use tracing::{debug, error};
 
-use crate::{crypt, lightning, model, web};
+use crate::{auth, crypt, lightning, model, web};
 
 #[allow(dead_code)]
 pub type Result<T> = core::result::Result<T, Error>;
@@ -30,7 +30,12 @@ pub enum Error {
     // -- CtxExtError
     // CtxExt(web::mw_auth::CtxExtError),
 
+    // -- Upstream
+    UpstreamBody(String),
+    UpstreamUri(String),
+
     // -- Modules
+    Auth(auth::Error),
     Model(model::Error),
     Crypt(crypt::Error),
     Lightning(lightning::Error),
@@ -46,6 +51,12 @@ impl From<model::Error> for Error {
     }
 }
 
+impl From<auth::Error> for Error {
+    fn from(val: auth::Error) -> Self {
+        Self::Auth(val)
+    }
+}
+
 impl From<crypt::Error> for Error {
     fn from(val: crypt::Error) -> Self {
         Self::Crypt(val)
@@ -0,0 +1,200 @@
+// Virtual routes trying OpenAI compatible providers in order.
+
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use axum::body::{Body, Bytes};
+use axum::http::header::CONTENT_LENGTH;
+use axum::http::{HeaderValue, Request, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use futures_util::future::BoxFuture;
+use serde_json::Value;
+use tower::ServiceExt;
+use tracing::info;
+
+use super::upstream::clone_parts;
+use super::{Error, Result};
+use crate::config::apis::{FallbackParams, FallbackTarget};
+
+/// Provider serving the response of a fallback route.
+const X_MATADOR_PROVIDER: &str = "x-matador-provider";
+
+/// Upstream of a provider, shared with its own route. The boxed tower
+/// services are not `Sync`, as the router needs.
+pub type ProviderService = Arc<dyn Fn(Request<Body>) -> BoxFuture<'static, Response> + Send + Sync>;
+
+pub fn provider_service<S>(service: S) -> ProviderService
+where
+    S: tower::Service<Request<Body>, Response = Response, Error = Infallible>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    S::Future: Send,
+{
+    Arc::new(move |req| {
+        let service = service.clone();
+        Box::pin(async move {
+            service
+                .oneshot(req)
+                .await
+                .unwrap_or_else(|infallible| match infallible {})
+        })
+    })
+}
+
+/// Forwards a chat completion to the first target of the route, and to the
+/// next ones while they fail. It sits behind `mw_402`, so the request is
+/// charged once whatever the number of targets tried.
+#[derive(Clone)]
+pub struct FallbackService {
+    name: String,
+    targets: Arc<Vec<(FallbackTarget, ProviderService)>>,
+}
+
+impl FallbackService {
+    pub fn new(params: &FallbackParams, targets: Vec<(FallbackTarget, ProviderService)>) -> Self {
+        Self {
+            name: params.name.clone(),
+            targets: Arc::new(targets),
+        }
+    }
+}
+
+impl tower::Service<Request<Body>> for FallbackService {
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = core::result::Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<core::result::Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let service = self.clone();
+
+        Box::pin(async move {
+            let res = service
+                .forward(req)
+                .await
+                .unwrap_or_else(IntoResponse::into_response);
+            Ok(res)
+        })
+    }
+}
+
+impl FallbackService {
+    async fn forward(&self, req: Request<Body>) -> Result<Response> {
+        let (parts, body) = req.into_parts();
+        let body = hyper::body::to_bytes(body)
+            .await
+            .map_err(|e| Error::UpstreamBody(e.to_string()))?;
+
+        let mut last_res = None;
+        for (target, provider) in self.targets.iter() {
+            let mut target_parts = clone_parts(&parts);
+            target_parts.uri = target_uri(target, &parts.uri)?;
+            let target_body = match &target.model {
+                Some(model) => {
+                    target_parts.headers.remove(CONTENT_LENGTH);
+                    with_model(&body, model)
+                }
+                None => body.clone(),
+            };
+
+            let mut res =
+                provider(Request::from_parts(target_parts, Body::from(target_body))).await;
+            if !should_fall_back(res.status()) {
+                if let Ok(provider) = HeaderValue::from_str(&target.provider) {
+                    res.headers_mut().insert(X_MATADOR_PROVIDER, provider);
+                }
+                return Ok(res);
+            }
+
+            info!(
+                "{}: {} failed with {}, falling back",
+                self.name,
+                target.provider,
+                res.status()
+            );
+            last_res = Some(res);
+        }
+
+        Ok(last_res.unwrap_or_else(|| StatusCode::BAD_GATEWAY.into_response()))
+    }
+}
+
+/// Failures of the provider, which another provider may not have. The errors
+/// of the request itself are returned as is.
+fn should_fall_back(status: StatusCode) -> bool {
+    status.is_server_error()
+        || matches!(
+            status,
+            StatusCode::TOO_MANY_REQUESTS
+                | StatusCode::UNAUTHORIZED
+                | StatusCode::PAYMENT_REQUIRED
+                | StatusCode::FORBIDDEN
+                | StatusCode::REQUEST_TIMEOUT
+        )
+}
+
+fn target_uri(target: &FallbackTarget, uri: &Uri) -> Result<Uri> {
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{}?{}", target.path, query),
+        None => target.path.clone(),
+    };
+
+    path_and_query
+        .parse()
+        .map_err(|_| Error::UpstreamUri(target.path.clone()))
+}
+
+/// The body with its `model` replaced, as is if it is not a JSON object.
+fn with_model(body: &Bytes, model: &str) -> Bytes {
+    match serde_json::from_slice::<Value>(body) {
+        Ok(Value::Object(mut object)) => {
+            object.insert("model".to_string(), Value::String(model.to_string()));
+            Value::Object(object).to_string().into()
+        }
+        _ => body.clone(),
+    }
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_with_model_ok() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_body = Bytes::from(r#"{"model":"gpt-3.5-turbo","messages":[]}"#);
+
+        // -- Exec
+        let body = with_model(&fx_body, "mixtral-8x7b-instruct");
+
+        // -- Check
+        let body: Value = serde_json::from_slice(&body)?;
+        assert_eq!(body["model"], "mixtral-8x7b-instruct");
+        assert_eq!(body["messages"], Value::Array(vec![]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_should_fall_back() -> Result<()> {
+        // -- Check
+        assert!(should_fall_back(StatusCode::BAD_GATEWAY));
+        assert!(should_fall_back(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!should_fall_back(StatusCode::BAD_REQUEST));
+        assert!(!should_fall_back(StatusCode::OK));
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
@@ -3,11 +3,17 @@ pub mod mw;
 // region:    --- Modules
 
 mod error;
+mod fallback;
 pub mod router;
+mod unified;
+mod upstream;
 
 // pub mod mw_auth;
 // pub mod mw_res_map;
 // pub mod routes_login;
+pub mod routes_admin;
+pub mod routes_l402;
+pub mod routes_quote;
 pub mod routes_static;
 // pub mod rpc;
 
@@ -4,7 +4,7 @@ use reqwest::StatusCode;
 use serde::Serialize;
 use tracing::debug;
 
-use crate::lightning;
+use crate::{lightning, model};
 
 pub type Result<T> = core::result::Result<T, Error>;
 
@@ -13,7 +13,10 @@ pub type Result<T> = core::result::Result<T, Error>;
 pub enum Error {
     InvalidHeaderValue(String),
     InvalidRoute(String),
+    RequestBody(String),
+    RequestBodyTooLarge { limit: usize },
     Lightning(lightning::Error),
+    Model(model::Error),
 }
 
 // region:    --- Froms
@@ -28,6 +31,11 @@ impl From<lightning::Error> for Error {
         Self::Lightning(val)
     }
 }
+impl From<model::Error> for Error {
+    fn from(val: model::Error) -> Self {
+        Self::Model(val)
+    }
+}
 // endregion: --- Froms
 
 // region:    --- Axum IntoResponse
@@ -35,6 +43,11 @@ impl IntoResponse for Error {
     fn into_response(self) -> Response {
         debug!("{:<12} - model::Error {self:?}", "INTO_RES");
 
+        if let Self::RequestBodyTooLarge { limit } = self {
+            let message = format!("The request body is larger than {limit} bytes");
+            return (StatusCode::PAYLOAD_TOO_LARGE, message).into_response();
+        }
+
         // Create a placeholder Axum reponse.
         let mut response = StatusCode::INTERNAL_SERVER_ERROR.into_response();
 
@@ -1,3 +1,3 @@
 mod error;
-pub mod mw_add_api_auth;
+pub mod mw_admin_auth;
 pub mod mw_l402;
@@ -0,0 +1,78 @@
+use axum::http::{HeaderMap, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use subtle::ConstantTimeEq;
+use tracing::{debug, info};
+
+use super::error::Result;
+use crate::config::config::config;
+
+const AUTHORIZATION: &str = "authorization";
+
+/// Guards the admin routes with the `SERVICE_ADMIN_API_KEY` bearer token.
+/// Without a configured key the admin routes do not exist.
+pub async fn mw_admin_auth<B>(req: Request<B>, next: Next<B>) -> Result<Response> {
+    debug!("{:<12} - mw_admin_auth", "MIDDLEWARE");
+
+    let config = config();
+    let Some(authorized) = check_admin_key(config.ADMIN_API_KEY.as_deref(), req.headers()) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    if !authorized {
+        info!("Admin request with invalid credentials");
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    Ok(next.run(req).await)
+}
+
+/// Whether the bearer token is the admin key, `None` without a key, an empty
+/// one included, as it would let an empty bearer in.
+fn check_admin_key(admin_key: Option<&str>, headers: &HeaderMap) -> Option<bool> {
+    let admin_key = admin_key.filter(|key| !key.trim().is_empty())?;
+
+    let bearer = headers
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "));
+    // Compared in constant time, not to leak the key through the timing.
+    let authorized =
+        bearer.is_some_and(|bearer| bool::from(bearer.as_bytes().ct_eq(admin_key.as_bytes())));
+
+    Some(authorized)
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_check_admin_key_empty() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_bearer = |token: &str| -> Result<HeaderMap> {
+            Ok(HeaderMap::from_iter([(
+                AUTHORIZATION.parse()?,
+                format!("Bearer {token}").parse()?,
+            )]))
+        };
+
+        // -- Exec & Check
+        assert_eq!(check_admin_key(Some(""), &fx_bearer("")?), None);
+        assert_eq!(check_admin_key(Some("  "), &fx_bearer("  ")?), None);
+        assert_eq!(check_admin_key(None, &fx_bearer("")?), None);
+        assert_eq!(
+            check_admin_key(Some("secret"), &fx_bearer("")?),
+            Some(false)
+        );
+        assert_eq!(
+            check_admin_key(Some("secret"), &fx_bearer("secret")?),
+            Some(true)
+        );
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
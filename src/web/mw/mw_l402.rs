@@ -1,25 +1,72 @@
+use std::time::Duration;
+
+use axum::body::{Body, Bytes};
+use axum::extract::State;
+use axum::http::header::CONTENT_LENGTH;
+use axum::http::request::Parts;
 use axum::http::{HeaderName, HeaderValue, Request, StatusCode};
 use axum::middleware::Next;
 use axum::response::{IntoResponse, Response};
+use http_body::{LengthLimitError, Limited};
 use moksha_core::model::{Proofs, TokenV3};
 use moksha_core::*;
 use serde::{Deserialize, Serialize};
+use tracing::info;
 
-use super::error::Result;
+use super::error::{Error, Result};
+use crate::config::apis::apis_config;
+use crate::config::bundles::{bundles_config, BundleParams};
 use crate::config::config::config;
+use crate::config::env_var;
+use crate::config::products::Product;
+use crate::ctx::Ctx;
 use crate::lightning::l402::L402;
-use crate::lightning::{Cashu402Builder, L402Builder, LightningAddress};
+use crate::lightning::{Cashu402Builder, Caveat, L402Builder, LightningAddress, Meter};
+use crate::model::comp_token::CompTokenBmc;
+use crate::model::token_usage::{TokenUsage, TokenUsageBmc, UsageLeft};
+use crate::model::ModelManager;
+use crate::policy;
+use crate::pricing::{self, service_price_msat, split_service, PricedRequest, DEFAULT_PRICE_MSAT};
 
+const RETRY_AFTER: &str = "retry-after";
 const WWW_AUTHENTICATE: &str = "www-authenticate";
 const X_CASHU: &str = "x-cashu";
+const X_L402_REMAINING_USES: &str = "x-l402-remaining-uses";
+const X_L402_REMAINING_BUDGET_MSAT: &str = "x-l402-remaining-budget-msat";
+/// Sats of a Cashu token kept for the fees of its melt, on top of the price.
+const CASHU_FEE_RESERVE_SAT: u64 = 2;
+
+pub async fn mw_402(
+    State(mm): State<ModelManager>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Result<Response> {
+    if let Some(res) = wait_for_provider(&req).await {
+        return Ok(res);
+    }
+
+    // Buffered to be checked and priced, the upstream services buffer it
+    // anyway.
+    let (mut parts, body) = req.into_parts();
+    let body = read_body(body).await?;
+    let body = match check_request(&mut parts, body).await {
+        Ok(body) => body,
+        Err(e) => return Ok(e.into_response()),
+    };
+    let body = bound_request(&mut parts, body);
+    let price_msat = match request_price_msat(&parts, &body).await {
+        Ok(price_msat) => price_msat,
+        Err(e) => return Ok(e.into_response()),
+    };
+    let service = split_service(parts.uri.path()).0.to_string();
+    let req = Request::from_parts(parts, Body::from(body));
 
-pub async fn mw_402<B>(req: Request<B>, next: Next<B>) -> Result<Response> {
     let headers = req.headers().clone();
 
     // X-Cashu handling
     let cashu_header = headers.get("X-Cashu").or(headers.get("x-cashu"));
     if let Some(header) = cashu_header {
-        return handle_cashu_header(header, req, next).await;
+        return handle_cashu_header(header, &service, price_msat, req, next).await;
     }
 
     // L402 handling
@@ -27,50 +74,263 @@ pub async fn mw_402<B>(req: Request<B>, next: Next<B>) -> Result<Response> {
         .get("Authorization")
         .or(headers.get("authorization"));
     if let Some(header) = auth_header {
-        return handle_auth_header(header, req, next).await;
+        return handle_auth_header(&mm, header, &service, price_msat, req, next).await;
     }
 
     // If the authorization header is missing or does not start with "L402", return
     // a 402 error
-    handle_missing_auth_header(req).await
+    handle_missing_auth_header(&service, price_msat, req).await
+}
+
+/// Body of a request, up to `SERVICE_MAX_BODY_BYTES`.
+pub async fn read_body(body: Body) -> Result<Bytes> {
+    let limit = config().MAX_BODY_BYTES;
+    hyper::body::to_bytes(Limited::new(body, limit))
+        .await
+        .map_err(|e| match e.downcast_ref::<LengthLimitError>() {
+            Some(_) => Error::RequestBodyTooLarge { limit },
+            None => Error::RequestBody(e.to_string()),
+        })
+}
+
+/// Waits for a key of a rate limited provider, up to its queue limit, else
+/// sheds the request before it is charged.
+async fn wait_for_provider<B>(req: &Request<B>) -> Option<Response> {
+    let service = req.uri().path().split('/').nth(1).unwrap_or_default();
+    let api_params = apis_config().get_params(service)?;
+    let wait_ms = api_params.key_pool().ok()?.wait_ms() as u64;
+    if wait_ms == 0 {
+        return None;
+    }
+
+    if wait_ms <= api_params.rate_limit.queue_ms {
+        info!("{} rate limited, queueing for {}ms", service, wait_ms);
+        tokio::time::sleep(Duration::from_millis(wait_ms)).await;
+        return None;
+    }
+
+    info!("{} rate limited for {}ms, shedding", service, wait_ms);
+    let retry_after_sec = wait_ms.div_ceil(1000).to_string();
+    Some(
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(RETRY_AFTER, retry_after_sec)],
+            "Provider rate limited, retry later",
+        )
+            .into_response(),
+    )
 }
 
 async fn handle_cashu_header<B>(
     header: &HeaderValue,
+    service: &str,
+    price_msat: u64,
     req: Request<B>,
     next: Next<B>,
 ) -> Result<Response> {
-    match handle_cashu(header).await {
+    match handle_cashu(header, price_msat).await {
         true => Ok(next.run(req).await),
-        false => generate_payment_required_response().await,
+        false => generate_payment_required_response(service, price_msat).await,
     }
 }
 
 async fn handle_auth_header<B>(
+    mm: &ModelManager,
     header: &HeaderValue,
+    service: &str,
+    price_msat: u64,
     req: Request<B>,
     next: Next<B>,
 ) -> Result<Response> {
     let l402 = L402::from_auth_header(header.to_str().unwrap())?;
-    match l402.is_valid().unwrap() {
-        true => Ok(next.run(req).await),
-        false => generate_payment_required_response().await,
+    let ctx = Ctx::root_ctx();
+    let (meters, usages) = match pay_with_token(&ctx, mm, &l402, service, price_msat).await? {
+        TokenPayment::Paid { meters, usages } => (meters, usages),
+        TokenPayment::Unpaid => {
+            return generate_payment_required_response(service, price_msat).await
+        }
+        TokenPayment::TopUp(bundle) => return generate_top_up_required_response(&bundle).await,
+    };
+    if meters.is_empty() {
+        return Ok(next.run(req).await);
+    }
+
+    let mut res = next.run(req).await;
+
+    // Requests the provider failed or throttled are not charged.
+    let status = res.status();
+    let usages = match status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+        true => {
+            info!("L402 use refunded, upstream answered {}", status);
+            TokenUsageBmc::refund_use(&ctx, mm, &meters, price_msat as i64).await?
+        }
+        false => usages,
+    };
+    let left = UsageLeft::new(&meters, &usages);
+    if let Some(uses) = left.uses {
+        res.headers_mut()
+            .insert(X_L402_REMAINING_USES, HeaderValue::from(uses));
+    }
+    if let Some(budget_msat) = left.budget_msat {
+        res.headers_mut()
+            .insert(X_L402_REMAINING_BUDGET_MSAT, HeaderValue::from(budget_msat));
+    }
+
+    Ok(res)
+}
+
+/// Outcome of paying for a request with a token.
+enum TokenPayment {
+    /// The use is recorded on the meters of the token, if it has any.
+    Paid {
+        meters: Vec<Meter>,
+        usages: Vec<TokenUsage>,
+    },
+    /// Answered with a challenge for a new token.
+    Unpaid,
+    /// An exhausted bundle token, answered with a challenge to top it up.
+    TopUp(BundleParams),
+}
+
+/// Checks that the token covers a request of `price_msat` to `service`, and
+/// records the use on its meters.
+async fn pay_with_token(
+    ctx: &Ctx,
+    mm: &ModelManager,
+    l402: &L402,
+    service: &str,
+    price_msat: u64,
+) -> Result<TokenPayment> {
+    if !l402.is_valid().unwrap_or(false) {
+        return Ok(TokenPayment::Unpaid);
     }
+
+    let caveats = l402.caveats()?;
+    if !caveats.covers_service(service) {
+        info!("L402 token not valid for service: {}", service);
+        return Ok(TokenPayment::Unpaid);
+    }
+
+    if caveats.comp.is_some() {
+        let comp_token = CompTokenBmc::get_by_token_id(ctx, mm, &l402.token_id()).await?;
+        if comp_token.is_none_or(|comp_token| comp_token.revoked) {
+            info!("L402 comp token unknown or revoked");
+            return Ok(TokenPayment::Unpaid);
+        }
+    }
+
+    if !caveats.allows_price(price_msat as i64) {
+        info!("L402 token max_per_request below price: {}", price_msat);
+        return Ok(TokenPayment::Unpaid);
+    }
+
+    let meters = l402.meters()?;
+    if meters.is_empty() {
+        return Ok(TokenPayment::Paid {
+            meters,
+            usages: Vec::new(),
+        });
+    }
+
+    let usages = TokenUsageBmc::record_use(ctx, mm, &meters, price_msat as i64).await?;
+    let Some(usages) = usages else {
+        info!("L402 token has no uses, budget or time left");
+        let bundles_config = bundles_config();
+        let bundle = caveats
+            .bundle
+            .as_deref()
+            .and_then(|id| bundles_config.get(id));
+        return Ok(match bundle {
+            Some(bundle) => TokenPayment::TopUp(bundle.clone()),
+            None => TokenPayment::Unpaid,
+        });
+    };
+
+    Ok(TokenPayment::Paid { meters, usages })
+}
+
+/// Body of a request allowed by the policy of its provider, rewritten if the
+/// policy clamps it, before anything is charged.
+async fn check_request(parts: &mut Parts, body: Bytes) -> policy::Result<Bytes> {
+    let (service, path) = split_service(parts.uri.path());
+    let req = PricedRequest {
+        method: &parts.method,
+        path: &path,
+        headers: &parts.headers,
+        body: &body,
+    };
+    let Some(rewritten) = policy::check_service_request(service, &req).await? else {
+        return Ok(body);
+    };
+
+    parts
+        .headers
+        .insert(CONTENT_LENGTH, HeaderValue::from(rewritten.len()));
+    Ok(Bytes::from(rewritten))
+}
+
+/// Body of a request with the completion tokens it is priced for set, so the
+/// provider cannot generate more than is charged.
+fn bound_request(parts: &mut Parts, body: Bytes) -> Bytes {
+    let (service, path) = split_service(parts.uri.path());
+    let req = PricedRequest {
+        method: &parts.method,
+        path: &path,
+        headers: &parts.headers,
+        body: &body,
+    };
+    let Some(bounded) = pricing::service_bounded_body(service, &req) else {
+        return body;
+    };
+
+    parts
+        .headers
+        .insert(CONTENT_LENGTH, HeaderValue::from(bounded.len()));
+    Bytes::from(bounded)
+}
+
+/// Price of a single request, quoted in the challenges and checked against the
+/// spend caps of the token.
+async fn request_price_msat(parts: &Parts, body: &[u8]) -> pricing::Result<u64> {
+    let (service, path) = split_service(parts.uri.path());
+    let req = PricedRequest {
+        method: &parts.method,
+        path: &path,
+        headers: &parts.headers,
+        body,
+    };
+
+    Ok(service_price_msat(service, &req)
+        .await?
+        .unwrap_or(DEFAULT_PRICE_MSAT))
 }
 
-async fn handle_missing_auth_header<B>(req: Request<B>) -> Result<Response> {
-    generate_payment_required_response().await
+async fn handle_missing_auth_header<B>(
+    service: &str,
+    price_msat: u64,
+    req: Request<B>,
+) -> Result<Response> {
+    generate_payment_required_response(service, price_msat).await
 }
 
-async fn generate_payment_required_response() -> Result<Response> {
+/// Challenges for a token good for this request only: its service, once, at
+/// up to its price, so it cannot be replayed against other or costlier ones.
+async fn generate_payment_required_response(service: &str, price_msat: u64) -> Result<Response> {
     let mut res = StatusCode::PAYMENT_REQUIRED.into_response();
-    let l402 = L402Builder::new().build().await?;
+    let l402 = L402Builder::new()
+        .amount(price_msat)
+        .caveats(Caveat::single_request(service, price_msat))
+        .build()
+        .await?;
     res.headers_mut().insert(
         WWW_AUTHENTICATE,
         HeaderValue::from_str(&l402.to_authenticate_string()).unwrap(),
     );
 
-    let cashu402 = Cashu402Builder::new().amount(3).build().await?;
+    let cashu402 = Cashu402Builder::new()
+        .amount(cashu_amount_sat(price_msat))
+        .build()
+        .await?;
     res.headers_mut().insert(
         "x-cashu",
         HeaderValue::from_str(&cashu402.to_authenticate_string()).unwrap(),
@@ -78,45 +338,81 @@ async fn generate_payment_required_response() -> Result<Response> {
     Ok(res)
 }
 
-async fn handle_cashu(header: &HeaderValue) -> bool {
-    let header = header.to_str().unwrap().to_string();
-    let tokens = TokenV3::deserialize(header).unwrap();
-    let mint_url = tokens.tokens.get(0).unwrap().clone().mint.unwrap();
-    println!("mint_url: {}", mint_url);
-    println!("cashu_mint_url: {}", config().CASHU_MINT_URL);
+/// Answers an exhausted bundle token with a challenge for the same bundle.
+async fn generate_top_up_required_response(bundle: &BundleParams) -> Result<Response> {
+    let mut res = StatusCode::PAYMENT_REQUIRED.into_response();
+    let l402 = L402Builder::new()
+        .amount(bundle.price_msat())
+        .caveats(bundle.caveats())
+        .build()
+        .await?;
+    res.headers_mut().insert(
+        WWW_AUTHENTICATE,
+        HeaderValue::from_str(&l402.to_authenticate_string()).unwrap(),
+    );
+    res.headers_mut()
+        .insert(X_L402_REMAINING_USES, HeaderValue::from(0));
+    Ok(res)
+}
+
+/// Sats a Cashu token must hold to pay for a request of `price_msat`.
+fn cashu_amount_sat(price_msat: u64) -> u64 {
+    price_msat
+        .div_ceil(1000)
+        .saturating_add(CASHU_FEE_RESERVE_SAT)
+}
+
+/// Melts the token into an invoice of the operator. `false` if it is
+/// malformed, from another mint, worth less than the request or not melted.
+async fn handle_cashu(header: &HeaderValue, price_msat: u64) -> bool {
+    let Some(tokens) = header
+        .to_str()
+        .ok()
+        .and_then(|header| TokenV3::deserialize(header.to_string()).ok())
+    else {
+        return false;
+    };
+    let Some(mint_url) = tokens.tokens.first().and_then(|token| token.mint.clone()) else {
+        return false;
+    };
     if mint_url != config().CASHU_MINT_URL {
         return false;
     }
-    let mint_url = format!("{}/melt", mint_url.to_string());
-    let lnaddress = LightningAddress::new(
-        dotenv::var("LNADDRESS")
-            .expect("LNADDRESS not set")
-            .as_str(),
-    )
-    .await;
-    let pr = lnaddress
-        .get_invoice(((tokens.total_amount() - 2) * 1000).try_into().unwrap())
+    let total_sat = tokens.total_amount();
+    if total_sat < cashu_amount_sat(price_msat) {
+        info!("Cashu token of {total_sat} sat below the price of {price_msat} msat");
+        return false;
+    }
+
+    let Some(lnaddress) = env_var("LNADDRESS") else {
+        info!("LNADDRESS not set, Cashu tokens cannot be melted");
+        return false;
+    };
+    let Some(amount_msat) = (total_sat - CASHU_FEE_RESERVE_SAT)
+        .checked_mul(1000)
+        .and_then(|amount_msat| i64::try_from(amount_msat).ok())
+    else {
+        return false;
+    };
+    let pr = LightningAddress::new(&lnaddress)
+        .await
+        .get_invoice(amount_msat)
         .await
         .into_signed_raw()
         .to_string();
     let proofs = tokens.proofs();
-    let body = serde_json::to_string(&PostMeltRequest { pr, proofs }).unwrap();
+    let Ok(body) = serde_json::to_string(&PostMeltRequest { pr, proofs }) else {
+        return false;
+    };
 
-    let client = reqwest::Client::new();
-    let req = client
-        .post(mint_url)
+    let res = reqwest::Client::new()
+        .post(format!("{mint_url}/melt"))
         .header("Content-Type", "application/json")
-        .body(body);
-
-    let res = req.send().await.unwrap();
+        .body(body)
+        .send()
+        .await;
 
-    // println!("res: {:?}", res.text().await.unwrap());
-
-    if res.status().is_success() {
-        return true;
-    }
-
-    false
+    res.is_ok_and(|res| res.status().is_success())
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -124,3 +420,67 @@ pub struct PostMeltRequest {
     pub proofs: Proofs,
     pub pr: String,
 }
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::{anyhow, Result};
+    use macaroon::Format;
+    use serial_test::serial;
+    use sha2::Digest;
+
+    use super::*;
+    use crate::{_dev_utils, crypt};
+
+    #[serial]
+    #[tokio::test]
+    #[ignore = "needs the dev postgres, see _dev_utils"]
+    async fn test_pay_with_token_replay_err() -> Result<()> {
+        // -- Setup & Fixtures
+        let mm = _dev_utils::init_test().await;
+        let ctx = Ctx::root_ctx();
+        // A token paid after a challenge for a request of 1000 msat to openai.
+        let fx_preimage = [7u8; 32];
+        let mut fx_caveats = vec![Caveat::PaymentHash(hex::encode(sha2::Sha256::digest(
+            fx_preimage,
+        )))];
+        fx_caveats.extend(Caveat::single_request("openai", 1000));
+        let fx_token_id = uuid::Uuid::new_v4().to_string();
+        let fx_token = crypt::macaroon::generate_macaroon(&fx_token_id, fx_caveats)
+            .serialize(Format::V2)
+            .map_err(|e| anyhow!("{e:?}"))?;
+        let fx_l402 =
+            L402::from_auth_header(&format!("L402 {}:{}", fx_token, hex::encode(fx_preimage)))?;
+
+        // -- Exec
+        let costlier = pay_with_token(&ctx, &mm, &fx_l402, "openai", 2000).await?;
+        let other_service = pay_with_token(&ctx, &mm, &fx_l402, "anthropic", 1000).await?;
+        let paid = pay_with_token(&ctx, &mm, &fx_l402, "openai", 1000).await?;
+        let replayed = pay_with_token(&ctx, &mm, &fx_l402, "openai", 1000).await?;
+
+        // -- Check
+        assert!(matches!(costlier, TokenPayment::Unpaid));
+        assert!(matches!(other_service, TokenPayment::Unpaid));
+        assert!(matches!(paid, TokenPayment::Paid { .. }));
+        assert!(matches!(replayed, TokenPayment::Unpaid));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_handle_cashu_err_malformed() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_headers = ["cashuAnot-base64", "cashuAeyJ0b2tlbiI6W119", "L402 abc:def"];
+
+        // -- Exec & Check
+        for fx_header in fx_headers {
+            assert!(!handle_cashu(&HeaderValue::from_static(fx_header), 1000).await);
+        }
+        // The price rounded up to the sat, and the melt fees.
+        assert_eq!(cashu_amount_sat(1), 3);
+        assert_eq!(cashu_amount_sat(21_000), 23);
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
@@ -1,18 +1,45 @@
 // src/router.rs
 
-use axum::routing::get;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+
+use axum::body::Body;
+use axum::response::Response;
 use axum::{middleware, Router};
-use reverse_proxy_service::TrimPrefix;
-use tracing::info;
+use http::Request;
+use hyper::client::connect::Connect;
+use hyper::Client;
+use hyperlocal::UnixConnector;
+use once_cell::sync::Lazy;
+use reverse_proxy_service::{PathRewriter, ReusedServiceBuilder};
+use tokio::signal::unix::{signal, SignalKind};
+use tower::util::Oneshot;
+use tower::{Service, ServiceExt};
+use tracing::{error, info};
 
-use super::mw::mw_add_api_auth::add_auth;
+use super::fallback::{provider_service, FallbackService, ProviderService};
 use super::mw::mw_l402::mw_402;
-use crate::config::apis::{apis_config, ApiParams, ApisConfig};
-use crate::web::routes_static;
+use super::unified::UnifiedService;
+use super::upstream::UpstreamService;
+use crate::config::apis::{
+    apis_config, swap_apis_config, ApiParams, ApisConfig, Scheme, CHAT_COMPLETIONS_PATH,
+};
+use crate::config::bundles::{swap_bundles_config, BundlesConfig};
+use crate::config::config::{swap_config, Config};
+use crate::config::passes::{swap_passes_config, PassesConfig};
+use crate::config::{reload_dotenv, restore_dotenv};
+use crate::model::ModelManager;
+use crate::web::{routes_admin, routes_l402, routes_quote, routes_static};
 use anyhow::{Error, Result};
-use http::{header, HeaderValue, Method};
 use tower_http::cors::{Any, CorsLayer};
 
+/// Provider routes, rebuilt and swapped on reload. `Router` is not `Sync`, so
+/// it is cloned under the lock for each request.
+static PROXY_ROUTER: Lazy<Mutex<Router>> = Lazy::new(|| Mutex::new(Router::new()));
+
 fn log_error<T, E: std::fmt::Debug>(result: Result<T, E>) -> Result<T, E> {
     if let Err(ref e) = result {
         info!("Error: {:?}", e);
@@ -20,50 +47,259 @@ fn log_error<T, E: std::fmt::Debug>(result: Result<T, E>) -> Result<T, E> {
     result
 }
 
-pub fn setup_router() -> Result<Router> {
-    let cors = CorsLayer::new()
+fn cors_layer() -> CorsLayer {
+    CorsLayer::new()
         .allow_methods(Any)
         .allow_origin(Any)
         .allow_headers(Any)
-        .expose_headers(Any);
+        .expose_headers(Any)
+}
 
-    let mut router = Router::new();
-    let router = log_error(set_api_proxy_routes(router))?;
-    let router = log_error(set_l402_wrapper(router))?;
-    let router = router
-        .layer(cors)
-        .fallback_service(routes_static::serve_dir());
+pub fn setup_router(mm: ModelManager) -> Result<Router> {
+    let proxy_router = build_proxy_router(&apis_config(), mm.clone())?;
+    swap_proxy_router(proxy_router);
+
+    let router = Router::new()
+        .merge(routes_l402::routes(mm.clone()))
+        .merge(routes_quote::routes())
+        .merge(routes_admin::routes(mm))
+        .layer(cors_layer())
+        .fallback_service(ReloadableProxy);
 
     Ok(router)
 }
 
-fn set_l402_wrapper(mut router: Router) -> Result<Router> {
-    router = router.layer(middleware::from_fn(mw_402));
+/// Reloads the .env file, the config, the products and the providers. Nothing
+/// is swapped unless all of them are valid, so a bad edit keeps the current
+/// setup.
+///
+/// Run on a blocking thread, as it reads files and may run credential commands.
+pub async fn reload(mm: ModelManager) -> Result<()> {
+    tokio::task::spawn_blocking(move || reload_blocking(mm)).await?
+}
+
+fn reload_blocking(mm: ModelManager) -> Result<()> {
+    let previous_dotenv = reload_dotenv();
+    let loaded = load_all(mm);
+    let (config, passes_config, bundles_config, apis_config, proxy_router) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            restore_dotenv(previous_dotenv);
+            return Err(e);
+        }
+    };
+
+    info!("Reloaded {} providers", apis_config.providers.len());
+    swap_config(config);
+    swap_passes_config(passes_config);
+    swap_bundles_config(bundles_config);
+    swap_apis_config(apis_config);
+    swap_proxy_router(proxy_router);
+
+    Ok(())
+}
+
+fn load_all(mm: ModelManager) -> Result<(Config, PassesConfig, BundlesConfig, ApisConfig, Router)> {
+    let config = Config::load_from_env().map_err(|e| Error::msg(format!("config: {e:?}")))?;
+    let passes_config = PassesConfig::load_from_env()?;
+    let bundles_config = BundlesConfig::load_from_env()?;
+    let apis_config = ApisConfig::load(Some(&apis_config()))?;
+    let proxy_router = build_proxy_router(&apis_config, mm)?;
+
+    Ok((
+        config,
+        passes_config,
+        bundles_config,
+        apis_config,
+        proxy_router,
+    ))
+}
+
+fn swap_proxy_router(proxy_router: Router) {
+    *PROXY_ROUTER.lock().unwrap_or_else(|e| e.into_inner()) = proxy_router;
+}
+
+/// Reloads on every SIGHUP until the process exits.
+pub async fn reload_on_sighup(mm: ModelManager) {
+    let mut sighup = match signal(SignalKind::hangup()) {
+        Ok(sighup) => sighup,
+        Err(e) => {
+            error!("Cannot listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+
+    while sighup.recv().await.is_some() {
+        info!("SIGHUP received, reloading");
+        if let Err(e) = reload(mm.clone()).await {
+            error!("Reload failed, keeping the current config: {:?}", e);
+        }
+    }
+}
+
+fn build_proxy_router(apis_config: &ApisConfig, mm: ModelManager) -> Result<Router> {
+    let router = Router::new();
+    let router = log_error(set_api_proxy_routes(router, apis_config))?;
+    let router = log_error(set_l402_wrapper(router, mm))?;
+    let router = router
+        .layer(cors_layer())
+        .fallback_service(routes_static::serve_dir());
+
     Ok(router)
 }
 
-async fn root() -> &'static str {
-    "Hello, World!"
+fn set_l402_wrapper(mut router: Router, mm: ModelManager) -> Result<Router> {
+    router = router.layer(middleware::from_fn_with_state(mm, mw_402));
+    Ok(router)
 }
 
-fn set_api_proxy_routes(mut router: Router) -> Result<Router> {
-    let params = apis_config().get_params_per_api_keys_set();
+fn set_api_proxy_routes(mut router: Router, apis_config: &ApisConfig) -> Result<Router> {
+    let params = &apis_config.providers;
 
     if params.is_empty() {
         return Err(Error::msg("No API keys set"));
     }
 
-    for p in &params {
-        let host = reverse_proxy_service::builder_https(p.host).unwrap();
-        let service = host.build(TrimPrefix(p.path));
-        let subrouter = Router::new().nest_service(p.path, service);
+    let mut services = HashMap::new();
+    for p in params {
+        let service = match p.scheme {
+            Scheme::Https => {
+                let builder = reverse_proxy_service::builder_https(p.authority())?;
+                route_provider(&mut router, p, builder)
+            }
+            Scheme::Http => {
+                let builder = reverse_proxy_service::builder_http(p.authority())?;
+                route_provider(&mut router, p, builder)
+            }
+            Scheme::Unix => {
+                let socket = p.socket.as_deref().unwrap_or_default();
+                let client = Client::builder().build(UnixConnector);
+                // hyperlocal reads the socket path from the hex encoded host.
+                let authority = format!("{}:0", hex::encode(socket));
+                let builder = reverse_proxy_service::builder(client, "unix", authority)?;
+                route_provider(&mut router, p, builder)
+            }
+        };
 
         info!("Setting routing for service: {}", p.path);
+        services.insert(p.name.as_str(), service);
+    }
 
-        router = router.nest("/", subrouter);
+    for f in &apis_config.fallbacks {
+        let targets = f
+            .targets
+            .iter()
+            .filter_map(|target| {
+                let service = services.get(target.provider.as_str())?;
+                Some((target.clone(), service.clone()))
+            })
+            .collect();
+        info!("Setting fallback routing for service: {}", f.path);
+
+        router = router.route_service(&f.path, FallbackService::new(f, targets));
     }
 
-    router = router.layer(middleware::from_fn(add_auth));
+    if !apis_config.models.is_empty() {
+        let routes = apis_config
+            .models
+            .iter()
+            .filter_map(|route| {
+                let service = services.get(route.provider.as_str())?;
+                Some((route.clone(), service.clone()))
+            })
+            .collect();
+        info!(
+            "Setting unified routing for service: {}",
+            CHAT_COMPLETIONS_PATH
+        );
+
+        router = router.route_service(CHAT_COMPLETIONS_PATH, UnifiedService::new(routes));
+    }
 
     Ok(router)
 }
+
+/// Nests the reverse proxy of the provider, and returns its upstream for the
+/// virtual routes.
+fn route_provider<C>(
+    router: &mut Router,
+    p: &ApiParams,
+    builder: ReusedServiceBuilder<C>,
+) -> ProviderService
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    let path = ProviderPath {
+        prefix: p.path.clone(),
+        base_path: p.base_path.clone().unwrap_or_default(),
+    };
+    let upstream = UpstreamService::new(p.clone(), builder.build(path));
+    *router = std::mem::take(router).nest_service(&p.path, upstream.clone());
+
+    provider_service(upstream)
+}
+
+/// Path of the provider trimmed and replaced by its base path, owned as the
+/// provider paths are not `'static` once reloadable.
+#[derive(Clone)]
+struct ProviderPath {
+    prefix: String,
+    base_path: String,
+}
+
+impl PathRewriter for ProviderPath {
+    fn rewrite<'a>(&mut self, path: &'a str) -> Cow<'a, str> {
+        let path = path.strip_prefix(self.prefix.as_str()).unwrap_or(path);
+        match self.base_path.trim_end_matches('/') {
+            "" => Cow::Borrowed(path),
+            base_path => Cow::Owned(format!("{base_path}{path}")),
+        }
+    }
+}
+
+/// Forwards to the current proxy router.
+#[derive(Clone)]
+struct ReloadableProxy;
+
+impl Service<Request<Body>> for ReloadableProxy {
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Oneshot<Router, Request<Body>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let router = PROXY_ROUTER
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        router.oneshot(req)
+    }
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn test_provider_path_rewrite() -> Result<()> {
+        // -- Setup & Fixtures
+        let mut fx_path = ProviderPath {
+            prefix: "/ollama".to_string(),
+            base_path: "/api/".to_string(),
+        };
+
+        // -- Exec & Check
+        assert_eq!(fx_path.rewrite("/ollama/chat"), "/api/chat");
+        fx_path.base_path = String::new();
+        assert_eq!(fx_path.rewrite("/ollama/chat"), "/chat");
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
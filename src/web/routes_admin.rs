@@ -0,0 +1,201 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{middleware, Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{debug, error};
+
+use super::error::Result;
+use super::mw::mw_admin_auth::mw_admin_auth;
+use crate::config::apis::apis_config;
+use crate::ctx::Ctx;
+use crate::lightning::{check_services, Caveat, L402};
+use crate::model::comp_token::{CompTokenBmc, CompTokenForCreate};
+use crate::model::ModelManager;
+use crate::web::router;
+
+/// Operator routes, guarded by the admin api key.
+pub fn routes(mm: ModelManager) -> Router {
+    Router::new()
+        .route(
+            "/admin/comp",
+            post(mint_comp_handler).get(list_comp_handler),
+        )
+        .route("/admin/comp/:token_id", delete(revoke_comp_handler))
+        .route("/admin/reload", post(reload_handler))
+        .route("/admin/keys", get(list_keys_handler))
+        .layer(middleware::from_fn(mw_admin_auth))
+        .with_state(mm)
+}
+
+#[derive(Debug, Deserialize)]
+struct CompForMint {
+    label: String,
+    budget_msat: Option<i64>,
+    uses: Option<i64>,
+    expires_in_sec: Option<i64>,
+    #[serde(default)]
+    services: Vec<String>,
+}
+
+impl CompForMint {
+    /// Limits must be positive, a token good for nothing being a mistake.
+    fn validate(&self) -> core::result::Result<(), String> {
+        if self.label.trim().is_empty() {
+            return Err("label must not be empty".to_string());
+        }
+        let limits = [
+            ("budget_msat", self.budget_msat),
+            ("uses", self.uses),
+            ("expires_in_sec", self.expires_in_sec),
+        ];
+        for (name, value) in limits {
+            if value.is_some_and(|value| value <= 0) {
+                return Err(format!("{name} must be positive"));
+            }
+        }
+        check_services(&self.services)
+    }
+
+    fn caveats(&self) -> Vec<Caveat> {
+        let mut caveats = Vec::new();
+        if let Some(budget_msat) = self.budget_msat {
+            caveats.push(Caveat::Budget(budget_msat));
+        }
+        if let Some(uses) = self.uses {
+            caveats.push(Caveat::Uses(uses));
+        }
+        if let Some(expires_in_sec) = self.expires_in_sec {
+            let now = chrono::Utc::now().timestamp();
+            caveats.push(Caveat::Expires(now.saturating_add(expires_in_sec)));
+        }
+        if !self.services.is_empty() {
+            caveats.push(Caveat::Services(self.services.clone()));
+        }
+
+        caveats
+    }
+}
+
+/// Mints a complimentary token and records it so it can be revoked.
+async fn mint_comp_handler(
+    State(mm): State<ModelManager>,
+    Json(comp_m): Json<CompForMint>,
+) -> Result<Response> {
+    debug!("{:<12} - mint_comp {}", "HANDLER", comp_m.label);
+
+    if let Err(message) = comp_m.validate() {
+        return Ok((StatusCode::BAD_REQUEST, message).into_response());
+    }
+
+    let l402 = L402::mint_comp(&comp_m.label, comp_m.caveats());
+    let token_id = l402.token_id();
+
+    let ctx = Ctx::root_ctx();
+    CompTokenBmc::create(
+        &ctx,
+        &mm,
+        CompTokenForCreate {
+            token_id: token_id.clone(),
+            label: comp_m.label.clone(),
+        },
+    )
+    .await?;
+
+    Ok(Json(json!({
+        "token_id": token_id,
+        "label": comp_m.label,
+        "authorization": l402.to_authorization_string(),
+    }))
+    .into_response())
+}
+
+async fn list_comp_handler(State(mm): State<ModelManager>) -> Result<Json<Value>> {
+    debug!("{:<12} - list_comp", "HANDLER");
+
+    let ctx = Ctx::root_ctx();
+    let comp_tokens = CompTokenBmc::list(&ctx, &mm).await?;
+
+    Ok(Json(json!({ "comp_tokens": comp_tokens })))
+}
+
+async fn revoke_comp_handler(
+    State(mm): State<ModelManager>,
+    Path(token_id): Path<String>,
+) -> Result<Response> {
+    debug!("{:<12} - revoke_comp {token_id}", "HANDLER");
+
+    let ctx = Ctx::root_ctx();
+    match CompTokenBmc::revoke(&ctx, &mm, &token_id).await? {
+        true => Ok(StatusCode::NO_CONTENT.into_response()),
+        false => Ok((StatusCode::NOT_FOUND, "Comp token not found").into_response()),
+    }
+}
+
+/// Usage counters and cooldowns of the keys of every provider.
+async fn list_keys_handler() -> Json<Value> {
+    debug!("{:<12} - list_keys", "HANDLER");
+
+    let providers: Vec<Value> = apis_config()
+        .providers
+        .iter()
+        .map(|provider| {
+            let keys = provider.key_pool().map(|key_pool| key_pool.stats()).ok();
+            json!({ "name": provider.name, "keys": keys })
+        })
+        .collect();
+
+    Json(json!({ "providers": providers }))
+}
+
+/// Reloads the config and providers, same as a SIGHUP.
+async fn reload_handler(State(mm): State<ModelManager>) -> Response {
+    debug!("{:<12} - reload", "HANDLER");
+
+    match router::reload(mm).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            error!("Reload failed, keeping the current config: {:?}", e);
+            (StatusCode::BAD_REQUEST, e.to_string()).into_response()
+        }
+    }
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_comp_for_mint_validate() -> Result<()> {
+        // -- Setup & Fixtures
+        let fx_comp = |fields: Value| -> Result<CompForMint> {
+            let mut comp = json!({ "label": "partner-acme" });
+            comp.as_object_mut()
+                .unwrap()
+                .extend(fields.as_object().cloned().unwrap_or_default());
+            Ok(serde_json::from_value(comp)?)
+        };
+
+        // -- Exec & Check
+        assert!(fx_comp(json!({ "uses": 10, "budget_msat": 1000 }))?
+            .validate()
+            .is_ok());
+        assert!(fx_comp(json!({ "uses": 0 }))?.validate().is_err());
+        assert!(fx_comp(json!({ "budget_msat": -1 }))?.validate().is_err());
+        assert!(fx_comp(json!({ "expires_in_sec": -60 }))?
+            .validate()
+            .is_err());
+        assert!(fx_comp(json!({ "services": ["openai,cohere"] }))?
+            .validate()
+            .is_err());
+
+        Ok(())
+    }
+}
+// endregion: --- Tests
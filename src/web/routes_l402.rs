@@ -0,0 +1,128 @@
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use tracing::debug;
+
+use super::error::Result;
+use crate::config::bundles::BundleParams;
+use crate::config::passes::PassParams;
+use crate::config::products::Product;
+use crate::ctx::Ctx;
+use crate::lightning::{L402Builder, L402};
+use crate::model::comp_token::CompTokenBmc;
+use crate::model::token_usage::{TokenUsageBmc, UsageLeft};
+use crate::model::ModelManager;
+
+const WWW_AUTHENTICATE: &str = "www-authenticate";
+const AUTHORIZATION: &str = "authorization";
+
+/// Routes to discover and buy L402 products and to inspect tokens, not gated
+/// by the L402 middleware.
+pub fn routes(mm: ModelManager) -> Router {
+    Router::new()
+        .route("/l402/token", get(token_handler))
+        .route("/l402/passes", get(list_products_handler::<PassParams>))
+        .route("/l402/passes/:id", post(buy_product_handler::<PassParams>))
+        .route("/l402/bundles", get(list_products_handler::<BundleParams>))
+        .route(
+            "/l402/bundles/:id",
+            post(buy_product_handler::<BundleParams>),
+        )
+        .with_state(mm)
+}
+
+/// Describes what the token of the `Authorization` header is still good for.
+async fn token_handler(State(mm): State<ModelManager>, headers: HeaderMap) -> Result<Response> {
+    debug!("{:<12} - token", "HANDLER");
+
+    let l402 = headers
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| L402::from_auth_header(header).ok());
+    let Some(l402) = l402 else {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            "Missing or invalid L402 authorization",
+        )
+            .into_response());
+    };
+
+    let token_id = l402.token_id();
+    let caveats = l402.caveats()?;
+    let meters = l402.meters()?;
+
+    // Usage is only reported for authentic tokens.
+    let ctx = Ctx::root_ctx();
+    let authentic = l402.is_valid().unwrap_or(false);
+    let usages = match authentic {
+        true => TokenUsageBmc::list_by_meters(&ctx, &mm, &meters).await?,
+        false => Vec::new(),
+    };
+    let left = UsageLeft::new(&meters, &usages);
+
+    let revoked = match caveats.comp {
+        Some(_) => Some(
+            CompTokenBmc::get_by_token_id(&ctx, &mm, &token_id)
+                .await?
+                .is_none_or(|comp_token| comp_token.revoked),
+        ),
+        None => None,
+    };
+    // Exhausted bundles and passes past their window pay for nothing more.
+    let valid =
+        authentic && revoked != Some(true) && !left.is_exhausted(chrono::Utc::now().timestamp());
+
+    Ok(Json(json!({
+        "token_id": token_id,
+        "valid": valid,
+        "settled": l402.is_settled().unwrap_or(false),
+        "revoked": revoked,
+        "caveats": caveats,
+        "remaining": left,
+        "meters": meters
+            .iter()
+            .map(|meter| {
+                let usage = usages.iter().find(|usage| usage.meter_id == meter.id);
+                json!({ "limits": meter, "usage": usage })
+            })
+            .collect::<Vec<_>>(),
+    }))
+    .into_response())
+}
+
+async fn list_products_handler<P: Product>() -> Json<Value> {
+    debug!("{:<12} - list_{}", "HANDLER", P::KINDS);
+
+    Json(json!({ P::KINDS: P::config().products }))
+}
+
+/// Answers with a 402 challenge whose token carries the product caveats.
+async fn buy_product_handler<P: Product>(Path(id): Path<String>) -> Result<Response> {
+    debug!("{:<12} - buy_{} {id}", "HANDLER", P::KIND);
+
+    let config = P::config();
+    let Some(product) = config.get(&id) else {
+        return Ok((StatusCode::NOT_FOUND, format!("Unknown {} {id}", P::KIND)).into_response());
+    };
+
+    let l402 = L402Builder::new()
+        .amount(product.price_msat())
+        .caveats(product.caveats())
+        .build()
+        .await?;
+
+    let mut res = (
+        StatusCode::PAYMENT_REQUIRED,
+        Json(json!({ P::KIND: product })),
+    )
+        .into_response();
+    res.headers_mut().insert(
+        WWW_AUTHENTICATE,
+        HeaderValue::from_str(&l402.to_authenticate_string()).unwrap(),
+    );
+
+    Ok(res)
+}
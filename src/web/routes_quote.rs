@@ -0,0 +1,114 @@
+use axum::extract::{Path, Query, RawBody};
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::debug;
+
+use super::error::Result;
+use super::mw::mw_l402::read_body;
+use crate::config::config::config;
+use crate::lightning::{Caveat, L402Builder};
+use crate::policy;
+use crate::pricing::{service_price_msat, PricedRequest};
+
+const WWW_AUTHENTICATE: &str = "www-authenticate";
+/// Payments accepted by the L402 middleware.
+const PAYMENT_METHODS: [&str; 2] = ["l402", "cashu"];
+const MSAT_PER_BTC: f64 = 100_000_000_000.0;
+
+/// Prices requests without forwarding them, not gated by the L402 middleware.
+pub fn routes() -> Router {
+    // Uploads are quoted whole, up to the size the middleware reads.
+    Router::new().route("/quote/:service/*path", post(quote_handler))
+}
+
+#[derive(Debug, Deserialize)]
+struct QuoteParams {
+    /// Method of the quoted request, `POST` by default.
+    method: Option<String>,
+    /// Adds an invoice for a token good for one such request.
+    #[serde(default)]
+    invoice: bool,
+}
+
+/// Prices the body and the headers of the request as if it were sent to
+/// `/<service>/<path>`.
+async fn quote_handler(
+    Path((service, path)): Path<(String, String)>,
+    Query(params): Query<QuoteParams>,
+    headers: HeaderMap,
+    RawBody(body): RawBody,
+) -> Result<Response> {
+    debug!("{:<12} - quote {service}/{path}", "HANDLER");
+
+    let body = match read_body(body).await {
+        Ok(body) => body,
+        Err(e) => return Ok(e.into_response()),
+    };
+
+    let method = match params.method.as_deref().map(str::parse::<Method>) {
+        None => Method::POST,
+        Some(Ok(method)) => method,
+        Some(Err(_)) => return Ok((StatusCode::BAD_REQUEST, "Invalid method").into_response()),
+    };
+    let path = format!("/{}", path.trim_start_matches('/'));
+    let mut req = PricedRequest {
+        method: &method,
+        path: &path,
+        headers: &headers,
+        body: &body,
+    };
+    // Quoted as forwarded: refused, or priced with the body the policy clamped.
+    let rewritten = match policy::check_service_request(&service, &req).await {
+        Ok(rewritten) => rewritten,
+        Err(e) => return Ok(e.into_response()),
+    };
+    if let Some(rewritten) = &rewritten {
+        req.body = rewritten;
+    }
+    let price_msat = match service_price_msat(&service, &req).await {
+        Ok(Some(price_msat)) => price_msat,
+        Ok(None) => return Ok((StatusCode::NOT_FOUND, "Service not found").into_response()),
+        Err(e) => return Ok(e.into_response()),
+    };
+
+    // The rate is set by the operator, not a live one: labelled as such.
+    let fiat = config().BTC_PRICE_USD.map(|btc_price_usd| {
+        json!({
+            "currency": "USD",
+            "amount": price_msat as f64 / MSAT_PER_BTC * btc_price_usd,
+            "btc_price": btc_price_usd,
+            "btc_price_source": "static",
+        })
+    });
+    let mut quote = json!({
+        "service": service,
+        "method": method.as_str(),
+        "path": path,
+        "price_msat": price_msat,
+        "fiat": fiat,
+        "payment_methods": PAYMENT_METHODS,
+    });
+    if !params.invoice {
+        return Ok(Json(quote).into_response());
+    }
+
+    let l402 = L402Builder::new()
+        .amount(price_msat)
+        .caveats(Caveat::single_request(&service, price_msat))
+        .build()
+        .await?;
+    quote["invoice"] = json!(l402.invoice());
+    quote["l402"] = json!(l402.to_authenticate_string());
+
+    let mut res = Json(quote).into_response();
+    res.headers_mut().insert(
+        WWW_AUTHENTICATE,
+        HeaderValue::from_str(&l402.to_authenticate_string()).unwrap(),
+    );
+
+    Ok(res)
+}
@@ -0,0 +1,159 @@
+// OpenAI chat completions for all the models, routed by model name.
+
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use axum::body::{boxed, Body, BoxBody, StreamBody};
+use axum::http::header::{ACCEPT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE};
+use axum::http::{HeaderValue, Request, StatusCode};
+use axum::response::{IntoResponse, Json, Response};
+use tracing::info;
+
+use super::fallback::ProviderService;
+use super::upstream::clone_parts;
+use super::{Error, Result};
+use crate::config::apis::{ModelRoute, CHAT_COMPLETIONS_PATH};
+use crate::translate::{self, translate_stream, ChatRequest, Dialect};
+
+/// Provider serving the response of a unified request.
+const X_MATADOR_PROVIDER: &str = "x-matador-provider";
+
+/// Routes a chat completion to the provider of its model, translating it to
+/// and from the format of the provider.
+#[derive(Clone)]
+pub struct UnifiedService {
+    routes: Arc<Vec<(ModelRoute, ProviderService)>>,
+}
+
+impl UnifiedService {
+    pub fn new(routes: Vec<(ModelRoute, ProviderService)>) -> Self {
+        Self {
+            routes: Arc::new(routes),
+        }
+    }
+}
+
+impl tower::Service<Request<Body>> for UnifiedService {
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = core::result::Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<core::result::Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let service = self.clone();
+
+        Box::pin(async move {
+            let res = service
+                .forward(req)
+                .await
+                .unwrap_or_else(IntoResponse::into_response);
+            Ok(res)
+        })
+    }
+}
+
+impl UnifiedService {
+    async fn forward(&self, req: Request<Body>) -> Result<Response> {
+        let (mut parts, body) = req.into_parts();
+        let body = hyper::body::to_bytes(body)
+            .await
+            .map_err(|e| Error::UpstreamBody(e.to_string()))?;
+        let chat: ChatRequest = match serde_json::from_slice(&body) {
+            Ok(chat) => chat,
+            Err(e) => return Ok(error_response(StatusCode::BAD_REQUEST, &e.to_string())),
+        };
+
+        let Some((route, provider)) = self
+            .routes
+            .iter()
+            .find(|(route, _)| route.matches(&chat.model))
+        else {
+            let message = format!("no provider for the model {}", chat.model);
+            return Ok(error_response(StatusCode::NOT_FOUND, &message));
+        };
+        info!("Routing model {} to {}", chat.model, route.provider);
+
+        let dialect = translate::dialect(route.format);
+        let (path, body) = match &dialect {
+            Some(dialect) => match dialect.request(&chat) {
+                Ok(native) => (dialect.path(), native.to_string().into()),
+                Err(e) => return Ok(error_response(StatusCode::BAD_REQUEST, &e.to_string())),
+            },
+            None => (CHAT_COMPLETIONS_PATH, body),
+        };
+
+        // The translated response must be readable.
+        parts.headers.remove(ACCEPT_ENCODING);
+        parts.headers.remove(CONTENT_LENGTH);
+        parts
+            .headers
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let mut provider_parts = clone_parts(&parts);
+        provider_parts.uri = route
+            .path
+            .as_deref()
+            .unwrap_or(path)
+            .parse()
+            .map_err(|_| Error::UpstreamUri(path.to_string()))?;
+
+        let mut res = provider(Request::from_parts(provider_parts, Body::from(body))).await;
+        if let Ok(provider) = HeaderValue::from_str(&route.provider) {
+            res.headers_mut().insert(X_MATADOR_PROVIDER, provider);
+        }
+
+        match dialect {
+            Some(dialect) => Ok(translate_response(dialect.as_ref(), &chat, res).await),
+            None => Ok(res),
+        }
+    }
+}
+
+async fn translate_response(dialect: &dyn Dialect, chat: &ChatRequest, res: Response) -> Response {
+    let (mut parts, body) = res.into_parts();
+    parts.headers.remove(CONTENT_LENGTH);
+
+    let body: BoxBody = if !parts.status.is_success() {
+        let body = hyper::body::to_bytes(body).await.unwrap_or_default();
+        parts
+            .headers
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        boxed(Body::from(translate::error_body(&body).to_string()))
+    } else if chat.stream {
+        parts
+            .headers
+            .insert(CONTENT_TYPE, HeaderValue::from_static("text/event-stream"));
+        let stream = translate_stream(body, dialect.stream(&chat.model));
+        boxed(StreamBody::new(stream))
+    } else {
+        let completion = hyper::body::to_bytes(body)
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|body| serde_json::from_slice(&body).map_err(|e| e.to_string()))
+            .and_then(|res| {
+                dialect
+                    .response(res, &chat.model)
+                    .map_err(|e| e.to_string())
+            });
+        match completion {
+            Ok(completion) => boxed(Body::from(completion.to_string())),
+            Err(e) => return error_response(StatusCode::BAD_GATEWAY, &e),
+        }
+    };
+
+    Response::from_parts(parts, body)
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response {
+    let kind = match status {
+        StatusCode::BAD_GATEWAY => "upstream_error",
+        _ => "invalid_request_error",
+    };
+
+    (status, Json(translate::error(message, kind))).into_response()
+}
@@ -0,0 +1,168 @@
+// Forwarding of the requests to their provider, with a key of its pool.
+
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use axum::body::{boxed, Body, Bytes, HttpBody};
+use axum::http::header::HOST;
+use axum::http::{HeaderValue, Request, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::BoxError;
+use http::request::Parts;
+use serde_json::Value;
+use tower::{Service, ServiceExt};
+use tracing::info;
+
+use super::{Error, Result};
+use crate::auth::{self, retry_after_ms};
+use crate::config::apis::{ApiParams, Scheme};
+use crate::config::azure::AzureParams;
+use crate::utils::{remove_host_header, set_path, set_query_param};
+
+/// First backoff of a rate limited request without a `retry-after`.
+const RETRY_BACKOFF_MS: u64 = 500;
+const RETRY_BACKOFF_MAX_MS: u64 = 8_000;
+const AZURE_API_VERSION: &str = "api-version";
+
+/// Forwards the requests of a provider through `inner`, the reverse proxy,
+/// injecting a key of the pool and retrying the requests rate limited by the
+/// provider until their deadline, so a paying client rarely sees a 429.
+#[derive(Clone)]
+pub struct UpstreamService<S> {
+    params: ApiParams,
+    inner: S,
+}
+
+impl<S> UpstreamService<S> {
+    pub fn new(params: ApiParams, inner: S) -> Self {
+        Self { params, inner }
+    }
+}
+
+impl<S, E, B> Service<Request<Body>> for UpstreamService<S>
+where
+    S: Service<
+            Request<Body>,
+            Response = core::result::Result<http::Response<B>, E>,
+            Error = Infallible,
+        > + Clone
+        + Send
+        + 'static,
+    S::Future: Send,
+    E: IntoResponse,
+    B: HttpBody<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = core::result::Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<core::result::Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        let params = self.params.clone();
+        let inner = self.inner.clone();
+        remove_host_header(&mut req);
+        if self.params.scheme == Scheme::Unix {
+            // The socket has no host name, local servers expect one.
+            req.headers_mut()
+                .insert(HOST, HeaderValue::from_static("localhost"));
+        }
+
+        Box::pin(async move {
+            let res = forward(&params, inner, req)
+                .await
+                .unwrap_or_else(IntoResponse::into_response);
+            Ok(res)
+        })
+    }
+}
+
+async fn forward<S, E, B>(params: &ApiParams, inner: S, req: Request<Body>) -> Result<Response>
+where
+    S: Service<
+            Request<Body>,
+            Response = core::result::Result<http::Response<B>, E>,
+            Error = Infallible,
+        > + Clone,
+    E: IntoResponse,
+    B: HttpBody<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    let (mut parts, body) = req.into_parts();
+    // Buffered, to be sent again on retry.
+    let body = hyper::body::to_bytes(body)
+        .await
+        .map_err(|e| Error::UpstreamBody(e.to_string()))?;
+    if let Some(azure) = &params.azure {
+        parts.uri = azure_uri(azure, &parts.uri, &body)?;
+    }
+    let deadline = Instant::now() + Duration::from_millis(params.rate_limit.retry_deadline_ms);
+
+    let mut attempt: u32 = 0;
+    loop {
+        let key = params.key_pool()?.select();
+        let mut attempt_parts = clone_parts(&parts);
+        auth::apply(params, &mut attempt_parts, &body, &key.get().await?)?;
+        let attempt_req = Request::from_parts(attempt_parts, Body::from(Bytes::clone(&body)));
+
+        let res = match inner.clone().oneshot(attempt_req).await {
+            Ok(Ok(res)) => res.map(boxed),
+            Ok(Err(e)) => return Ok(e.into_response()),
+            Err(infallible) => match infallible {},
+        };
+        key.record_response(res.status(), res.headers());
+        if res.status() != StatusCode::TOO_MANY_REQUESTS {
+            return Ok(res);
+        }
+
+        // Another key may be free already, else wait as told by the provider.
+        let wait = match params.key_pool()?.wait_ms() {
+            0 => Duration::ZERO,
+            _ => retry_after_ms(res.headers())
+                .map(|ms| Duration::from_millis(ms as u64))
+                .unwrap_or_else(|| backoff(attempt)),
+        };
+        if Instant::now()
+            .checked_add(wait)
+            .is_none_or(|retry_at| retry_at > deadline)
+        {
+            info!("{} rate limited past the retry deadline", params.name);
+            return Ok(res);
+        }
+
+        info!("{} rate limited, retrying in {:?}", params.name, wait);
+        tokio::time::sleep(wait).await;
+        attempt += 1;
+    }
+}
+
+/// Uri of the deployment of the requested model, with the API version.
+fn azure_uri(azure: &AzureParams, uri: &Uri, body: &[u8]) -> Result<Uri> {
+    let body: Option<Value> = serde_json::from_slice(body).ok();
+    let model = body.as_ref().and_then(|body| body["model"].as_str());
+
+    set_path(uri, &azure.path(uri.path(), model))
+        .and_then(|uri| set_query_param(&uri, AZURE_API_VERSION, &azure.api_version))
+        .map_err(|e| Error::UpstreamUri(e.to_string()))
+}
+
+fn backoff(attempt: u32) -> Duration {
+    Duration::from_millis((RETRY_BACKOFF_MS << attempt.min(8)).min(RETRY_BACKOFF_MAX_MS))
+}
+
+/// `Parts` is not `Clone`, the extensions are left out.
+pub fn clone_parts(parts: &Parts) -> Parts {
+    let mut req = Request::new(());
+    *req.method_mut() = parts.method.clone();
+    *req.uri_mut() = parts.uri.clone();
+    *req.version_mut() = parts.version;
+    *req.headers_mut() = parts.headers.clone();
+
+    req.into_parts().0
+}